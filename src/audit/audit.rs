@@ -0,0 +1,240 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::audit::OfflineService;
+use crate::block::{AncestorError, Block, BlockConsensus, BlockHeader, BlockId};
+use crate::node::{PowConfig, PowService};
+use crate::primitives::CCTimestamp;
+use crate::utils::to_hex;
+use crate::work::validate_consensus;
+
+/// An inconsistency found in an exported chain
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+  pub block_num: u64,
+  pub block_id: BlockId,
+  pub problem: String,
+}
+
+impl Finding {
+  fn new(block: &Block, problem: impl Display) -> Self {
+    Self {
+      block_num: block.block_num,
+      block_id: block.block_id.clone(),
+      problem: problem.to_string(),
+    }
+  }
+}
+
+impl Display for Finding {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    write!(
+      f,
+      "block {} {}: {}",
+      self.block_num,
+      to_hex(&self.block_id),
+      self.problem
+    )
+  }
+}
+
+/// Run the checks a node runs on new blocks over every exported block, at time `now`.
+///
+/// `config` stands in for the on-chain settings, which an export doesn't carry. Blocks are checked
+/// against their exported predecessor, those of the lowest height may have none.
+pub fn audit(mut blocks: Vec<Block>, config: &PowConfig, now: CCTimestamp) -> Vec<Finding> {
+  blocks.sort_by_key(|block| block.block_num);
+  let first_num: Option<u64> = blocks.first().map(|block| block.block_num);
+
+  let (offline, duplicates) = OfflineService::new(blocks.iter().cloned());
+  let mut findings: Vec<Finding> = duplicates
+    .iter()
+    .map(|block| Finding::new(block, "exported more than once"))
+    .collect();
+  let mut service = PowService::new(Box::new(offline));
+
+  for block in blocks.iter().filter(|block| block.block_num > 0) {
+    if let Err(problem) = check_block(block, first_num, &mut service, config, now) {
+      findings.push(Finding::new(block, problem));
+    }
+  }
+
+  findings
+}
+
+fn check_block(
+  block: &Block,
+  first_num: Option<u64>,
+  service: &mut PowService,
+  config: &PowConfig,
+  now: CCTimestamp,
+) -> Result<(), String> {
+  // a block without PoW consensus may predate the switch to PoW, a garbled PoW one is a finding
+  let header: BlockHeader =
+    BlockHeader::from_any_consensus(Cow::Borrowed(block)).map_err(|error| error.to_string())?;
+  if header.consensus.is_pow() && *config.strict_consensus.at(block.block_num) {
+    BlockConsensus::deserialize_strict(&block.payload).map_err(|error| error.to_string())?;
+  }
+
+  let predecessor: BlockHeader = match service.get_header(&block.previous_id) {
+    Ok(predecessor) => predecessor,
+    Err(AncestorError::UnknownBlock(..)) if Some(block.block_num) == first_num => return Ok(()),
+    Err(AncestorError::UnknownBlock(..)) => {
+      return Err(format!(
+        "predecessor {} is missing",
+        to_hex(&block.previous_id)
+      ))
+    }
+    Err(error) => return Err(format!("predecessor unreadable: {}", error)),
+  };
+  if predecessor.block_num + 1 != block.block_num {
+    return Err(format!(
+      "follows block {} {}",
+      predecessor.block_num,
+      to_hex(&predecessor.block_id)
+    ));
+  }
+
+  // blocks from before the switch to PoW were checked by the consensus of their time
+  if !header.consensus.is_pow() {
+    return if predecessor.consensus.is_pow() {
+      Err("not a PoW block, after the switch to PoW".into())
+    } else {
+      Ok(())
+    };
+  }
+
+  validate_consensus(&header, &predecessor, service, config, now).map_err(|error| error.to_string())
+}
+
+#[cfg(all(test, not(feature = "test-futures")))]
+mod tests {
+  use super::*;
+  use crate::node::Schedule;
+  use crate::simulator::{BlockStore, SimulatedNetwork, SimulatedService};
+  use crate::utils::Clock;
+  use crate::work::{get_difficulty, required_difficulty};
+  use crate::Duration;
+  use sawtooth_sdk::consensus::engine::Error;
+
+  /// The chain of a single miner, from its head down to genesis, and the time it stopped at
+  fn mined_chain() -> Result<(Vec<Block>, CCTimestamp), Error> {
+    let store = BlockStore::new();
+    store.set_setting(conf_key!("initial_difficulty"), "1");
+    let mut network = SimulatedNetwork::new(1, store.clone(), 5)?;
+    network.run_for(Duration::from_secs(300))?;
+    network.settle()?;
+
+    let mut chain: Vec<Block> = vec![network.service(0).chain_head()];
+    while let Some(parent) = store.get(&chain[chain.len() - 1].previous_id) {
+      chain.push(parent);
+    }
+
+    Ok((chain, network.clock().now()))
+  }
+
+  fn config() -> PowConfig {
+    PowConfig {
+      initial_difficulty: 1,
+      strict_difficulty: Schedule::new(true),
+      ..PowConfig::new()
+    }
+  }
+
+  #[test]
+  fn a_mined_chain_passes_and_tampering_is_reported() -> Result<(), Error> {
+    let (chain, now) = mined_chain()?;
+    assert!(chain.len() > 3, "{} blocks", chain.len());
+    assert_eq!(audit(chain.clone(), &config(), now), vec![]);
+
+    // claim another difficulty for the successor of the block below the head
+    let mut tampered: Vec<Block> = chain.clone();
+    let consensus = BlockConsensus::deserialize(&tampered[1].payload).unwrap();
+    tampered[1].payload = BlockConsensus::serialize(
+      consensus.expected_difficulty + 1,
+      consensus.timestamp,
+      consensus.nonce,
+    );
+    let findings: Vec<Finding> = audit(tampered, &config(), now);
+    assert_eq!(findings[0].block_id, chain[1].block_id);
+    assert!(
+      findings[0].problem.contains("difficulty"),
+      "{}",
+      findings[0]
+    );
+
+    // a hole in the export, and a block exported twice
+    let mut holed: Vec<Block> = chain.clone();
+    holed.remove(2);
+    holed.push(chain[0].clone());
+    let findings: Vec<Finding> = audit(holed, &config(), now);
+    assert_eq!(findings.len(), 2, "{:?}", findings);
+    assert_eq!(findings[0].problem, "exported more than once");
+    assert_eq!(findings[1].block_id, chain[1].block_id);
+    assert!(findings[1].problem.contains("missing"));
+
+    Ok(())
+  }
+
+  /// Two blocks from before the switch to PoW, then `count` PoW blocks mined by hand, genesis first
+  fn switching_chain(config: &PowConfig, count: u8) -> Vec<Block> {
+    let store = BlockStore::new();
+    let mut chain: Vec<Block> = vec![store.genesis()];
+
+    for n in 1..=2 + count {
+      let parent: Block = chain[chain.len() - 1].clone();
+      let block = |payload: Vec<u8>| Block {
+        block_id: vec![n; 8],
+        previous_id: parent.block_id.clone(),
+        signer_id: b"ssssssssssssssss".to_vec(),
+        block_num: n as u64,
+        payload,
+        ..Block::default()
+      };
+
+      let block: Block = if n <= 2 {
+        block(b"Devmode".to_vec())
+      } else {
+        let mut service = PowService::new(Box::new(SimulatedService::new(store.clone(), vec![])));
+        let predecessor = BlockHeader::from_any_consensus(Cow::Borrowed(&parent)).unwrap();
+        let timestamp: CCTimestamp = 1000.0 + n as f64;
+        let next = get_difficulty(&predecessor, timestamp, &mut service, config).unwrap();
+        let required = required_difficulty(&predecessor, config);
+        (0..)
+          .map(|nonce| block(BlockConsensus::serialize(next, timestamp, nonce)))
+          .find(|block| {
+            BlockHeader::borrowed(block)
+              .unwrap()
+              .validate(config, required)
+              .is_ok()
+          })
+          .unwrap()
+      };
+      store.insert(block.clone());
+      chain.push(block);
+    }
+
+    chain
+  }
+
+  #[test]
+  fn blocks_from_before_the_switch_to_pow_are_skipped() {
+    let chain: Vec<Block> = switching_chain(&config(), 3);
+    assert_eq!(audit(chain.clone(), &config(), 2000.0), vec![]);
+
+    // but not once PoW started
+    let mut late: Vec<Block> = chain.clone();
+    let head: &Block = &chain[chain.len() - 1];
+    late.push(Block {
+      block_id: vec![9; 8],
+      previous_id: head.block_id.clone(),
+      block_num: head.block_num + 1,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    });
+    let findings: Vec<Finding> = audit(late, &config(), 2000.0);
+    assert_eq!(findings.len(), 1, "{:?}", findings);
+    assert_eq!(findings[0].block_id, vec![9; 8]);
+    assert!(findings[0].problem.contains("not a PoW block"));
+  }
+}
@@ -0,0 +1,97 @@
+use std::io::BufRead;
+
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+use crate::block::Block;
+use crate::utils::unhex;
+
+/// Read an exported chain, one block per line as a JSON object:
+///
+/// `{"block_id": "..", "previous_id": "..", "signer_id": "..", "block_num": 1, "payload": ".."}`
+///
+/// Ids and payloads are hex encoded, other keys, such as `batches`, are ignored and blank lines
+/// skipped.
+pub fn read_export(reader: impl BufRead) -> Result<Vec<Block>> {
+  let mut blocks: Vec<Block> = Vec::new();
+
+  for (index, line) in reader.lines().enumerate() {
+    let line: String = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+
+    let block: Block =
+      parse_block(&line).map_err(|error| anyhow!("line {}: {}", index + 1, error))?;
+    blocks.push(block);
+  }
+
+  Ok(blocks)
+}
+
+fn parse_block(line: &str) -> Result<Block> {
+  let fields: Map<String, Value> = match serde_json::from_str(line)? {
+    Value::Object(fields) => fields,
+    _ => return Err(anyhow!("expected an object")),
+  };
+  let field =
+    |key: &str| -> Result<&Value> { fields.get(key).ok_or_else(|| anyhow!("missing {}", key)) };
+  let bytes = |key: &str| -> Result<Vec<u8>> {
+    let hex: &str = field(key)?
+      .as_str()
+      .ok_or_else(|| anyhow!("{} is not a string", key))?;
+    unhex(hex).map_err(|error| anyhow!("{} is not hex: {}", key, error))
+  };
+
+  Ok(Block {
+    block_id: bytes("block_id")?,
+    previous_id: bytes("previous_id")?,
+    signer_id: bytes("signer_id")?,
+    block_num: field("block_num")?
+      .as_u64()
+      .ok_or_else(|| anyhow!("invalid block_num"))?,
+    payload: bytes("payload")?,
+    summary: match fields.get("summary") {
+      None | Some(Value::Null) => Vec::new(),
+      Some(_) => bytes("summary")?,
+    },
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn blocks_are_read_one_per_line() {
+    let export = concat!(
+      r#"{"block_id": "0a0b", "previous_id": "0000", "signer_id": "ff", "block_num": 0, "payload": ""}"#,
+      "\n\n",
+      r#"{"block_num":1,"block_id":"0c","previous_id":"0a0b","signer_id":"ff","payload":"3a","batches":[{"header":{"signer":"ff"},"transactions":[]}]}"#,
+      "\n",
+    );
+    let blocks: Vec<Block> = read_export(export.as_bytes()).unwrap();
+
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].block_id, vec![0x0a, 0x0b]);
+    assert_eq!(blocks[1].block_num, 1);
+    assert_eq!(blocks[1].previous_id, vec![0x0a, 0x0b]);
+    assert_eq!(blocks[1].payload, b":".to_vec());
+  }
+
+  #[test]
+  fn malformed_lines_are_reported_with_their_number() {
+    let export = "{\"block_id\": \"0a\"}\n";
+    let error = read_export(export.as_bytes()).unwrap_err().to_string();
+    assert_eq!(error, "line 1: missing previous_id");
+
+    let export = "\n{\"block_id\": \"open}\n";
+    let error = read_export(export.as_bytes()).unwrap_err().to_string();
+    assert!(error.starts_with("line 2: "), "{}", error);
+    assert!(parse_block("[1]").is_err());
+    assert!(parse_block(
+      r#"{"block_id":"0c","previous_id":"0a","signer_id":"ff","block_num":-1,"payload":""}"#
+    )
+    .is_err());
+  }
+}
@@ -0,0 +1,7 @@
+mod audit;
+mod export;
+mod service;
+
+pub use self::audit::*;
+pub use self::export::*;
+pub use self::service::*;
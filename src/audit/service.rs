@@ -0,0 +1,109 @@
+use sawtooth_sdk::consensus::{engine::Error, service::Service};
+use std::collections::HashMap;
+
+use crate::block::{Block, BlockId};
+use crate::node::PeerId;
+
+/// A read-only `Service` over exported blocks, for running the consensus checks without a validator.
+///
+/// Blocks are served as exported, there are no on-chain settings and anything that would change
+/// the chain is refused.
+#[derive(Clone, Debug, Default)]
+pub struct OfflineService {
+  blocks: HashMap<BlockId, Block>,
+}
+
+impl OfflineService {
+  /// Serve `blocks`, returns the ones whose id was already taken
+  pub fn new(blocks: impl IntoIterator<Item = Block>) -> (Self, Vec<Block>) {
+    let mut service: Self = Self::default();
+    let duplicates: Vec<Block> = blocks
+      .into_iter()
+      .filter_map(|block| service.insert(block))
+      .collect();
+
+    (service, duplicates)
+  }
+
+  /// Add `block`, unless a block with the same id is already there, which is then returned
+  pub fn insert(&mut self, block: Block) -> Option<Block> {
+    if self.blocks.contains_key(&block.block_id) {
+      Some(block)
+    } else {
+      self.blocks.insert(block.block_id.clone(), block);
+      None
+    }
+  }
+
+  fn read_only() -> Error {
+    Error::InvalidState("The offline service is read-only".into())
+  }
+}
+
+impl Service for OfflineService {
+  fn send_to(
+    &mut self,
+    _peer: &PeerId,
+    _message_type: &str,
+    _payload: Vec<u8>,
+  ) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn broadcast(&mut self, _message_type: &str, _payload: Vec<u8>) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn initialize_block(&mut self, _previous_id: Option<BlockId>) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+    Err(Self::read_only())
+  }
+  fn finalize_block(&mut self, _data: Vec<u8>) -> Result<BlockId, Error> {
+    Err(Self::read_only())
+  }
+  fn cancel_block(&mut self) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn check_blocks(&mut self, _priority: Vec<BlockId>) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn commit_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn ignore_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn fail_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+    Err(Self::read_only())
+  }
+  fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+    Ok(
+      block_ids
+        .into_iter()
+        .filter_map(|id| self.blocks.get(&id).cloned().map(|block| (id, block)))
+        .collect(),
+    )
+  }
+  fn get_chain_head(&mut self) -> Result<Block, Error> {
+    self
+      .blocks
+      .values()
+      .max_by_key(|block| block.block_num)
+      .cloned()
+      .ok_or(Error::NoChainHead)
+  }
+  fn get_settings(
+    &mut self,
+    _block_id: BlockId,
+    _settings: Vec<String>,
+  ) -> Result<HashMap<String, String>, Error> {
+    Ok(HashMap::new())
+  }
+  fn get_state(
+    &mut self,
+    _block_id: BlockId,
+    _addresses: Vec<String>,
+  ) -> Result<HashMap<String, Vec<u8>>, Error> {
+    Ok(HashMap::new())
+  }
+}
@@ -1,13 +1,97 @@
 use std::borrow::Cow;
+use std::error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::ops::DerefMut;
 
-use crate::block::BlockHeader;
+use sawtooth_sdk::consensus::engine::Error;
+
 use crate::block::PairedFork;
+use crate::block::{BlockHeader, BlockId, ConsensusError};
 use crate::node::PowService;
+use crate::utils::to_hex;
 
 pub type BlockAncestorBlock<'a> = Cow<'a, [u8]>;
 type Block = [u8];
 
+/// Why a walk down the ancestors of a block stopped
+#[derive(Debug)]
+pub enum AncestorError {
+  /// The genesis block was reached, it carries no proof of work
+  Genesis,
+  /// A block without PoW consensus was reached, from before the chain switched to PoW
+  NotPow(BlockId),
+  /// The validator didn't return the block
+  UnknownBlock(BlockId, Error),
+  /// The consensus of the block couldn't be read
+  Unparsable(BlockId, ConsensusError),
+}
+
+impl AncestorError {
+  /// Whether the walk ran out of PoW blocks, rather than failing to read one
+  pub fn is_end(&self) -> bool {
+    matches!(self, Self::Genesis | Self::NotPow(_))
+  }
+}
+
+impl error::Error for AncestorError {}
+
+impl Display for AncestorError {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    match self {
+      Self::Genesis => write!(f, "Reached the genesis block"),
+      Self::NotPow(id) => write!(f, "Reached non-PoW block {}", to_hex(id)),
+      Self::UnknownBlock(id, e) => write!(f, "Unknown block {}: {}", to_hex(id), e),
+      Self::Unparsable(id, e) => write!(f, "Unparsable block {}: {}", to_hex(id), e),
+    }
+  }
+}
+
+impl From<AncestorError> for Error {
+  fn from(error: AncestorError) -> Self {
+    match error {
+      AncestorError::UnknownBlock(_, e) => e,
+      error => Error::InvalidState(error.to_string()),
+    }
+  }
+}
+
+/// The PoW header of `block_id`, or why there is none
+pub(crate) fn read_ancestor<'a>(
+  service: &mut PowService,
+  block_id: &[u8],
+) -> Result<BlockHeader<'a>, AncestorError> {
+  let header: BlockHeader = service.get_header(block_id)?;
+  if header.is_genesis() {
+    return Err(AncestorError::Genesis);
+  }
+  if !header.consensus.is_pow() {
+    return Err(AncestorError::NotPow(block_id.to_owned()));
+  }
+
+  Ok(header)
+}
+
+/// Up to `count` PoW headers from `block_id` down, fewer if the PoW chain ends first
+pub fn pow_chain<'a>(
+  block_id: &'a Block,
+  count: u64,
+  service: &mut PowService,
+) -> Result<Vec<BlockHeader<'a>>, AncestorError> {
+  let mut headers: Vec<BlockHeader<'a>> = Vec::new();
+
+  for header in BlockAncestors::new(block_id, service).take(count as usize) {
+    match header {
+      Ok(header) => headers.push(header),
+      Err(error) if error.is_end() => break,
+      Err(error) => return Err(error),
+    }
+  }
+
+  Ok(headers)
+}
+
+/// The PoW headers from a block down, ending with an error once they can't go on: either the
+/// end of the PoW chain, see `AncestorError::is_end`, or a block that couldn't be read.
 pub struct BlockAncestors<'a, T>
 where
   T: DerefMut<Target = PowService>,
@@ -39,20 +123,72 @@ impl<'a, T> Iterator for BlockAncestors<'a, T>
 where
   T: DerefMut<Target = PowService>,
 {
-  type Item = BlockHeader<'a>;
+  type Item = Result<BlockHeader<'a>, AncestorError>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    let result: Option<Self::Item> = self
-      .block
-      .take()
-      //Watchout for the service Deref
-      .and_then(|block_id| (*self.service).get_block(&block_id).ok())
-      .and_then(|block| BlockHeader::owned(block).ok());
-
-    self.block = result
-      .as_ref()
-      .map(|block| Cow::Owned(block.previous_id.to_owned()));
-
-    result
+    let block_id: BlockAncestorBlock = self.block.take()?;
+    //Watchout for the service Deref
+    let result: Self::Item = read_ancestor(&mut self.service, &block_id);
+
+    if let Ok(header) = &result {
+      self.block = Some(Cow::Owned(header.previous_id.to_owned()));
+    }
+
+    Some(result)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::BlockConsensus;
+  use crate::simulator::{BlockStore, SimulatedService};
+
+  fn block(block_num: u64, payload: Vec<u8>) -> crate::block::Block {
+    crate::block::Block {
+      block_id: vec![block_num as u8 + 1],
+      previous_id: vec![block_num as u8],
+      block_num,
+      payload,
+      ..Default::default()
+    }
+  }
+
+  fn service(store: &BlockStore) -> PowService {
+    PowService::new(Box::new(SimulatedService::new(store.clone(), vec![])))
+  }
+
+  fn walk(store: &BlockStore, head: u8) -> Vec<Result<u64, String>> {
+    let mut service = service(store);
+    BlockAncestors::new(&[head], &mut service)
+      .map(|header| header.map(|header| header.block_num))
+      .map(|result| result.map_err(|e| e.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn walks_end_with_the_reason_they_stopped() {
+    let store = BlockStore::new();
+    store.insert(block(0, vec![]));
+    store.insert(block(1, b"Devmode".to_vec()));
+    store.insert(block(2, BlockConsensus::serialize(1, 2.0, 0)));
+    store.insert(block(3, BlockConsensus::serialize(1, 3.0, 0)));
+    store.insert(block(4, b"PoW:x".to_vec()));
+    store.insert(block(5, BlockConsensus::serialize(1, 5.0, 0)));
+
+    assert_eq!(
+      walk(&store, 4),
+      vec![Ok(3), Ok(2), Err("Reached non-PoW block 02".into())]
+    );
+    assert!(matches!(walk(&store, 6)[1], Err(ref e) if e.starts_with("Unparsable block 05")));
+    assert!(matches!(&walk(&store, 9)[..], [Err(e)] if e.starts_with("Unknown block 09")));
+
+    let mut service = service(&store);
+    let mut ancestors = BlockAncestors::new(&[1], &mut service);
+    assert!(matches!(
+      ancestors.next(),
+      Some(Err(AncestorError::Genesis))
+    ));
+    assert!(ancestors.next().is_none());
   }
 }
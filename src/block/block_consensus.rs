@@ -1,5 +1,4 @@
-use anyhow::Result;
-use byteorder::ReadBytesExt;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::error;
 use std::fmt;
 use std::fmt::Display;
@@ -9,13 +8,20 @@ use std::io::Read;
 use std::str::from_utf8;
 use std::str::FromStr;
 
-use crate::primitives::{CCDifficulty, CCNonce, CCTimestamp};
+use crate::primitives::{CCDifficulty, CCNonce, CCTimestamp, H256};
 
 const POW_STR: &str = "PoW";
 const POW_BYTES: &[u8] = b"PoW";
+const POW_V2_BYTES: &[u8] = b"PW2";
+
+/// tag + difficulty (u32) + nonce (u64) + timestamp (f64 bits), all big-endian
+const POW_V2_LEN: usize = 3 + 4 + 8 + 8;
 
 const GLUE_BYTE: u8 = b':';
 
+/// 9999-12-31T23:59:59Z, the largest timestamp accepted in strict mode
+const MAX_TIMESTAMP: CCTimestamp = 253_402_300_799.0;
+
 pub type ByteTag = [u8; 3];
 
 pub type SerializedBlockConsensus = Vec<u8>;
@@ -42,25 +48,84 @@ impl BlockConsensus {
     format!("{}:{}:{}:{}", POW_STR, difficulty, nonce, timestamp).into_bytes()
   }
 
+  /// Fixed-width big-endian encoding, keeps the exact bits of the timestamp
+  pub fn serialize_binary(
+    difficulty: CCDifficulty,
+    timestamp: CCTimestamp,
+    nonce: CCNonce,
+  ) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(POW_V2_LEN);
+    bytes.extend_from_slice(POW_V2_BYTES);
+    // writing to a Vec can't fail
+    let _ = bytes.write_u32::<BigEndian>(difficulty);
+    let _ = bytes.write_u64::<BigEndian>(nonce);
+    let _ = bytes.write_u64::<BigEndian>(timestamp.to_bits());
+    bytes
+  }
+
+  pub fn serialize_as(
+    format: ConsensusFormat,
+    difficulty: CCDifficulty,
+    timestamp: CCTimestamp,
+    nonce: CCNonce,
+  ) -> Vec<u8> {
+    match format {
+      ConsensusFormat::Text => Self::serialize(difficulty, timestamp, nonce),
+      ConsensusFormat::Binary => Self::serialize_binary(difficulty, timestamp, nonce),
+    }
+  }
+
+  /// Reads either the `PoW:d:n:t` text format or the `PW2` binary format
   pub fn deserialize<T: AsRef<[u8]>>(slice: T) -> Result<Self, ConsensusError> {
-    let mut reader: Cursor<&[u8]> = Cursor::new(slice.as_ref());
+    Self::parse(slice.as_ref(), false)
+  }
+
+  /// Like `deserialize`, but rejects anything other than the canonical encoding
+  /// of a finite, in-range timestamp.
+  pub fn deserialize_strict<T: AsRef<[u8]>>(slice: T) -> Result<Self, ConsensusError> {
+    let bytes: &[u8] = slice.as_ref();
+    let consensus: Self = Self::parse(bytes, true)?;
+
+    consensus.verify_timestamp()?;
+
+    if consensus.to_bytes() != bytes {
+      return Err(ConsensusError::NonCanonical {
+        payload: String::from_utf8_lossy(bytes).into_owned(),
+      });
+    }
+
+    Ok(consensus)
+  }
+
+  fn parse(bytes: &[u8], strict: bool) -> Result<Self, ConsensusError> {
+    let mut reader: Cursor<&[u8]> = Cursor::new(bytes);
     let mut tag: ByteTag = Default::default();
 
     // read and verify tag
-    if let Err(e) = reader.read_exact(&mut tag) {
-      return Err(ConsensusError::ParsingError(e.to_string()));
+    if reader.read_exact(&mut tag).is_err() {
+      return Err(ConsensusError::Truncated { field: "tag" });
     }
-    if let Err(e) = Self::verify_tag(&tag) {
-      return Err(ConsensusError::NotPoWError(e.to_string()));
+    Self::verify_tag(&tag)?;
+    if tag == POW_V2_BYTES {
+      return Self::deserialize_binary(tag, &mut reader);
     }
     // skip glue after tag
-    if let Err(e) = reader.read_u8() {
-      return Err(ConsensusError::ParsingError(e.to_string()));
+    match reader.read_u8() {
+      Ok(GLUE_BYTE) => (),
+      Ok(glue) if strict => return Err(ConsensusError::InvalidGlue { glue }),
+      Ok(_) => (),
+      Err(_) => return Err(ConsensusError::Truncated { field: "glue" }),
     }
     //order matters
     let difficulty: Vec<u8> = Self::read_sequence(&mut reader, GLUE_BYTE)?;
     let nonce: Vec<u8> = Self::read_sequence(&mut reader, GLUE_BYTE)?;
     let timestamp: Vec<u8> = Self::read_sequence(&mut reader, GLUE_BYTE)?;
+
+    let trailing: usize = bytes.len() - reader.position() as usize;
+    if strict && trailing > 0 {
+      return Err(ConsensusError::TrailingData { bytes: trailing });
+    }
+
     Ok(Self {
       tag,
       expected_difficulty: Self::parse_from_utf8("difficulty", &difficulty)?,
@@ -73,8 +138,67 @@ impl BlockConsensus {
     Self::default()
   }
 
+  /// Re-encode in the format the consensus was read from
+  pub fn to_bytes(&self) -> Vec<u8> {
+    Self::serialize_as(
+      self.format().unwrap_or_default(),
+      self.expected_difficulty,
+      self.timestamp,
+      self.nonce,
+    )
+  }
+
+  pub fn verify_timestamp(&self) -> Result<(), ConsensusError> {
+    if !self.timestamp.is_finite()
+      || self.timestamp.is_sign_negative()
+      || self.timestamp > MAX_TIMESTAMP
+    {
+      Err(ConsensusError::BadTimestamp {
+        value: self.timestamp,
+      })
+    } else {
+      Ok(())
+    }
+  }
+
   pub fn is_pow(&self) -> bool {
-    self.tag == POW_BYTES
+    self.format().is_some()
+  }
+
+  pub fn format(&self) -> Option<ConsensusFormat> {
+    match &self.tag[..] {
+      POW_BYTES => Some(ConsensusFormat::Text),
+      POW_V2_BYTES => Some(ConsensusFormat::Binary),
+      _ => None,
+    }
+  }
+
+  fn deserialize_binary(tag: ByteTag, reader: &mut Cursor<&[u8]>) -> Result<Self, ConsensusError> {
+    let length: usize = reader.get_ref().len();
+    if length != POW_V2_LEN {
+      return Err(ConsensusError::BadLength {
+        length,
+        expected: POW_V2_LEN,
+      });
+    }
+
+    let truncated = |field: &'static str| move |_| ConsensusError::Truncated { field };
+    let expected_difficulty = reader
+      .read_u32::<BigEndian>()
+      .map_err(truncated("difficulty"))?;
+    let nonce = reader.read_u64::<BigEndian>().map_err(truncated("nonce"))?;
+    let timestamp = f64::from_bits(
+      reader
+        .read_u64::<BigEndian>()
+        .map_err(truncated("timestamp"))?,
+    );
+
+    Ok(Self {
+      tag,
+      expected_difficulty,
+      timestamp,
+      nonce,
+    })
   }
 
   pub(crate) fn read_sequence<R>(reader: &mut R, terminator: u8) -> Result<Vec<u8>, ConsensusError>
@@ -107,7 +231,7 @@ impl BlockConsensus {
     match reader.read_u8() {
       Ok(byte) => Ok(Some(byte)),
       Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(None),
-      Err(error) => Err(ConsensusError::ParsingError(error.to_string())),
+      Err(error) => Err(ConsensusError::Unreadable { kind: error.kind() }),
     }
   }
 
@@ -115,27 +239,107 @@ impl BlockConsensus {
   where
     T: AsRef<[u8]>,
     U: FromStr,
-    <U as FromStr>::Err: Display,
   {
-    match from_utf8(slice.as_ref()) {
-      Ok(string) => string
-        .parse::<U>()
-        .map_err(|e| ConsensusError::ParsingError(format!("{}:{}", property, e))),
-      Err(e) => Err(ConsensusError::ParsingError(e.to_string())),
+    let bad_field = || ConsensusError::BadField {
+      field: property,
+      value: String::from_utf8_lossy(slice.as_ref()).into_owned(),
+    };
+    from_utf8(slice.as_ref())
+      .map_err(|_| bad_field())?
+      .parse::<U>()
+      .map_err(|_| bad_field())
+  }
+
+  pub(crate) fn verify_tag(tag: &[u8]) -> Result<(), ConsensusError> {
+    if tag == POW_BYTES || tag == POW_V2_BYTES {
+      Ok(())
+    } else {
+      Err(ConsensusError::NotPow { tag: tag.to_vec() })
+    }
+  }
+}
+
+/// The encoding of new consensus payloads, selected by `sawtooth.consensus.pow.consensus_format`
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum ConsensusFormat {
+  /// `PoW:difficulty:nonce:timestamp`
+  #[default]
+  Text,
+  /// `PW2` followed by big-endian difficulty, nonce and timestamp
+  Binary,
+}
+
+impl FromStr for ConsensusFormat {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "text" => Ok(Self::Text),
+      "binary" => Ok(Self::Binary),
+      _ => Err(format!("Unknown consensus format: {}", string)),
     }
   }
+}
 
-  pub(crate) fn verify_tag(tag: &[u8]) -> Result<()> {
-    ensure!(tag == POW_BYTES, "Consensus has invalid tag");
-    Ok(())
+impl Display for ConsensusFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Text => f.write_str("text"),
+      Self::Binary => f.write_str("binary"),
+    }
   }
 }
 
+/// Why a consensus payload was rejected, either unreadable or breaking a consensus rule
 #[derive(Debug, PartialEq)]
 pub enum ConsensusError {
-  ParsingError(String),
-  NotPoWError(String),
-  InvalidHash(String),
+  /// The payload ended before `field` could be read
+  Truncated { field: &'static str },
+  /// The payload couldn't be read at all
+  Unreadable { kind: ErrorKind },
+  /// A binary payload of `length` bytes, rather than `expected`
+  BadLength { length: usize, expected: usize },
+  /// The text of `field` isn't a valid number
+  BadField { field: &'static str, value: String },
+  /// The payload doesn't start with a PoW tag, it belongs to another consensus
+  NotPow { tag: Vec<u8> },
+  /// The hash scores `actual` leading zeros, short of the `expected` difficulty
+  InvalidHash {
+    expected: CCDifficulty,
+    actual: CCDifficulty,
+  },
+  /// The hash is above the `target` the compact difficulty encodes
+  HashAboveTarget { target: H256, hash: H256 },
+  /// The byte after the tag isn't ':'
+  InvalidGlue { glue: u8 },
+  /// Bytes left over after the timestamp
+  TrailingData { bytes: usize },
+  /// The payload doesn't re-encode to the same bytes, e.g. leading zeros
+  NonCanonical { payload: String },
+  /// A timestamp that isn't finite, is negative or is past year 9999
+  BadTimestamp { value: CCTimestamp },
+  /// The timestamp isn't after the `median` of the previous blocks
+  TimestampBeforeMedian {
+    value: CCTimestamp,
+    median: CCTimestamp,
+  },
+  /// The timestamp is past the latest acceptable time, `limit`
+  TimestampInFuture {
+    value: CCTimestamp,
+    limit: CCTimestamp,
+  },
+  /// The next difficulty `claimed` by the block isn't the one recomputed from its ancestors
+  UnexpectedDifficulty {
+    claimed: CCDifficulty,
+    expected: CCDifficulty,
+  },
+}
+
+impl ConsensusError {
+  /// Whether the payload belongs to another consensus, rather than being a broken PoW one
+  pub fn is_not_pow(&self) -> bool {
+    matches!(self, Self::NotPow { .. })
+  }
 }
 
 impl error::Error for ConsensusError {}
@@ -143,10 +347,49 @@ impl error::Error for ConsensusError {}
 impl fmt::Display for ConsensusError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     use self::ConsensusError::*;
-    match *self {
-      ParsingError(ref s) => write!(f, "Unparsable Consensus: {}", s),
-      NotPoWError(ref s) => write!(f, "Not PoW Consensus: {}", s),
-      InvalidHash(ref s) => write!(f, "Hash doesn't meet diffulty {}", s),
+    match self {
+      Truncated { field } => write!(f, "Unparsable Consensus: missing {}", field),
+      Unreadable { kind } => write!(f, "Unparsable Consensus: {}", kind),
+      BadLength { length, expected } => write!(
+        f,
+        "Unparsable Consensus: binary consensus is {} bytes, expected {}",
+        length, expected
+      ),
+      BadField { field, value } => write!(f, "Unparsable Consensus: {} {:?}", field, value),
+      NotPow { tag } => write!(
+        f,
+        "Not PoW Consensus: tag {:?}",
+        String::from_utf8_lossy(tag)
+      ),
+      InvalidHash { expected, actual } => write!(
+        f,
+        "Hash doesn't meet difficulty (Expected {}, got {})",
+        expected, actual
+      ),
+      HashAboveTarget { target, hash } => write!(
+        f,
+        "Hash doesn't meet difficulty (Expected at most {}, got {})",
+        target, hash
+      ),
+      InvalidGlue { glue } => write!(f, "Invalid separator after tag: {:#04x}", glue),
+      TrailingData { bytes } => write!(f, "Unexpected {} byte(s) after the timestamp", bytes),
+      NonCanonical { payload } => write!(f, "Non-canonical consensus encoding: {}", payload),
+      BadTimestamp { value } => write!(f, "Timestamp out of range: {}", value),
+      TimestampBeforeMedian { value, median } => write!(
+        f,
+        "Timestamp {} not after median time past {}",
+        value, median
+      ),
+      TimestampInFuture { value, limit } => write!(
+        f,
+        "Timestamp {} too far in the future (limit {})",
+        value, limit
+      ),
+      UnexpectedDifficulty { claimed, expected } => write!(
+        f,
+        "Claimed next difficulty {}, expected {}",
+        claimed, expected
+      ),
     }
   }
 }
@@ -183,9 +426,15 @@ mod tests {
   }
 
   #[test]
-  #[should_panic(expected = "Consensus has invalid tag")]
   fn test_deserialize_invalid_tag() {
-    BlockConsensus::deserialize(b"woo:30:123:500.555").unwrap();
+    let e = BlockConsensus::deserialize(b"woo:30:123:500.555").unwrap_err();
+    assert_eq!(
+      e,
+      ConsensusError::NotPow {
+        tag: b"woo".to_vec()
+      }
+    );
+    assert!(e.is_not_pow());
   }
 
   #[test]
@@ -193,7 +442,10 @@ mod tests {
     let e = BlockConsensus::deserialize(b"PoW:---:123:500.555").unwrap_err();
     assert_eq!(
       e,
-      ConsensusError::ParsingError("difficulty:invalid digit found in string".into())
+      ConsensusError::BadField {
+        field: "difficulty",
+        value: "---".into()
+      }
     );
   }
 
@@ -202,7 +454,106 @@ mod tests {
     let e = BlockConsensus::deserialize(b"PoW:30:---:500.555").unwrap_err();
     assert_eq!(
       e,
-      ConsensusError::ParsingError("nonce:invalid digit found in string".into())
+      ConsensusError::BadField {
+        field: "nonce",
+        value: "---".into()
+      }
+    );
+  }
+
+  #[test]
+  fn test_binary_round_trip() {
+    let timestamp: CCTimestamp = 1_650_000_000.123_456_7;
+    let bytes = BlockConsensus::serialize_binary(30, timestamp, u64::MAX);
+    let consensus = BlockConsensus::deserialize(&bytes).unwrap();
+
+    assert_eq!(bytes.len(), POW_V2_LEN);
+    assert_eq!(&bytes[..3], POW_V2_BYTES);
+    assert_eq!(consensus.format(), Some(ConsensusFormat::Binary));
+    assert_eq!(consensus.expected_difficulty, 30);
+    assert_eq!(consensus.nonce, u64::MAX);
+    assert_eq!(consensus.timestamp.to_bits(), timestamp.to_bits());
+  }
+
+  #[test]
+  fn test_deserialize_binary_wrong_length() {
+    let mut bytes = BlockConsensus::serialize_binary(30, 500.555, 123);
+    bytes.push(0);
+    assert_eq!(
+      BlockConsensus::deserialize(&bytes).unwrap_err(),
+      ConsensusError::BadLength {
+        length: POW_V2_LEN + 1,
+        expected: POW_V2_LEN
+      }
+    );
+
+    bytes.truncate(POW_V2_LEN - 2);
+    assert!(BlockConsensus::deserialize(&bytes).is_err());
+  }
+
+  #[test]
+  fn test_serialize_as_text() {
+    let bytes = BlockConsensus::serialize_as(ConsensusFormat::Text, 30, 500.555, 123);
+    assert_eq!(bytes, b"PoW:30:123:500.555".to_vec());
+  }
+
+  #[test]
+  fn test_strict_accepts_canonical() {
+    let text = BlockConsensus::deserialize_strict(b"PoW:30:123:500.555").unwrap();
+    let binary =
+      BlockConsensus::deserialize_strict(BlockConsensus::serialize_binary(30, 500.555, 123))
+        .unwrap();
+
+    assert_eq!(text.timestamp, binary.timestamp);
+    assert_eq!(text.nonce, binary.nonce);
+  }
+
+  #[test]
+  fn test_strict_rejects_invalid_glue() {
+    let e = BlockConsensus::deserialize_strict(b"PoW-30:123:500.555").unwrap_err();
+    assert_eq!(e, ConsensusError::InvalidGlue { glue: b'-' });
+    assert!(BlockConsensus::deserialize(b"PoW-30:123:500.555").is_ok());
+  }
+
+  #[test]
+  fn test_strict_rejects_extra_fields() {
+    let e = BlockConsensus::deserialize_strict(b"PoW:30:123:500.555:extra").unwrap_err();
+    assert_eq!(e, ConsensusError::TrailingData { bytes: 5 });
+    assert!(BlockConsensus::deserialize(b"PoW:30:123:500.555:extra").is_ok());
+  }
+
+  #[test]
+  fn test_strict_rejects_non_canonical() {
+    for payload in [
+      &b"PoW:030:123:500.555"[..],
+      b"PoW:30:+123:500.555",
+      b"PoW:30:123:500.5550",
+    ] {
+      let e = BlockConsensus::deserialize_strict(payload).unwrap_err();
+      assert!(matches!(e, ConsensusError::NonCanonical { .. }), "{:?}", e);
+    }
+  }
+
+  #[test]
+  fn test_strict_rejects_bad_timestamps() {
+    for payload in [
+      &b"PoW:30:123:NaN"[..],
+      b"PoW:30:123:inf",
+      b"PoW:30:123:-inf",
+      b"PoW:30:123:-1",
+      b"PoW:30:123:-0",
+      b"PoW:30:123:1e300",
+    ] {
+      let e = BlockConsensus::deserialize_strict(payload).unwrap_err();
+      assert!(matches!(e, ConsensusError::BadTimestamp { .. }), "{:?}", e);
+    }
+
+    let nan = BlockConsensus::serialize_binary(30, f64::NAN, 123);
+    let e = BlockConsensus::deserialize_strict(nan).unwrap_err();
+    assert!(
+      matches!(e, ConsensusError::BadTimestamp { value } if value.is_nan()),
+      "{:?}",
+      e
     );
   }
 
@@ -211,7 +562,10 @@ mod tests {
     let e = BlockConsensus::deserialize(b"PoW:30:123:---").unwrap_err();
     assert_eq!(
       e,
-      ConsensusError::ParsingError("timestamp:invalid float literal".into())
+      ConsensusError::BadField {
+        field: "timestamp",
+        value: "---".into()
+      }
     );
   }
 }
@@ -1,11 +1,12 @@
 use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::ops::Deref;
 
 use crate::block::{Block, BlockConsensus, ConsensusError};
+use crate::node::PowConfig;
 use crate::primitives::{CCDifficulty, H256};
-use crate::work::get_hasher;
-use crate::work::{is_valid_proof_of_work, mkhash};
+use crate::work::DifficultyMode;
 
 #[derive(Clone)]
 pub struct BlockHeader<'a> {
@@ -31,27 +32,45 @@ impl<'a> BlockHeader<'a> {
     Ok(Self { block, consensus })
   }
 
+  /// Like `from_cow`, but a block without PoW consensus, from before the chain switched to PoW,
+  /// gets an empty one as the genesis block does
+  pub fn from_any_consensus(block: Cow<'a, Block>) -> Result<Self, ConsensusError> {
+    if block.block_num == 0 {
+      return Self::from_cow(block);
+    }
+
+    let consensus = match BlockConsensus::deserialize(&block.payload) {
+      Err(error) if error.is_not_pow() => BlockConsensus::new(),
+      result => result?,
+    };
+    Ok(Self { block, consensus })
+  }
+
   pub fn is_genesis(&self) -> bool {
     self.block_num == 0
   }
 
-  pub fn work(&self) -> u64 {
-    let actual_difficulty = self
-      //we don't want to validate difficulty, we want the actual_difficulty, use the minimum input value so that the method never fails
-      .validate_proof_of_work(0)
-      .expect("Validity was previously attested when creating the BlockHeader");
+  /// The work proven by the hash itself, in the difficulty mode of this block
+  pub fn work(&self, config: &PowConfig) -> H256 {
+    let mode: DifficultyMode = *config.difficulty_mode.at(self.block_num);
+    let mut algorithm = config.algorithm.at(self.block_num).build();
+    let hash: H256 = algorithm.hash(&self.previous_id, &self.signer_id, self.consensus.nonce);
 
-    2u64.pow(actual_difficulty)
+    mode.achieved_work(&*algorithm, &hash)
   }
 
-  //Validate that the solution has a difficulty greater orequal than the minimum difficulty (now stored in the predecessor)
-  pub fn validate(self, minimum_difficulty: CCDifficulty) -> Result<Self, ConsensusError> {
+  //Validate that the solution meets the minimum difficulty (now stored in the predecessor)
+  pub fn validate(
+    self,
+    config: &PowConfig,
+    minimum_difficulty: CCDifficulty,
+  ) -> Result<Self, ConsensusError> {
     // The genesis block is always valid
     if self.is_genesis() {
       return Ok(self);
     }
 
-    let _ = self.validate_proof_of_work(minimum_difficulty)?;
+    self.validate_proof_of_work(config, minimum_difficulty)?;
 
     Ok(self)
   }
@@ -59,25 +78,29 @@ impl<'a> BlockHeader<'a> {
   // is valid proof of work using the consensus difficulty field
   fn validate_proof_of_work(
     &self,
+    config: &PowConfig,
     difficulty: CCDifficulty,
   ) -> Result<CCDifficulty, ConsensusError> {
-    let hash: H256 = mkhash(
-      &mut get_hasher(),
-      &self.previous_id,
-      &self.signer_id,
-      self.consensus.nonce,
-    );
+    let mode: DifficultyMode = *config.difficulty_mode.at(self.block_num);
+    let mut algorithm = config.algorithm.at(self.block_num).build();
+    let hash: H256 = algorithm.hash(&self.previous_id, &self.signer_id, self.consensus.nonce);
 
-    let (is_valid, actual_difficulty) = is_valid_proof_of_work(&hash, difficulty);
+    let actual_difficulty: CCDifficulty = algorithm.score(&hash);
 
-    if is_valid {
-      Ok(actual_difficulty)
-    } else {
-      Err(ConsensusError::InvalidHash(format!(
-        "(Expected {}, got {})",
-        difficulty, actual_difficulty
-      )))
+    if mode.meets(&*algorithm, &hash, difficulty) {
+      return Ok(actual_difficulty);
     }
+
+    Err(match mode {
+      DifficultyMode::LeadingZeros => ConsensusError::InvalidHash {
+        expected: difficulty,
+        actual: actual_difficulty,
+      },
+      DifficultyMode::Target => ConsensusError::HashAboveTarget {
+        target: H256::from_compact(difficulty),
+        hash,
+      },
+    })
   }
 }
 
@@ -89,15 +112,19 @@ impl Deref for BlockHeader<'_> {
   }
 }
 
-impl From<Block> for BlockHeader<'_> {
-  fn from(block: Block) -> Self {
-    Self::owned(block).unwrap()
+impl TryFrom<Block> for BlockHeader<'_> {
+  type Error = ConsensusError;
+
+  fn try_from(block: Block) -> Result<Self, Self::Error> {
+    Self::owned(block)
   }
 }
 
-impl<'a> From<&'a Block> for BlockHeader<'a> {
-  fn from(block: &'a Block) -> Self {
-    Self::borrowed(block).unwrap()
+impl<'a> TryFrom<&'a Block> for BlockHeader<'a> {
+  type Error = ConsensusError;
+
+  fn try_from(block: &'a Block) -> Result<Self, Self::Error> {
+    Self::borrowed(block)
   }
 }
 
@@ -131,7 +158,7 @@ mod tests {
 
   use crate::miner::Miner;
   use crate::node::tests::MockService;
-  use crate::node::{PowConfig, PowService};
+  use crate::node::{PowService, Schedule};
 
   #[test]
   ///Validate proof of work could mistakenly return the expected difficulty instead of the actual difficulty.
@@ -143,8 +170,8 @@ mod tests {
 
     {
       config.initial_difficulty = 7;
-      let block_id = b"1111111111111111".iter().copied().collect();
-      let peer_id = b"2222222222222222".iter().copied().collect();
+      let block_id = b"1111111111111111".to_vec();
+      let peer_id = b"2222222222222222".to_vec();
       miner
         .mine(block_id, peer_id, &mut service, &config)
         .unwrap();
@@ -159,7 +186,26 @@ mod tests {
 
     let block_header = BlockHeader::borrowed(&b).expect("test-block");
     let exp_diff = 0;
-    let actual_diff = block_header.validate_proof_of_work(exp_diff).unwrap();
+    let actual_diff = block_header
+      .validate_proof_of_work(&config, exp_diff)
+      .unwrap();
     assert_ne!(actual_diff, exp_diff);
   }
+
+  #[test]
+  fn target_mode_checks_the_hash_against_the_target() {
+    let mut config = PowConfig::new();
+    config.difficulty_mode = Schedule::new(DifficultyMode::Target);
+    let block = Block {
+      block_num: 1,
+      payload: BlockConsensus::serialize(0, 1000.0, 42),
+      ..Block::default()
+    };
+    let header = BlockHeader::borrowed(&block).unwrap();
+
+    let loosest = DifficultyMode::Target.minimum();
+    assert!(header.validate_proof_of_work(&config, loosest).is_ok());
+    assert!(header.validate_proof_of_work(&config, 0).is_err());
+    assert!(header.work(&config) >= H256::from_u64(1));
+  }
 }
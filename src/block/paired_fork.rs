@@ -1,5 +1,5 @@
-use crate::block::BlockAncestorBlock;
 use crate::block::BlockHeader;
+use crate::block::{read_ancestor, AncestorError, BlockAncestorBlock};
 use crate::node::PowService;
 use std::borrow::Cow;
 use std::iter::Iterator;
@@ -35,54 +35,26 @@ impl<'a, T> Iterator for PairedFork<'a, T>
 where
   T: DerefMut<Target = PowService>,
 {
-  type Item = (BlockHeader<'a>, BlockHeader<'a>);
+  /// The local and the foreign ancestor at the same depth
+  type Item = (
+    Result<BlockHeader<'a>, AncestorError>,
+    Result<BlockHeader<'a>, AncestorError>,
+  );
 
   fn next(&mut self) -> Option<Self::Item> {
-    let tuplify = |prev_header: Option<BlockHeader<'a>>| match prev_header {
-      Some(block_header) => (
-        Some(Cow::Owned(block_header.previous_id.to_owned())),
-        Some(block_header),
-      ),
-      None => (None, None),
-    };
+    let local_block = self.local_head_block.take()?;
+    let foreign_block = self.foreign_head_block.take()?;
 
-    let mut advance = |fork: Fork| {
-      let PairedFork {
-        local_head_block,
-        foreign_head_block,
-        service,
-      } = self;
+    //Watchout for the service Deref
+    let local_header = read_ancestor(&mut self.service, &local_block);
+    let foreign_header = read_ancestor(&mut self.service, &foreign_block);
 
-      let block = match fork {
-        Fork::Local => local_head_block,
-        Fork::Foreign => foreign_head_block,
-      };
-
-      let prev_block_header = block
-        .take()
-        //Watchout for the service Deref
-        .and_then(|block_id| (*service).get_block(&block_id).ok())
-        .and_then(|block| BlockHeader::owned(block).ok());
-
-      let (next_block, header) = tuplify(prev_block_header);
-      match fork {
-        Fork::Local => self.local_head_block = next_block,
-        Fork::Foreign => self.foreign_head_block = next_block,
-      };
-      header
-    };
-
-    let local_header = advance(Fork::Local);
-    let foreign_header = advance(Fork::Foreign);
-
-    match (local_header, foreign_header) {
-      (Some(l), Some(f)) => Some((l, f)),
-      _ => None,
+    // Carry on only while both forks do
+    if let (Ok(local), Ok(foreign)) = (&local_header, &foreign_header) {
+      self.local_head_block = Some(Cow::Owned(local.previous_id.to_owned()));
+      self.foreign_head_block = Some(Cow::Owned(foreign.previous_id.to_owned()));
     }
-  }
-}
 
-enum Fork {
-  Local,
-  Foreign,
+    Some((local_header, foreign_header))
+  }
 }
@@ -7,7 +7,6 @@ use std::sync::mpsc::Receiver;
 use crate::{
   futures::{Builder, Runtime, UpdateStream},
   node::{PowConfig, PowNode},
-  Duration,
 };
 
 const ENGINE_NAME: &str = "PoW";
@@ -44,8 +43,7 @@ impl Engine for PowEngine {
     let rt = PowEngine::build_rt();
 
     {
-      let time_til_publishing = Duration::from_millis(500);
-      let stream = UpdateStream::new(updates, node, time_til_publishing);
+      let stream = UpdateStream::new(updates, node);
 
       rt.block_on(stream.update_loop());
     }
@@ -83,7 +81,7 @@ impl PowEngine {
     // validator - an error here is considered fatal and prevents startup.
     node.initialize(startup).map_err(|err| {
       error!("Failed to initialize PoW engine: {:?}", err);
-      err
+      err.into()
     })
   }
 
@@ -0,0 +1,118 @@
+use std::error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use sawtooth_sdk::consensus::engine::Error;
+
+use crate::block::{AncestorError, ConsensusError};
+use crate::utils::HexError;
+
+/// Why the engine failed, for library users to match on.
+///
+/// Converts into the sawtooth `Error` where it crosses the `Engine` boundary.
+#[derive(Debug)]
+pub enum PowError {
+  /// A block's consensus couldn't be read or breaks a rule
+  Consensus(ConsensusError),
+  /// A walk down the ancestors of a block couldn't go on
+  Ancestor(AncestorError),
+  /// The validator refused or failed a request
+  Service(Error),
+  /// Hex that doesn't decode, in an id or a payload
+  Hex(HexError),
+}
+
+impl PowError {
+  /// The consensus rule or encoding error behind this one, if any
+  pub fn consensus(&self) -> Option<&ConsensusError> {
+    match self {
+      Self::Consensus(error) | Self::Ancestor(AncestorError::Unparsable(_, error)) => Some(error),
+      _ => None,
+    }
+  }
+}
+
+impl error::Error for PowError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      Self::Consensus(error) => Some(error),
+      Self::Ancestor(error) => Some(error),
+      Self::Service(error) => Some(error),
+      Self::Hex(error) => Some(error),
+    }
+  }
+}
+
+impl Display for PowError {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    match self {
+      Self::Consensus(error) => Display::fmt(error, f),
+      Self::Ancestor(error) => Display::fmt(error, f),
+      Self::Service(error) => Display::fmt(error, f),
+      Self::Hex(error) => Display::fmt(error, f),
+    }
+  }
+}
+
+impl From<ConsensusError> for PowError {
+  fn from(error: ConsensusError) -> Self {
+    Self::Consensus(error)
+  }
+}
+
+impl From<AncestorError> for PowError {
+  fn from(error: AncestorError) -> Self {
+    Self::Ancestor(error)
+  }
+}
+
+impl From<Error> for PowError {
+  fn from(error: Error) -> Self {
+    Self::Service(error)
+  }
+}
+
+impl From<HexError> for PowError {
+  fn from(error: HexError) -> Self {
+    Self::Hex(error)
+  }
+}
+
+/// Service errors are passed through as they are, the others are invalid states to the validator
+impl From<PowError> for Error {
+  fn from(error: PowError) -> Self {
+    match error {
+      PowError::Service(error) => error,
+      PowError::Ancestor(error) => error.into(),
+      error => Error::InvalidState(error.to_string()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn errors_keep_their_reason_until_the_engine_boundary() {
+    let error = PowError::from(ConsensusError::TrailingData { bytes: 5 });
+    assert_eq!(
+      error.consensus(),
+      Some(&ConsensusError::TrailingData { bytes: 5 })
+    );
+    assert!(matches!(
+      Error::from(error),
+      Error::InvalidState(reason) if reason == "Unexpected 5 byte(s) after the timestamp"
+    ));
+
+    let error = PowError::from(AncestorError::UnknownBlock(
+      vec![1],
+      Error::UnknownBlock("01".into()),
+    ));
+    assert!(error.consensus().is_none());
+    assert!(matches!(Error::from(error), Error::UnknownBlock(_)));
+    assert!(matches!(
+      Error::from(PowError::from(Error::BlockNotReady)),
+      Error::BlockNotReady
+    ));
+  }
+}
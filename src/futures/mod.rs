@@ -16,9 +16,14 @@ pub use std::task::Poll;
 pub use tokio::runtime;
 pub use tokio::runtime::Builder;
 pub use tokio::runtime::Runtime;
+pub use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+pub use tokio::sync::{watch, Notify};
 pub use tokio::time::sleep;
 pub use tokio::time::Interval;
 pub use tokio::time::Sleep;
 
+/// Raised once it is time to publish, see `PublishSchedulerFuture`
+pub type PublishingFlag = Arc<watch::Sender<bool>>;
+
 pub use publish_future::*;
 pub use update_stream::*;
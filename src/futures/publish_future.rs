@@ -1,26 +1,31 @@
 /*!
-A future that will flag the publishing time using an atomic construct.
+A future that will flag the publishing time through a watch channel.
 */
 use crate::futures::*;
+use crate::utils::{ClockSleep, SharedClock};
 #[cfg(feature = "test-futures")]
 use std::println as trace;
 
 ///schedule a task to mark publishing time, after publishing, reschedule a new publishing future.
 pub struct PublishSchedulerFuture {
-  flag: AtomicFlag,
-  sleep: Pin<Box<Sleep>>,
+  flag: PublishingFlag,
+  sleep: ClockSleep,
 }
 
 impl PublishSchedulerFuture {
-  pub fn schedule_publishing(flag: AtomicFlag, time_til_publishing: Duration) -> Self {
+  pub fn schedule_publishing(
+    flag: PublishingFlag,
+    time_til_publishing: Duration,
+    clock: &SharedClock,
+  ) -> Self {
     PublishSchedulerFuture {
       flag,
-      sleep: Box::pin(sleep(time_til_publishing)),
+      sleep: clock.sleep(time_til_publishing),
     }
   }
 }
 
-///A future that flags publishing time through an atomic bool.
+///A future that flags publishing time through a watch channel, waking whoever waits on it.
 ///After publishing don't forget to set the flag to false and schedule a new publishing future.
 impl Future for PublishSchedulerFuture {
   type Output = ();
@@ -30,14 +35,13 @@ impl Future for PublishSchedulerFuture {
     cx: &mut std::task::Context<'_>,
   ) -> std::task::Poll<Self::Output> {
     let PublishSchedulerFuture { flag, sleep } = self.get_mut();
-    if Pin::new(sleep).poll(cx).is_pending() {
+    if sleep.as_mut().poll(cx).is_pending() {
       return Poll::Pending;
     }
 
     #[cfg(feature = "test-futures")]
     trace!("Publishing time!");
-    //set atomic
-    flag.store(true, Ordering::Release);
+    flag.send_replace(true);
     Poll::Ready(())
   }
 }
@@ -45,19 +49,42 @@ impl Future for PublishSchedulerFuture {
 #[cfg(test)]
 pub mod tests {
   use super::*;
+  use crate::utils::{MockClock, SystemClock};
+  use futures::FutureExt;
 
   #[test]
   fn publishing_future() {
-    let flag = Arc::new(AtomicBool::new(false));
+    let (flag, due) = watch::channel(false);
+    let flag = Arc::new(flag);
     let time_til_publishing = Duration::from_millis(1000);
     let rt = runtime::Runtime::new().unwrap();
     {
       let flag = flag.clone();
+      let clock = SystemClock::shared();
       let fut = async move {
-        PublishSchedulerFuture::schedule_publishing(flag, time_til_publishing).await;
+        PublishSchedulerFuture::schedule_publishing(flag, time_til_publishing, &clock).await;
       };
       rt.block_on(fut);
     }
-    assert!(flag.load(std::sync::atomic::Ordering::Acquire))
+    assert!(*due.borrow())
+  }
+
+  #[test]
+  fn publishing_future_follows_the_clock() {
+    let (flag, mut due) = watch::channel(false);
+    let clock = MockClock::new(0.0);
+    let mut fut = Box::pin(PublishSchedulerFuture::schedule_publishing(
+      Arc::new(flag),
+      Duration::from_secs(60),
+      &clock.shared(),
+    ));
+
+    assert!(fut.as_mut().now_or_never().is_none());
+    assert!(!*due.borrow());
+
+    clock.advance(Duration::from_secs(60));
+    assert!(fut.as_mut().now_or_never().is_some());
+    assert!(due.has_changed().unwrap());
+    assert!(*due.borrow_and_update());
   }
 }
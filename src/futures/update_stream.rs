@@ -1,10 +1,13 @@
 use crate::consensus::engine::Update;
-use crate::primitives::AtomicFlag;
 
+use crate::error::PowError;
 use crate::futures::*;
+use crate::metrics::METRICS;
 use crate::node::EventPublishResult;
-use futures::pin_mut;
-use futures::{select, FutureExt};
+use crate::utils::{ClockSleep, SharedClock};
+use futures::FutureExt;
+use std::thread;
+use std::time::Instant;
 
 #[cfg(feature = "test-futures")]
 use std::sync::atomic::AtomicUsize;
@@ -12,87 +15,154 @@ use std::sync::atomic::AtomicUsize;
 use crate::node::PowNode;
 
 pub struct UpdateStream {
-  updates: Receiver<Update>,
+  /// Updates from the validator, forwarded by the bridge thread with the time they were received
+  updates: UnboundedReceiver<(Instant, Update)>,
   node: PowNode,
-  publishing_flag: AtomicFlag,
-  new_chainhead_flag: AtomicFlag,
-  time_til_publishing: Duration,
+  /// Raised by the publishing timer, lowered once published or when a new chain head restarts the timer
+  publishing: PublishingFlag,
+  /// Notified for every new chain head
+  committed: Notify,
+  /// Drives the publishing timer, the node's own clock
+  clock: SharedClock,
 }
 
+/// How long to wait for the miner before trying a due publication again
+const PUBLISHING_RETRY: Duration = Duration::from_millis(10);
+
 #[cfg(feature = "test-futures")]
 static COUNT_COMMITTER: AtomicUsize = AtomicUsize::new(0);
 #[cfg(feature = "test-futures")]
 use std::println as trace;
 
 impl UpdateStream {
-  pub fn new(updates: Receiver<Update>, node: PowNode, time_til_publishing: Duration) -> Self {
-    let publishing_flag = Arc::new(AtomicBool::new(false));
-    let new_chainhead_flag = Arc::new(AtomicBool::new(false));
-    #[cfg(feature = "test-futures")]
-    let time_til_publishing = Duration::from_secs(time_til_publishing.as_secs() / 60 * 2);
+  pub fn new(updates: Receiver<Update>, node: PowNode) -> Self {
+    let (publishing, _) = watch::channel(false);
+    let clock: SharedClock = node.clock().clone();
     Self {
-      updates,
+      updates: UpdateStream::bridge(updates),
       node,
-      publishing_flag,
-      new_chainhead_flag,
-      time_til_publishing,
+      publishing: Arc::new(publishing),
+      committed: Notify::new(),
+      clock,
     }
   }
 
-  #[allow(dead_code)]
-  async fn toggle_off_reactor(flag: AtomicFlag) {
-    while flag.load(Ordering::Acquire) {
-      sleep(Duration::from_millis(10)).await
-    }
+  /// Forward the blocking validator receiver to an async channel, until either end hangs up
+  fn bridge(updates: Receiver<Update>) -> UnboundedReceiver<(Instant, Update)> {
+    let (sender, receiver) = unbounded_channel();
+
+    thread::Builder::new()
+      .name("update-bridge".into())
+      .spawn(move || {
+        while let Ok(update) = updates.recv() {
+          if sender.send((Instant::now(), update)).is_err() {
+            break;
+          }
+        }
+      })
+      .expect("Bridge thread failed to spawn");
+
+    receiver
   }
 
-  async fn toggle_on_reactor(flag: AtomicFlag) {
-    while !flag.load(Ordering::Acquire) {
-      sleep(Duration::from_millis(10)).await
+  /// Start the publishing timer, with the delay the node's settings ask for as of now
+  fn schedule_publishing(&mut self) -> PublishSchedulerFuture {
+    let time_til_publishing: Duration = self.node.publishing_delay();
+    trace!("Publishing in {:?}", time_til_publishing);
+    PublishSchedulerFuture::schedule_publishing(
+      self.publishing.clone(),
+      time_til_publishing,
+      &self.clock,
+    )
+  }
+
+  /// Resolves when the next mining summary is due, never if the summaries are off
+  fn schedule_summary(&self) -> ClockSleep {
+    match self.node.config.stats_interval_secs {
+      0 => Box::pin(pending()),
+      seconds => self.clock.sleep(Duration::from_secs(seconds)),
     }
   }
 
-  pub async fn update_loop(mut self) {
-    let publishing_flag = self.publishing_flag.clone();
-    let time = self.time_til_publishing;
-    let commit_flag = self.new_chainhead_flag.clone();
-    //publishing timer
-    let scheduler =
-      { PublishSchedulerFuture::schedule_publishing(publishing_flag.clone(), time).fuse() };
+  /// Resolves when the earliest postponed block is due to be checked again, never if there is none
+  fn schedule_recheck(&self) -> ClockSleep {
+    match self.node.next_postponed() {
+      Some(due) => {
+        let wait: f64 = (due - self.clock.now()).max(0.0);
+        self.clock.sleep(Duration::from_secs_f64(wait))
+      }
+      None => Box::pin(pending()),
+    }
+  }
 
-    //update calls from the validator
-    let updater = async move {
-      while let EventResult::Continue = self.update_call().await {}
-      EventResult::Shutdown
+  /// Resolves once publishing is due, and not before `retry` if the last attempt was pending
+  async fn publishing_due(due: &mut watch::Receiver<bool>, retry: &mut Option<ClockSleep>) {
+    if let Some(retry) = retry {
+      retry.await;
     }
-    .fuse();
 
-    //commit listener
-    let committer = UpdateStream::toggle_on_reactor(commit_flag.clone()).fuse();
+    while !*due.borrow_and_update() {
+      if due.changed().await.is_err() {
+        pending::<()>().await;
+      }
+    }
+  }
 
-    pin_mut!(scheduler, updater, committer);
+  /// Handle validator updates as they arrive. Schedule a publishing timer, when the timer yields,
+  /// publish. Every new chain head restarts the timer. Nothing runs in between.
+  pub async fn update_loop(mut self) {
+    let mut due: watch::Receiver<bool> = self.publishing.subscribe();
+    let mut retry: Option<ClockSleep> = None;
+    //publishing timer
+    let mut scheduler = self.schedule_publishing().fuse();
+    let mut summary: ClockSleep = self.schedule_summary();
+    let mut recheck: ClockSleep = self.schedule_recheck();
 
-    //Schedule a publishing timer, when the timer yields, toggle on the publishing flag.
-    //Schedule a commit reactor, when the flag turns on, start a publishing timer.
     loop {
-      select! {
+      tokio::select! {
         // timer
-        () = scheduler=>{},
+        () = &mut scheduler => {},
+        () = &mut summary => {
+          self.node.log_mining_summary();
+          summary = self.schedule_summary();
+        },
+        () = &mut recheck => {
+          self.node.recheck_postponed();
+          recheck = self.schedule_recheck();
+        },
         //new block commited as the new chain head
-        () = committer =>{
+        () = self.committed.notified() => {
           #[cfg(feature = "test-futures")]
           trace!("Commiter fut");
-          commit_flag.clone().store(false, Ordering::SeqCst);
           //publishing timer kicked in but publishing was unsuccessful, and a new chain head arrived.
-          publishing_flag.clone().store(false,Ordering::Release);
+          self.publishing.send_replace(false);
+          retry = None;
           //reset publisher timer
-          scheduler.set(PublishSchedulerFuture::schedule_publishing(publishing_flag.clone(), time).fuse());
+          scheduler = self.schedule_publishing().fuse();
           #[cfg(feature = "test-futures")]
           COUNT_COMMITTER.fetch_add(1usize, Ordering::Relaxed);
-          committer.set(UpdateStream::toggle_on_reactor(commit_flag.clone()).fuse());
         },
-        _ = updater => break,
-        complete =>{}
+        () = UpdateStream::publishing_due(&mut due, &mut retry) => {
+          match self.try_publish() {
+            Ok(EventPublishResult::Published) => retry = None,
+            Ok(EventPublishResult::Pending) => retry = Some(self.clock.sleep(PUBLISHING_RETRY)),
+            Err(e) => {
+              warn!(
+                "Publishing Error {}. Consensus event handler is stopping.",
+                e
+              );
+              break;
+            }
+          }
+        },
+        //update calls from the validator
+        update = self.updates.recv() => {
+          if let EventResult::Shutdown = self.update_call(update) {
+            break;
+          }
+          // the update may have postponed a block
+          recheck = self.schedule_recheck();
+        },
       }
     }
   }
@@ -104,30 +174,24 @@ static COUNT_PUBLISHED: AtomicUsize = AtomicUsize::new(0);
 static COUNT_UPDATED: AtomicUsize = AtomicUsize::new(0);
 
 impl UpdateStream {
-  async fn update_call(&mut self) -> EventResult {
-    if self.publishing_flag.load(Ordering::Acquire) {
-      match self.node.try_publish() {
-        Ok(EventPublishResult::Published) => {
-          #[cfg(feature = "test-futures")]
-          {
-            trace!("Resetting publishing flag");
-            COUNT_PUBLISHED.fetch_add(1usize, Ordering::Relaxed);
-          }
-          self.publishing_flag.store(false, Ordering::Release);
-        }
-        Ok(EventPublishResult::Pending) => {}
-        Err(e) => {
-          warn!(
-            "Publishing Error {}. Consensus event handler is stopping.",
-            e
-          );
-          return EventResult::Shutdown;
-        }
+  /// Try to publish the due block, lowering the publishing flag once published
+  fn try_publish(&mut self) -> Result<EventPublishResult, PowError> {
+    let result = self.node.try_publish()?;
+    if let EventPublishResult::Published = result {
+      #[cfg(feature = "test-futures")]
+      {
+        trace!("Resetting publishing flag");
+        COUNT_PUBLISHED.fetch_add(1usize, Ordering::Relaxed);
       }
+      self.publishing.send_replace(false);
     }
+    Ok(result)
+  }
 
-    match self.updates.try_recv() {
-      Ok(update) => {
+  fn update_call(&mut self, update: Option<(Instant, Update)>) -> EventResult {
+    match update {
+      Some((received, update)) => {
+        METRICS.update_handled(received.elapsed());
         trace!("Incoming update {:?}", update);
         #[cfg(feature = "test-futures")]
         COUNT_UPDATED.fetch_add(1usize, Ordering::Relaxed);
@@ -137,9 +201,9 @@ impl UpdateStream {
           Ok(EventResult::Restart(eager_publish)) => {
             #[cfg(feature = "test-futures")]
             trace!("restart publishing");
-            self.new_chainhead_flag.store(true, Ordering::SeqCst);
+            self.committed.notify_one();
             if eager_publish {
-              self.publishing_flag.store(false, Ordering::Release)
+              self.publishing.send_replace(false);
             }
             EventResult::Continue
           }
@@ -149,14 +213,10 @@ impl UpdateStream {
           }
         }
       }
-      Err(TryRecvError::Disconnected) => {
+      None => {
         error!("Disconnected from validator");
         EventResult::Shutdown
       }
-      Err(TryRecvError::Empty) => {
-        sleep(Duration::from_millis(10)).await;
-        EventResult::Continue
-      }
     }
   }
 }
@@ -166,15 +226,18 @@ impl UpdateStream {
 /// e.g cargo test --features "test-futures" --package ccconsensus --lib -- futures::update_stream::tests --nocapture
 mod tests {
   use super::*;
-  use crate::block::Block;
-  use crate::engine::PowEngine;
   use crate::node::tests::MockService;
-  use crate::node::tests::{Service, StartupState};
-  use sawtooth_sdk::consensus::engine::{Engine, PeerInfo};
-  use std::boxed::Box;
-  use std::sync::mpsc::channel;
+  use crate::node::PowConfig;
+  use crate::utils::{Clock, MockClock};
+  use std::sync::mpsc::{channel, Sender};
+  use std::sync::Mutex;
   use std::thread;
 
+  const MIN_PUBLISHING_DELAY_MS: u64 = 2000;
+
+  /// The event counters are shared, run one loop at a time
+  static SERIAL: Mutex<()> = Mutex::new(());
+
   macro_rules! one_of_each_update {
     ($sx:ident, $update: ident) => {
       let _ = $sx.send(Update::$update(vec![]));
@@ -185,74 +248,109 @@ mod tests {
       };
   }
 
-  macro_rules! simple_engine_loop {
-    ($sx:ident, $rx:ident, $message_pattern:expr, $($updates:ident),* ) => {
-      one_of_each_update!($sx, $($updates),*);
-
-      thread::spawn(move || $message_pattern);
+  /// Run the update loop of a node on `clock` until `driver`, running alongside, shuts it down
+  fn run_update_loop(
+    clock: &MockClock,
+    sx: Sender<Update>,
+    rx: Receiver<Update>,
+    driver: impl FnOnce(Sender<Update>) + Send + 'static,
+  ) {
+    let config = PowConfig {
+      min_publishing_delay_ms: MIN_PUBLISHING_DELAY_MS,
+      ..PowConfig::new()
+    };
+    let node = PowNode::with_clock(config, Box::new(MockService {}), clock.shared());
+    let stream = UpdateStream::new(rx, node);
+    let driver = thread::spawn(move || driver(sx));
 
-      let mut engine = PowEngine::new();
-      let service = Box::new(MockService {});
+    Builder::new_current_thread()
+      .build()
+      .expect("Async runtime")
+      .block_on(stream.update_loop());
+    driver.join().expect("Driver");
+  }
 
-      //dummy block
-      let chain_head = Block::default();
-      let startup_state: StartupState = StartupState {
-        chain_head,
-        peers: vec![],
-        local_peer_info: PeerInfo::default(),
-      };
+  /// Wait, without moving the clock, until the update loop catches up with `done`
+  fn wait_for(done: impl Fn() -> bool) {
+    while !done() {
+      thread::yield_now();
+    }
+  }
 
-      engine
-        .start($rx, service as Box<dyn Service>, startup_state)
-        .expect("start");
-    };
+  fn counted(counter: &'static AtomicUsize) -> impl Fn() -> usize + Copy {
+    let start = counter.load(Ordering::Acquire);
+    move || counter.load(Ordering::Acquire) - start
   }
 
   #[test]
   ///The event loop processed `COUNT_UPDATED` events.
-  /// Will fail if run in bulk due to the shared static update event counter.
-  /// use --test-threads 1 instead
   fn singled_out_update_events() {
+    let _serial = SERIAL
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let updated = counted(&COUNT_UPDATED);
+    let clock = MockClock::new(0.0);
     let (sx, rx) = channel::<Update>();
-    simple_engine_loop!(
-      sx,
-      rx,
-      {
-        let _ = sx.send(Update::Shutdown);
-      },
-      PeerDisconnected,
-      BlockValid,
-      BlockInvalid,
-      BlockCommit
-    );
-
-    assert_eq!(COUNT_UPDATED.load(Ordering::Acquire), 5);
+    one_of_each_update!(sx, PeerDisconnected, BlockValid, BlockInvalid, BlockCommit);
+    let _ = sx.send(Update::Shutdown);
+
+    run_update_loop(&clock, sx, rx, |_| {});
+
+    assert_eq!(updated(), 5);
   }
 
   #[test]
   ///The event loop attempted to publish `COUNT_PUBLISHED` and started the publisher interval `COUNT_COMMITTER` times.
   fn test_publishing_event() {
+    let _serial = SERIAL
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let published = counted(&COUNT_PUBLISHED);
+    let committed = counted(&COUNT_COMMITTER);
+    let clock = MockClock::new(0.0);
     let (sx, rx) = channel::<Update>();
-    simple_engine_loop!(
-      sx,
-      rx,
-      {
-        let _ = sx.send(Update::BlockCommit(vec![]));
-        //allow time for another publication
-        thread::sleep(Duration::from_secs(3));
+    one_of_each_update!(sx, BlockValid, BlockValid, BlockValid, BlockValid);
+
+    let driver_clock = clock.clone();
+    run_update_loop(&clock, sx, rx, move |sx| {
+      let clock = driver_clock;
+      for round in 1..=2 {
         let _ = sx.send(Update::BlockCommit(vec![]));
-        //allow time for another publication
-        thread::sleep(Duration::from_secs(3));
-        let _ = sx.send(Update::Shutdown);
-      },
-      BlockValid,
-      BlockValid,
-      BlockValid,
-      BlockValid
-    );
-
-    assert_eq!(COUNT_PUBLISHED.load(Ordering::Acquire), 2);
-    assert_eq!(COUNT_COMMITTER.load(Ordering::Acquire), 2);
+        wait_for(|| committed() == round);
+
+        // publishing waits for the timer restarted by the commit
+        clock.advance(Duration::from_millis(MIN_PUBLISHING_DELAY_MS - 10));
+        assert_eq!(published(), round - 1);
+        clock.advance(Duration::from_millis(20));
+        wait_for(|| published() == round);
+      }
+      let _ = sx.send(Update::Shutdown);
+    });
+
+    assert_eq!(published(), 2);
+    assert_eq!(committed(), 2);
+  }
+
+  #[test]
+  ///Updates are handled as they arrive, without the clock moving, and the loop stops once the validator hangs up.
+  fn updates_are_handled_as_they_arrive() {
+    let _serial = SERIAL
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let updated = counted(&COUNT_UPDATED);
+    let clock = MockClock::new(0.0);
+    let (sx, rx) = channel::<Update>();
+
+    let driver_clock = clock.clone();
+    run_update_loop(&clock, sx, rx, move |sx| {
+      for count in 1..=3 {
+        let _ = sx.send(Update::BlockValid(vec![]));
+        wait_for(|| updated() == count);
+      }
+      assert_eq!(driver_clock.now(), 0.0);
+    });
+
+    assert_eq!(updated(), 3);
   }
 
   /// Try to publish, leave it incomplete, then on_block_commit, finishes it,
@@ -12,11 +12,18 @@ mod macros;
 pub use sawtooth_sdk::consensus;
 pub use std::{pin::Pin, sync::mpsc, time::Duration};
 
+pub mod audit;
 pub mod block;
 pub mod engine;
+pub mod error;
 pub mod futures;
+pub mod logging;
+pub mod metrics;
 pub mod miner;
 pub mod node;
 pub mod primitives;
+pub mod settings;
+#[cfg(any(test, feature = "simulator"))]
+pub mod simulator;
 pub mod utils;
 pub mod work;
@@ -0,0 +1,161 @@
+use std::fmt::{Arguments, Display, Write};
+
+use fern::colors::{Color, ColoredLevelConfig};
+use fern::FormatCallback;
+use log::kv::{Error as KvError, Key, Value, VisitSource};
+use log::Record;
+
+const TIME_FMT: &str = "%Y-%m-%d %H:%M:%S.%3f";
+const JSON_TIME_FMT: &str = "%Y-%m-%dT%H:%M:%S.%3fZ";
+
+/// One line per record, with a coloured level, for terminals
+pub fn fmt_colored(out: FormatCallback, message: &Arguments, record: &Record) {
+  let colors = ColoredLevelConfig::new()
+    .info(Color::Green)
+    .debug(Color::Blue)
+    .trace(Color::BrightMagenta);
+
+  fmt_line(out, message, record, colors.color(record.level()))
+}
+
+/// One line per record, for files
+pub fn fmt_text(out: FormatCallback, message: &Arguments, record: &Record) {
+  fmt_line(out, message, record, record.level())
+}
+
+/// One JSON object per record, for log aggregators
+pub fn fmt_json(out: FormatCallback, message: &Arguments, record: &Record) {
+  let timestamp = chrono::Utc::now().format(JSON_TIME_FMT);
+  out.finish(format_args!(
+    "{}",
+    json_record(&timestamp.to_string(), message, record)
+  ))
+}
+
+fn fmt_line(out: FormatCallback, message: &Arguments, record: &Record, level: impl Display) {
+  let mut fields: String = String::new();
+  let _ = record.key_values().visit(&mut TextFields(&mut fields));
+
+  out.finish(format_args!(
+    "[{} {:<5} {}] {}{}",
+    chrono::Utc::now().format(TIME_FMT),
+    level,
+    module(record),
+    message,
+    fields
+  ))
+}
+
+fn module<'a>(record: &Record<'a>) -> &'a str {
+  record
+    .module_path_static()
+    .or_else(|| record.module_path())
+    .unwrap_or("???")
+}
+
+/// The JSON object for `record`, its structured fields follow the message
+pub(crate) fn json_record(timestamp: &str, message: &Arguments, record: &Record) -> String {
+  let mut out: String = String::from("{\"timestamp\":");
+  push_json_string(&mut out, timestamp);
+  out.push_str(",\"level\":");
+  push_json_string(&mut out, record.level().as_str());
+  out.push_str(",\"module\":");
+  push_json_string(&mut out, module(record));
+  out.push_str(",\"message\":");
+  push_json_string(&mut out, &message.to_string());
+  let _ = record.key_values().visit(&mut JsonFields(&mut out));
+  out.push('}');
+  out
+}
+
+fn push_json_string(out: &mut String, string: &str) {
+  out.push('"');
+  for c in string.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if c < ' ' => {
+        let _ = write!(out, "\\u{:04x}", c as u32);
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+/// Appends ` key=value` for every field
+struct TextFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for TextFields<'_> {
+  fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+    let _ = write!(self.0, " {}={}", key, value);
+    Ok(())
+  }
+}
+
+/// Appends `,"key":value` for every field, numbers and booleans are kept as such
+struct JsonFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+  fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+    let out: &mut String = self.0;
+    out.push(',');
+    push_json_string(out, key.as_str());
+    out.push(':');
+
+    if let Some(boolean) = value.to_bool() {
+      let _ = write!(out, "{}", boolean);
+    } else if let Some(number) = value.to_u64() {
+      let _ = write!(out, "{}", number);
+    } else if let Some(number) = value.to_i64() {
+      let _ = write!(out, "{}", number);
+    } else if let Some(number) = value.to_f64().filter(|number| number.is_finite()) {
+      let _ = write!(out, "{}", number);
+    } else {
+      push_json_string(out, &value.to_string());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use log::Level;
+
+  #[test]
+  fn records_are_rendered_as_flat_json_objects() {
+    let fields: [(&str, Value); 4] = [
+      ("block_num", Value::from(12u64)),
+      ("block_id", Value::from("ab\"cd")),
+      ("hash_rate", Value::from(1.5f64)),
+      ("valid", Value::from(true)),
+    ];
+    let record = Record::builder()
+      .level(Level::Info)
+      .module_path_static(Some("ccconsensus::node"))
+      .key_values(&fields)
+      .build();
+
+    assert_eq!(
+      json_record(
+        "2022-01-01T00:00:00.000Z",
+        &format_args!("line\none\t{}", 1),
+        &record
+      ),
+      "{\"timestamp\":\"2022-01-01T00:00:00.000Z\",\"level\":\"INFO\",\
+       \"module\":\"ccconsensus::node\",\"message\":\"line\\none\\t1\",\
+       \"block_num\":12,\"block_id\":\"ab\\\"cd\",\"hash_rate\":1.5,\"valid\":true}"
+    );
+  }
+
+  #[test]
+  fn control_characters_are_escaped() {
+    let mut out: String = String::new();
+    push_json_string(&mut out, "a\u{1}\\");
+    assert_eq!(out, "\"a\\u0001\\\\\"");
+  }
+}
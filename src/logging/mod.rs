@@ -0,0 +1,5 @@
+mod format;
+mod setup;
+
+pub use self::format::*;
+pub use self::setup::*;
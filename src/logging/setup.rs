@@ -0,0 +1,200 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::stdout;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use fern::{DateBased, Dispatch};
+use log::LevelFilter;
+
+use crate::logging::{fmt_colored, fmt_json, fmt_text};
+
+/// How records are written out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+  /// One line per record, coloured on the console
+  #[default]
+  Text,
+  /// One JSON object per record
+  Json,
+}
+
+impl FromStr for LogFormat {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "text" => Ok(Self::Text),
+      "json" => Ok(Self::Json),
+      _ => Err(format!("Unknown log format: {}", string)),
+    }
+  }
+}
+
+/// How often the log file is swapped for a new one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotation {
+  #[default]
+  Daily,
+  Hourly,
+}
+
+impl LogRotation {
+  /// The strftime suffix of the log files, a new suffix starts a new file
+  fn suffix(self) -> &'static str {
+    match self {
+      Self::Daily => "%Y-%m-%d",
+      Self::Hourly => "%Y-%m-%d-%H",
+    }
+  }
+}
+
+impl FromStr for LogRotation {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "daily" => Ok(Self::Daily),
+      "hourly" => Ok(Self::Hourly),
+      _ => Err(format!("Unknown log rotation: {}", string)),
+    }
+  }
+}
+
+/// The level of the records from a module and its submodules, written `module=level`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleLevel {
+  pub module: String,
+  pub level: LevelFilter,
+}
+
+impl FromStr for ModuleLevel {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    let (module, level) = string
+      .split_once('=')
+      .ok_or_else(|| format!("Expected module=level: {}", string))?;
+    let level: LevelFilter = level
+      .parse()
+      .map_err(|_| format!("Unknown log level: {}", level))?;
+
+    Ok(Self {
+      module: module.to_string(),
+      level,
+    })
+  }
+}
+
+impl Display for ModuleLevel {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    write!(f, "{}={}", self.module, self.level)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct LogSettings {
+  pub level: LevelFilter,
+  pub format: LogFormat,
+  /// Also log to files named after this path, followed by the date
+  pub file: Option<PathBuf>,
+  pub rotation: LogRotation,
+  /// Levels overriding `level`, the last one given for a module wins
+  pub modules: Vec<ModuleLevel>,
+}
+
+impl Default for LogSettings {
+  fn default() -> Self {
+    Self {
+      level: LevelFilter::Warn,
+      format: LogFormat::default(),
+      file: None,
+      rotation: LogRotation::default(),
+      modules: Vec::new(),
+    }
+  }
+}
+
+impl LogSettings {
+  /// Set the level of every module from `level`, or of a single one from `module=level`
+  pub fn set_level(&mut self, entry: &str) -> Result<(), String> {
+    if entry.contains('=') {
+      self.modules.push(entry.parse()?);
+    } else {
+      self.level = entry
+        .parse()
+        .map_err(|_| format!("Unknown log level: {}", entry))?;
+    }
+    Ok(())
+  }
+
+  /// Install the global logger, once per process
+  pub fn apply(&self) -> Result<()> {
+    let mut dispatch: Dispatch = Dispatch::new().level(self.level).level_for(
+      "sawtooth_sdk_creditcoin::messaging::zmq_stream",
+      LevelFilter::Error,
+    );
+    for module in self.modules.iter() {
+      dispatch = dispatch.level_for(module.module.clone(), module.level);
+    }
+
+    let console: Dispatch = match self.format {
+      LogFormat::Text => Dispatch::new().format(fmt_colored),
+      LogFormat::Json => Dispatch::new().format(fmt_json),
+    };
+    dispatch = dispatch.chain(console.chain(stdout()));
+
+    if let Some(file) = &self.file {
+      let mut prefix = file.clone().into_os_string();
+      prefix.push(".");
+      let file: Dispatch = match self.format {
+        LogFormat::Text => Dispatch::new().format(fmt_text),
+        LogFormat::Json => Dispatch::new().format(fmt_json),
+      };
+      dispatch =
+        dispatch.chain(file.chain(DateBased::new(prefix, self.rotation.suffix()).utc_time()));
+    }
+
+    dispatch.apply()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn module_levels_parse_from_the_command_line() {
+    assert_eq!(
+      "ccconsensus::node=debug".parse::<ModuleLevel>(),
+      Ok(ModuleLevel {
+        module: "ccconsensus::node".to_string(),
+        level: LevelFilter::Debug,
+      })
+    );
+    assert!("ccconsensus::node".parse::<ModuleLevel>().is_err());
+    assert!("ccconsensus::node=loud".parse::<ModuleLevel>().is_err());
+
+    assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+    assert_eq!("hourly".parse::<LogRotation>(), Ok(LogRotation::Hourly));
+    assert!("yearly".parse::<LogRotation>().is_err());
+  }
+
+  #[test]
+  fn levels_apply_to_every_module_or_to_one() {
+    let mut settings = LogSettings::default();
+    settings.set_level("info").unwrap();
+    settings.set_level("ccconsensus::node=trace").unwrap();
+
+    assert_eq!(settings.level, LevelFilter::Info);
+    assert_eq!(
+      settings.modules,
+      vec![ModuleLevel {
+        module: "ccconsensus::node".to_string(),
+        level: LevelFilter::Trace,
+      }]
+    );
+    assert!(settings.set_level("loud").is_err());
+  }
+}
@@ -6,6 +6,6 @@ macro_rules! conf_key {
 
 macro_rules! dbg_hex {
   ($expr:expr) => {
-    &$crate::utils::to_hex($expr)[..16]
+    &$crate::utils::to_hex_prefix($expr, 8)
   };
 }
@@ -5,81 +5,176 @@ extern crate clap;
 extern crate log;
 
 use anyhow::Result;
+use ccconsensus::audit::{self, Finding};
+use ccconsensus::block::Block;
 use ccconsensus::engine::PowEngine;
-use fern::{
-  colors::{Color, ColoredLevelConfig},
-  Dispatch, FormatCallback,
-};
-use log::{LevelFilter, Record};
+use ccconsensus::logging::LogSettings;
+use ccconsensus::metrics;
+use ccconsensus::node::PowConfig;
+use ccconsensus::settings::Settings;
+use ccconsensus::utils::utc_seconds_f64;
+use log::LevelFilter;
 use sawtooth_sdk::consensus::zmq_driver::ZmqDriver;
-use std::{fmt::Arguments, io::stdout};
+use std::env;
+use std::fs::File;
+use std::io::{stdin, BufReader};
 
 const DEFAULT_ENDPOINT: &str = "tcp://localhost:5050";
-
-const TIME_FMT: &str = "%Y-%m-%d %H:%M:%S.%3f";
-
-fn fmt_log(out: FormatCallback, message: &Arguments, record: &Record) {
-  let module: &str = record
-    .module_path_static()
-    .or_else(|| record.module_path())
-    .unwrap_or("???");
-  let colors = ColoredLevelConfig::new()
-    .info(Color::Green)
-    .debug(Color::Blue)
-    .trace(Color::BrightMagenta);
-
-  out.finish(format_args!(
-    "[{} {:<5} {}] {}",
-    chrono::Utc::now().format(TIME_FMT),
-    colors.color(record.level()),
-    module,
-    message
-  ))
-}
-
-fn setup_logs(verbose_count: u64) -> Result<()> {
-  let level = match verbose_count {
-    0 => LevelFilter::Warn,
-    1 => LevelFilter::Info,
-    2 => LevelFilter::Debug,
-    _ => LevelFilter::Trace,
-  };
-
-  Dispatch::new()
-    .level(level)
-    .level_for(
-      "sawtooth_sdk_creditcoin::messaging::zmq_stream",
-      LevelFilter::Error,
-    )
-    .format(fmt_log)
-    .chain(stdout())
-    .apply()?;
-
-  Ok(())
-}
+/// Metrics are only served to the host unless another address is set
+const DEFAULT_METRICS_ADDRESS: &str = "127.0.0.1";
+/// Exit status of an audit that found inconsistencies, one that couldn't run exits with 1
+const AUDIT_FINDINGS_STATUS: i32 = 2;
 
 fn main() -> Result<()> {
   let matches = clap_app!(consensus_engine =>
     (version: crate_version!())
     (author: crate_authors!())
     (about: crate_description!())
+    (@arg config: -c --config +takes_value "TOML settings file, also read from CCCONSENSUS_CONFIG; every setting may be set in it or as a CCCONSENSUS_<SETTING> variable, flags win over variables which win over the file, which wins over on-chain settings")
     (@arg endpoint: -E --endpoint +takes_value "connection endpoint for validator")
     (@arg verbose: -v --verbose +multiple "increase output verbosity")
+    (@arg log_format: --("log-format") +takes_value possible_values(&["text", "json"]) "text (default) or json, one object per record")
+    (@arg log_file: --("log-file") +takes_value "also log to files named after this path and the date")
+    (@arg log_rotation: --("log-rotation") +takes_value possible_values(&["daily", "hourly"]) "how often to start a new log file, daily by default")
+    (@arg log_level: -L --("log-level") +takes_value +multiple number_of_values(1) "level or module=level, e.g. ccconsensus::node=debug, may be repeated")
+    (@arg workers: -w --workers +takes_value "number of mining threads")
+    (@arg stats_interval: -s --("stats-interval") +takes_value "seconds between mining summaries in the log, 0 disables them")
+    (@arg metrics_port: -m --("metrics-port") +takes_value "port serving Prometheus metrics, off unless set")
+    (@arg metrics_address: --("metrics-address") +takes_value "address serving Prometheus metrics, 127.0.0.1 by default")
+    (@subcommand audit =>
+      (about: "check an exported chain against the consensus rules, with the settings in place of the on-chain ones, exits with 2 if it finds inconsistencies")
+      (@arg chain: +required "the exported chain, one JSON block per line, - for stdin")
+    )
   )
   .get_matches();
 
-  let endpoint: &str = matches.value_of("endpoint").unwrap_or(DEFAULT_ENDPOINT);
-  setup_logs(matches.occurrences_of("verbose"))?;
+  // CLI > env > file > on-chain: `PowConfig::load` skips the consensus settings pinned here
+  let env: Settings = Settings::from_env(env::vars());
+  let file: Settings = match matches.value_of("config").or_else(|| env.get("config")) {
+    Some(path) => Settings::from_file(path.as_ref())?,
+    None => Settings::default(),
+  };
+  let settings: Settings = file.merge(env);
+  let setting = |arg: &str, key: &str| -> Option<String> {
+    matches
+      .value_of(arg)
+      .or_else(|| settings.get(key))
+      .map(String::from)
+  };
+
+  let endpoint: String = setting("endpoint", "endpoint").unwrap_or_else(|| DEFAULT_ENDPOINT.into());
+
+  let mut logs: LogSettings = LogSettings {
+    file: setting("log_file", "log_file").map(Into::into),
+    ..LogSettings::default()
+  };
+  if !matches.is_present("log_level") {
+    for entry in settings
+      .get("log_level")
+      .into_iter()
+      .flat_map(|levels| levels.split(','))
+    {
+      logs
+        .set_level(entry.trim())
+        .map_err(|error| anyhow::anyhow!("Invalid log level {}: {}", entry, error))?;
+    }
+  }
+  logs.level = match matches.occurrences_of("verbose") {
+    0 => logs.level,
+    1 => LevelFilter::Info,
+    2 => LevelFilter::Debug,
+    _ => LevelFilter::Trace,
+  };
+  for entry in matches.values_of("log_level").into_iter().flatten() {
+    logs
+      .set_level(entry)
+      .map_err(|error| anyhow::anyhow!("Invalid log level {}: {}", entry, error))?;
+  }
+  if let Some(format) = setting("log_format", "log_format") {
+    logs.format = format
+      .parse()
+      .map_err(|error| anyhow::anyhow!("Invalid log format {}: {}", format, error))?;
+  }
+  if let Some(rotation) = setting("log_rotation", "log_rotation") {
+    logs.rotation = rotation
+      .parse()
+      .map_err(|error| anyhow::anyhow!("Invalid log rotation {}: {}", rotation, error))?;
+  }
+  logs.apply()?;
+
+  let mut config: PowConfig = settings.pow_config()?;
+
+  if let Some(matches) = matches.subcommand_matches("audit") {
+    let findings: usize = audit_chain(matches.value_of("chain").unwrap_or("-"), &config)?;
+    if findings > 0 {
+      // exiting skips the destructors that would flush the log files
+      log::logger().flush();
+      std::process::exit(AUDIT_FINDINGS_STATUS);
+    }
+    return Ok(());
+  }
+
+  if let Some(workers) = matches.value_of("workers") {
+    config.worker_threads = workers
+      .parse()
+      .map_err(|error| anyhow::anyhow!("Invalid worker count {}: {}", workers, error))?;
+  }
+
+  if let Some(interval) = matches.value_of("stats_interval") {
+    config.stats_interval_secs = interval
+      .parse()
+      .map_err(|error| anyhow::anyhow!("Invalid stats interval {}: {}", interval, error))?;
+  }
+
+  if let Some(port) = setting("metrics_port", "metrics_port") {
+    let port: u16 = port
+      .parse()
+      .map_err(|error| anyhow::anyhow!("Invalid metrics port {}: {}", port, error))?;
+    let address: String = setting("metrics_address", "metrics_address")
+      .unwrap_or_else(|| DEFAULT_METRICS_ADDRESS.into());
+    let address = metrics::serve((address.as_str(), port))?;
+    info!("PoW engine serving metrics on {}", address);
+  }
 
   info!("PoW engine ({})", env!("CARGO_PKG_VERSION"));
   info!("PoW engine connecting to {} ...", endpoint);
+  info!("PoW engine mining with {} thread(s)", config.worker_threads);
 
-  let engine: PowEngine = PowEngine::new();
+  let engine: PowEngine = PowEngine::with_config(config);
   let (driver, _stop) = ZmqDriver::new();
 
-  driver.start(endpoint, engine)?;
+  driver.start(&endpoint, engine)?;
 
   info!("PoW engine exiting ...");
 
   Ok(())
 }
+
+/// Print the inconsistencies found in the export at `path`, and return how many there are
+fn audit_chain(path: &str, config: &PowConfig) -> Result<usize> {
+  let blocks: Vec<Block> = if path == "-" {
+    audit::read_export(stdin().lock())?
+  } else {
+    let file =
+      File::open(path).map_err(|error| anyhow::anyhow!("Cannot open {}: {}", path, error))?;
+    audit::read_export(BufReader::new(file))?
+  };
+  let count: usize = blocks.len();
+
+  let findings: Vec<Finding> = audit::audit(blocks, config, utc_seconds_f64());
+  for finding in findings.iter() {
+    println!("{}", finding);
+  }
+
+  if findings.is_empty() {
+    println!("{} blocks audited, no inconsistencies", count);
+  } else {
+    println!(
+      "{} blocks audited, {} inconsistencies",
+      count,
+      findings.len()
+    );
+  }
+
+  Ok(findings.len())
+}
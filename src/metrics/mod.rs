@@ -0,0 +1,5 @@
+mod registry;
+mod server;
+
+pub use self::registry::*;
+pub use self::server::*;
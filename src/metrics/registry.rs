@@ -0,0 +1,381 @@
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::node::Guard;
+use crate::Duration;
+
+/// Every metric of the engine, collected whether or not the endpoint is served
+pub static METRICS: Metrics = Metrics::new();
+
+/// A count that only goes up
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+  pub const fn new() -> Self {
+    Self(AtomicU64::new(0))
+  }
+
+  pub fn inc(&self) {
+    self.add(1);
+  }
+
+  pub fn add(&self, count: u64) {
+    self.0.fetch_add(count, Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> u64 {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// A value that is set
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+  pub const fn new() -> Self {
+    Self(AtomicU64::new(0))
+  }
+
+  pub fn set(&self, value: u64) {
+    self.0.store(value, Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> u64 {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// Observations counted into cumulative buckets, as Prometheus histograms are
+#[derive(Debug)]
+pub struct Histogram<const N: usize> {
+  bounds: [f64; N],
+  buckets: [Counter; N],
+  count: Counter,
+  /// The bits of the `f64` sum of observations
+  sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+  pub const fn new(bounds: [f64; N]) -> Self {
+    Self {
+      bounds,
+      buckets: [const { Counter::new() }; N],
+      count: Counter::new(),
+      sum: AtomicU64::new(0),
+    }
+  }
+
+  pub fn observe(&self, value: f64) {
+    for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+      if value <= *bound {
+        bucket.inc();
+      }
+    }
+    self.count.inc();
+
+    let _ = self
+      .sum
+      .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + value).to_bits())
+      });
+  }
+
+  pub fn count(&self) -> u64 {
+    self.count.get()
+  }
+
+  pub fn sum(&self) -> f64 {
+    f64::from_bits(self.sum.load(Ordering::Relaxed))
+  }
+}
+
+/// Where `PowNode::try_publish` stopped
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum PublishOutcome {
+  /// A block was already published at this height
+  Skipped,
+  /// The stage is not ready yet
+  Pending,
+  Published,
+  Error,
+}
+
+impl PublishOutcome {
+  const ALL: [Self; 4] = [Self::Skipped, Self::Pending, Self::Published, Self::Error];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Skipped => "skipped",
+      Self::Pending => "pending",
+      Self::Published => "published",
+      Self::Error => "error",
+    }
+  }
+}
+
+const GUARDS: [Guard; 3] = [Guard::Consensus, Guard::Summarized, Guard::Finalized];
+
+fn guard_name(guard: Guard) -> &'static str {
+  match guard {
+    Guard::Consensus => "consensus",
+    Guard::Summarized => "summarized",
+    Guard::Finalized => "finalized",
+  }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+  /// Hashes computed by the mining threads
+  pub hashes: Counter,
+  /// Difficulty of the block being mined
+  pub difficulty: Gauge,
+  /// Difficulty the block being mined asks of its successor
+  pub next_difficulty: Gauge,
+  pub blocks_published: Counter,
+  pub blocks_committed: Counter,
+  pub blocks_ignored: Counter,
+  pub blocks_failed: Counter,
+  /// Fork resolutions that switched to the new fork
+  pub forks_switched: Counter,
+  /// Fork resolutions that kept the current chain
+  pub forks_kept: Counter,
+  /// Blocks between the common ancestor and the new head of each resolved fork
+  pub fork_depth: Histogram<7>,
+  /// Seconds an update from the validator waited before the update loop handled it
+  pub update_latency: Histogram<7>,
+  /// Block lookups answered from the header cache
+  pub header_cache_hits: Counter,
+  /// Block lookups that went to the validator
+  pub header_cache_misses: Counter,
+  /// `try_publish` calls by the stage they stopped at and how
+  publish_attempts: [[Counter; 4]; 3],
+}
+
+impl Metrics {
+  pub const fn new() -> Self {
+    Self {
+      hashes: Counter::new(),
+      difficulty: Gauge::new(),
+      next_difficulty: Gauge::new(),
+      blocks_published: Counter::new(),
+      blocks_committed: Counter::new(),
+      blocks_ignored: Counter::new(),
+      blocks_failed: Counter::new(),
+      forks_switched: Counter::new(),
+      forks_kept: Counter::new(),
+      fork_depth: Histogram::new([1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 21.0]),
+      update_latency: Histogram::new([0.0001, 0.0005, 0.001, 0.005, 0.01, 0.1, 1.0]),
+      header_cache_hits: Counter::new(),
+      header_cache_misses: Counter::new(),
+      publish_attempts: [const { [const { Counter::new() }; 4] }; 3],
+    }
+  }
+
+  pub fn publish_attempt(&self, stage: Guard, outcome: PublishOutcome) {
+    self.publish_attempts[stage as usize][outcome as usize].inc();
+  }
+
+  pub fn publish_attempts(&self, stage: Guard, outcome: PublishOutcome) -> u64 {
+    self.publish_attempts[stage as usize][outcome as usize].get()
+  }
+
+  pub fn update_handled(&self, latency: Duration) {
+    self.update_latency.observe(latency.as_secs_f64());
+  }
+
+  /// The Prometheus text exposition of every metric
+  pub fn render(&self) -> String {
+    let mut out: String = String::new();
+
+    header(
+      &mut out,
+      "hashes_total",
+      "counter",
+      "Hashes computed by the mining threads, its rate is the hash rate",
+    );
+    sample(&mut out, "hashes_total", "", self.hashes.get());
+
+    header(
+      &mut out,
+      "difficulty",
+      "gauge",
+      "Difficulty of the block being mined",
+    );
+    sample(&mut out, "difficulty", "", self.difficulty.get());
+
+    header(
+      &mut out,
+      "next_difficulty",
+      "gauge",
+      "Difficulty the block being mined asks of its successor",
+    );
+    sample(&mut out, "next_difficulty", "", self.next_difficulty.get());
+
+    header(
+      &mut out,
+      "blocks_total",
+      "counter",
+      "Blocks by what the engine did with them",
+    );
+    for (outcome, counter) in [
+      ("published", &self.blocks_published),
+      ("committed", &self.blocks_committed),
+      ("ignored", &self.blocks_ignored),
+      ("failed", &self.blocks_failed),
+    ] {
+      let labels: String = format!("outcome=\"{}\"", outcome);
+      sample(&mut out, "blocks_total", &labels, counter.get());
+    }
+
+    header(
+      &mut out,
+      "fork_resolutions_total",
+      "counter",
+      "Fork resolutions by their result",
+    );
+    for (result, counter) in [
+      ("switched", &self.forks_switched),
+      ("kept", &self.forks_kept),
+    ] {
+      let labels: String = format!("result=\"{}\"", result);
+      sample(&mut out, "fork_resolutions_total", &labels, counter.get());
+    }
+
+    header(
+      &mut out,
+      "fork_depth",
+      "histogram",
+      "Blocks between the common ancestor and the new head of a resolved fork",
+    );
+    histogram(&mut out, "fork_depth", &self.fork_depth);
+
+    header(
+      &mut out,
+      "publish_attempts_total",
+      "counter",
+      "Publishing attempts by the stage they stopped at and how",
+    );
+    for stage in GUARDS {
+      for outcome in PublishOutcome::ALL {
+        let labels: String = format!(
+          "stage=\"{}\",outcome=\"{}\"",
+          guard_name(stage),
+          outcome.name()
+        );
+        let count: u64 = self.publish_attempts(stage, outcome);
+        sample(&mut out, "publish_attempts_total", &labels, count);
+      }
+    }
+
+    header(
+      &mut out,
+      "update_latency_seconds",
+      "histogram",
+      "Time updates from the validator waited to be handled",
+    );
+    histogram(&mut out, "update_latency_seconds", &self.update_latency);
+
+    header(
+      &mut out,
+      "header_cache_lookups_total",
+      "counter",
+      "Block lookups by whether the header cache answered them",
+    );
+    for (result, counter) in [
+      ("hit", &self.header_cache_hits),
+      ("miss", &self.header_cache_misses),
+    ] {
+      let labels: String = format!("result=\"{}\"", result);
+      sample(
+        &mut out,
+        "header_cache_lookups_total",
+        &labels,
+        counter.get(),
+      );
+    }
+
+    out
+  }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+  let _ = writeln!(out, "# HELP ccconsensus_{} {}", name, help);
+  let _ = writeln!(out, "# TYPE ccconsensus_{} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl Display) {
+  if labels.is_empty() {
+    let _ = writeln!(out, "ccconsensus_{} {}", name, value);
+  } else {
+    let _ = writeln!(out, "ccconsensus_{}{{{}}} {}", name, labels, value);
+  }
+}
+
+fn histogram<const N: usize>(out: &mut String, name: &str, histogram: &Histogram<N>) {
+  let bucket: String = format!("{}_bucket", name);
+  for (bound, counter) in histogram.bounds.iter().zip(histogram.buckets.iter()) {
+    sample(out, &bucket, &format!("le=\"{}\"", bound), counter.get());
+  }
+  sample(out, &bucket, "le=\"+Inf\"", histogram.count());
+  sample(out, &format!("{}_sum", name), "", histogram.sum());
+  sample(out, &format!("{}_count", name), "", histogram.count());
+}
+
+impl Default for Metrics {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn histograms_count_into_cumulative_buckets() {
+    let histogram: Histogram<3> = Histogram::new([1.0, 2.0, 5.0]);
+    histogram.observe(1.0);
+    histogram.observe(3.0);
+    histogram.observe(8.0);
+
+    let mut out: String = String::new();
+    self::histogram(&mut out, "depth", &histogram);
+
+    assert_eq!(
+      out,
+      "ccconsensus_depth_bucket{le=\"1\"} 1\n\
+       ccconsensus_depth_bucket{le=\"2\"} 1\n\
+       ccconsensus_depth_bucket{le=\"5\"} 2\n\
+       ccconsensus_depth_bucket{le=\"+Inf\"} 3\n\
+       ccconsensus_depth_sum 12\n\
+       ccconsensus_depth_count 3\n"
+    );
+  }
+
+  #[test]
+  fn renders_every_family_with_its_type() {
+    let metrics: Metrics = Metrics::new();
+    metrics.difficulty.set(22);
+    metrics.blocks_ignored.add(2);
+    metrics.publish_attempt(Guard::Summarized, PublishOutcome::Pending);
+    metrics.update_handled(Duration::from_micros(300));
+    metrics.header_cache_hits.add(3);
+
+    let out: String = metrics.render();
+
+    assert!(out.contains("# TYPE ccconsensus_hashes_total counter\nccconsensus_hashes_total 0\n"));
+    assert!(out.contains("ccconsensus_difficulty 22\n"));
+    assert!(out.contains("ccconsensus_blocks_total{outcome=\"ignored\"} 2\n"));
+    assert!(out.contains(
+      "ccconsensus_publish_attempts_total{stage=\"summarized\",outcome=\"pending\"} 1\n"
+    ));
+    assert!(out
+      .contains("ccconsensus_publish_attempts_total{stage=\"consensus\",outcome=\"pending\"} 0\n"));
+    assert!(out.contains("ccconsensus_update_latency_seconds_bucket{le=\"0.0005\"} 1\n"));
+    assert!(out.contains("ccconsensus_update_latency_seconds_bucket{le=\"0.0001\"} 0\n"));
+    assert!(out.contains("ccconsensus_header_cache_lookups_total{result=\"hit\"} 3\n"));
+    assert_eq!(out.matches("# TYPE").count(), 9);
+  }
+}
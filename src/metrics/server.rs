@@ -0,0 +1,77 @@
+use std::io::{Read, Result as IoResult, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+use crate::metrics::METRICS;
+use crate::Duration;
+
+/// Requests larger than this are cut off, the metrics are served whatever was asked for
+const MAX_REQUEST: usize = 8192;
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serve `METRICS` in the Prometheus text format on `address`, from a thread of its own.
+///
+/// Returns the bound address, which tells the port picked when asked for port 0.
+pub fn serve(address: impl ToSocketAddrs) -> IoResult<SocketAddr> {
+  let listener: TcpListener = TcpListener::bind(address)?;
+  let local: SocketAddr = listener.local_addr()?;
+
+  thread::Builder::new()
+    .name("metrics".into())
+    .spawn(move || {
+      for stream in listener.incoming() {
+        match stream.and_then(respond) {
+          Ok(()) => {}
+          Err(error) => debug!("Metrics request failed: {}", error),
+        }
+      }
+    })?;
+
+  Ok(local)
+}
+
+fn respond(mut stream: TcpStream) -> IoResult<()> {
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+  // Read up to the end of the request headers
+  let mut request: Vec<u8> = Vec::new();
+  let mut buffer: [u8; 1024] = [0; 1024];
+  while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST {
+    match stream.read(&mut buffer)? {
+      0 => break,
+      read => request.extend_from_slice(&buffer[..read]),
+    }
+  }
+
+  let body: String = METRICS.render();
+  write!(
+    stream,
+    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    body.len(),
+    body
+  )?;
+  stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn serves_the_metrics_over_http() -> IoResult<()> {
+    let address: SocketAddr = serve("127.0.0.1:0")?;
+    METRICS.blocks_committed.inc();
+
+    let mut stream: TcpStream = TcpStream::connect(address)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response: String = String::new();
+    stream.read_to_string(&mut response)?;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(response.contains("\r\n\r\n# HELP ccconsensus_hashes_total"));
+    assert!(response.contains("ccconsensus_blocks_total{outcome=\"committed\"} "));
+
+    Ok(())
+  }
+}
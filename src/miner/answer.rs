@@ -1,5 +1,6 @@
 use crate::block::{BlockConsensus, SerializedBlockConsensus};
 use crate::miner::Challenge;
+use crate::primitives::H256;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Answer {
@@ -7,9 +8,23 @@ pub struct Answer {
   pub nonce: u64,
 }
 
+impl Answer {
+  /// The work the nonce proves, as fork choice weighs achieved work
+  pub fn work(&self) -> H256 {
+    let mut algorithm = self.challenge.algorithm.build();
+    let hash: H256 = algorithm.hash(
+      &self.challenge.block_id,
+      &self.challenge.peer_id,
+      self.nonce,
+    );
+    self.challenge.mode.achieved_work(&*algorithm, &hash)
+  }
+}
+
 impl From<&Answer> for SerializedBlockConsensus {
   fn from(answer: &Answer) -> Self {
-    BlockConsensus::serialize(
+    BlockConsensus::serialize_as(
+      answer.challenge.format,
       answer.challenge.next_difficulty,
       answer.challenge.timestamp,
       answer.nonce,
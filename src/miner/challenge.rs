@@ -1,6 +1,7 @@
-use crate::block::BlockId;
+use crate::block::{BlockId, ConsensusFormat};
 use crate::node::PeerId;
 use crate::primitives::{CCDifficulty, CCTimestamp};
+use crate::work::{DifficultyMode, PowAlgorithmKind};
 use std::fmt::{Debug, Formatter, Result};
 
 #[derive(Clone, PartialEq)]
@@ -11,6 +12,11 @@ pub struct Challenge {
   pub timestamp: CCTimestamp,
   pub block_id: BlockId,
   pub peer_id: PeerId,
+  pub algorithm: PowAlgorithmKind,
+  /// The payload encoding of the block being mined
+  pub format: ConsensusFormat,
+  /// How `difficulty` is read
+  pub mode: DifficultyMode,
 }
 
 impl Debug for Challenge {
@@ -21,6 +27,9 @@ impl Debug for Challenge {
       .field("timestamp", &self.timestamp)
       .field("block_id", &dbg_hex!(&self.block_id))
       .field("peer_id", &dbg_hex!(&self.peer_id))
+      .field("algorithm", &self.algorithm)
+      .field("format", &self.format)
+      .field("mode", &self.mode)
       .finish()
   }
 }
@@ -1,7 +1,26 @@
 use crossbeam_channel::unbounded;
 use crossbeam_channel::Receiver;
+use crossbeam_channel::RecvTimeoutError;
 use crossbeam_channel::Sender;
 use crossbeam_channel::TryRecvError;
+use std::error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Instant;
+
+/// The other end of a `Channel` is gone, e.g. a mining thread stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Disconnected;
+
+impl error::Error for Disconnected {}
+
+impl Display for Disconnected {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    write!(f, "Channel Disconnected")
+  }
+}
+
+/// Parent and child ends of a `Channel::fan_out`
+pub type FanOut<T, U> = (Vec<Channel<T, U>>, Vec<Channel<U, T>>);
 
 #[derive(Clone, Debug)]
 pub struct Channel<T, U> {
@@ -24,21 +43,72 @@ where
     (chan1, chan2)
   }
 
-  pub fn send(&self, message: T) {
-    self.tx.send(message).expect("Channel Disconnected (send)");
+  /// Connects a parent to `count` children.
+  ///
+  /// Each parent channel talks to its own child, but all of them share the
+  /// receiving end, so the parent can read every child's messages from any one of them.
+  pub fn fan_out(count: usize) -> FanOut<T, U> {
+    let (tx_c, rx_c): (Sender<U>, Receiver<U>) = unbounded();
+
+    (0..count)
+      .map(|_| {
+        let (tx_p, rx_p): (Sender<T>, Receiver<T>) = unbounded();
+
+        let parent: Channel<T, U> = Channel {
+          tx: tx_p,
+          rx: rx_c.clone(),
+        };
+        let child: Channel<U, T> = Channel {
+          tx: tx_c.clone(),
+          rx: rx_p,
+        };
+
+        (parent, child)
+      })
+      .unzip()
+  }
+
+  pub fn send(&self, message: T) -> Result<(), Disconnected> {
+    self.tx.send(message).map_err(|_| Disconnected)
   }
 
-  pub fn recv(&self) -> U {
-    self.rx.recv().expect("Channel Disconnected (recv)")
+  pub fn recv(&self) -> Result<U, Disconnected> {
+    self.rx.recv().map_err(|_| Disconnected)
   }
 
-  pub fn try_recv(&self) -> Option<U> {
+  /// The next message if there is one
+  pub fn try_recv(&self) -> Result<Option<U>, Disconnected> {
     match self.rx.try_recv() {
-      Ok(message) => Some(message),
-      Err(TryRecvError::Empty) => None,
-      Err(TryRecvError::Disconnected) => {
-        panic!("Channel Disconnected (try_recv)");
-      }
+      Ok(message) => Ok(Some(message)),
+      Err(TryRecvError::Empty) => Ok(None),
+      Err(TryRecvError::Disconnected) => Err(Disconnected),
+    }
+  }
+
+  /// The next message, waiting for one until `deadline`
+  pub fn recv_deadline(&self, deadline: Instant) -> Result<Option<U>, Disconnected> {
+    match self.rx.recv_deadline(deadline) {
+      Ok(message) => Ok(Some(message)),
+      Err(RecvTimeoutError::Timeout) => Ok(None),
+      Err(RecvTimeoutError::Disconnected) => Err(Disconnected),
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn a_dropped_end_is_an_error_not_a_panic() {
+    let (parent, child): (Channel<u8, u8>, Channel<u8, u8>) = Channel::duplex();
+    parent.send(1).unwrap();
+    assert_eq!(child.try_recv(), Ok(Some(1)));
+    assert_eq!(child.try_recv(), Ok(None));
+
+    drop(child);
+    assert_eq!(parent.send(2), Err(Disconnected));
+    assert_eq!(parent.recv(), Err(Disconnected));
+    assert_eq!(parent.try_recv(), Err(Disconnected));
+  }
+}
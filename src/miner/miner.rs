@@ -1,42 +1,118 @@
 use std::cell::RefCell;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::time::Instant;
 
-use sawtooth_sdk::consensus::engine::Error;
-
-use crate::utils::utc_seconds_f64;
-use crate::work::get_difficulty;
+use crate::error::PowError;
+use crate::metrics::METRICS;
+use crate::utils::{SharedClock, SystemClock};
+use crate::work::{
+  get_difficulty, initial_difficulty, median_time_past, DifficultyMode, PowAlgorithmKind,
+};
+use crate::Duration;
 use crate::{
-  block::{Block, BlockHeader, BlockId, SerializedBlockConsensus},
+  block::{BlockHeader, BlockId, ConsensusFormat, SerializedBlockConsensus},
   node::PowService,
 };
 use crate::{
-  miner::{Answer, Challenge, Worker},
+  miner::{Answer, Challenge, Disconnected, MiningStats, Worker},
   node::{PeerId, PowConfig},
 };
 
 use super::MessageToMiner;
 
-#[derive(Default)]
 pub struct Miner {
-  worker: Worker,
+  /// Replaced by a fresh pool if its threads stop
+  worker: RefCell<Worker>,
   answer: RefCell<Option<Answer>>,
+  stats: RefCell<MiningStats>,
+  /// Stamps the challenges
+  clock: SharedClock,
 }
 
 impl Miner {
+  pub fn new(worker_threads: usize, clock: SharedClock) -> Self {
+    let worker: Worker = Worker::new(worker_threads);
+    let stats: MiningStats = MiningStats::new(worker.threads());
+    Self {
+      worker: RefCell::new(worker),
+      answer: RefCell::new(None),
+      stats: RefCell::new(stats),
+      clock,
+    }
+  }
+
   pub fn try_create_consensus(&self) -> Option<SerializedBlockConsensus> {
-    // Drain answers from the worker thread
-    while let Some(msg) = self.worker.try_recv() {
-      match msg {
-        MessageToMiner::Solved(answer) => {
-          self.answer.borrow_mut().replace(answer);
-        }
-        MessageToMiner::Started => {
-          self.clear_answer();
+    self.drain();
+    self.answer.take().as_ref().map(|answer| answer.into())
+  }
+
+  /// Hash rate and best score of the worker threads, as of their latest reports
+  pub fn stats(&self) -> MiningStats {
+    self.drain();
+    self.stats.borrow().clone()
+  }
+
+  /// Block until the worker threads solved the current challenge, false if they didn't within
+  /// `timeout`. The answer is left for `try_create_consensus`.
+  pub fn wait_for_answer(&self, timeout: Duration) -> bool {
+    let deadline: Instant = Instant::now() + timeout;
+    self.drain();
+
+    while self.answer.borrow().is_none() {
+      let message: Result<Option<MessageToMiner>, Disconnected> =
+        self.worker.borrow().recv_deadline(deadline);
+      match message {
+        Ok(Some(msg)) => self.receive(msg),
+        Ok(None) => return false,
+        Err(Disconnected) if Instant::now() < deadline => self.restart_stopped(),
+        Err(Disconnected) => return false,
+      }
+    }
+
+    true
+  }
+
+  /// Drain answers and statistics from the worker threads
+  fn drain(&self) {
+    loop {
+      let message: Result<Option<MessageToMiner>, Disconnected> = self.worker.borrow().try_recv();
+      match message {
+        Ok(Some(msg)) => self.receive(msg),
+        Ok(None) => break,
+        Err(Disconnected) => {
+          self.restart_stopped();
+          break;
         }
       };
     }
+  }
 
-    self.answer.take().as_ref().map(|answer| answer.into())
+  fn receive(&self, msg: MessageToMiner) {
+    match msg {
+      MessageToMiner::Solved(answer) => {
+        // every thread only improves on its own answers, keep the best of all of them
+        let mut held = self.answer.borrow_mut();
+        let better: bool = match held.as_ref() {
+          Some(current) if current.challenge == answer.challenge => answer.work() > current.work(),
+          _ => true,
+        };
+        if better {
+          held.replace(answer);
+        }
+      }
+      MessageToMiner::Started => {
+        self.clear_answer();
+      }
+      MessageToMiner::Stats(stats) => {
+        self.stats.borrow_mut().record(stats);
+      }
+    };
+  }
+
+  /// Replace the stopped worker threads, resuming their challenge
+  fn restart_stopped(&self) {
+    let challenge: Option<Challenge> = self.worker.borrow().challenge();
+    self.restart(challenge);
   }
 
   pub fn mine(
@@ -45,17 +121,31 @@ impl Miner {
     peer_id: PeerId,
     service: &mut PowService,
     config: &PowConfig,
-  ) -> Result<(), Error> {
-    let block: Block = service.get_block(&block_id)?;
-    let header = BlockHeader::borrowed(&block).expect("Chain head Header");
-
-    let timestamp: f64 = utc_seconds_f64();
+  ) -> Result<(), PowError> {
+    // the chain head was accepted, it may still predate the switch to PoW
+    let header: BlockHeader = service.get_header(&block_id)?;
+
+    let mut timestamp: f64 = self.clock.now();
+    // Never mine a timestamp that peers would reject for being behind the median time past, which
+    // is taken from the chain head down
+    let median_time_span: u64 = *config.median_time_span.at(header.block_num + 1);
+    if median_time_span > 0 {
+      if let Some(median) = median_time_past(&header.block_id, service, median_time_span)? {
+        if timestamp <= median {
+          timestamp = median + 1.0;
+        }
+      }
+    }
     let difficulty = if header.consensus.is_pow() {
       header.consensus.expected_difficulty
     } else {
-      config.initial_difficulty
+      initial_difficulty(config, header.block_num + 1)
     };
-    let next_difficulty: u32 = get_difficulty(&header, timestamp, service, config);
+    // a challenge with a difficulty peers would reject is worse than none
+    let next_difficulty: u32 = get_difficulty(&header, timestamp, service, config)?;
+    let algorithm: PowAlgorithmKind = *config.algorithm.at(header.block_num + 1);
+    let format: ConsensusFormat = *config.consensus_format.at(header.block_num + 1);
+    let mode: DifficultyMode = *config.difficulty_mode.at(header.block_num + 1);
 
     let challenge: Challenge = Challenge {
       difficulty,
@@ -63,23 +153,59 @@ impl Miner {
       block_id,
       peer_id,
       next_difficulty,
+      algorithm,
+      format,
+      mode,
     };
 
-    self.worker.send(challenge);
+    METRICS.difficulty.set(difficulty as u64);
+    METRICS.next_difficulty.set(next_difficulty as u64);
+    self.dispatch(challenge);
 
     Ok(())
   }
 
+  /// Hand `challenge` to the worker threads, restarting them if they stopped
+  fn dispatch(&self, challenge: Challenge) {
+    if self.worker.borrow().send(challenge.clone()).is_err() {
+      self.restart(Some(challenge));
+    }
+  }
+
+  /// Replace the worker threads, which stopped, and resume mining `challenge` if any
+  fn restart(&self, challenge: Option<Challenge>) {
+    let threads: usize = self.worker.borrow().threads();
+    warn!("Mining threads stopped, restarting {} of them", threads);
+
+    let worker: Worker = Worker::new(threads);
+    if let Some(challenge) = challenge {
+      if worker.send(challenge).is_err() {
+        error!("Restarted mining threads stopped at once");
+      }
+    }
+    self.clear_answer();
+    // the stopped pool joins its threads as it drops
+    drop(self.worker.replace(worker));
+  }
+
   fn clear_answer(&self) {
     *self.answer.borrow_mut() = None;
   }
 }
 
+impl Default for Miner {
+  fn default() -> Self {
+    Self::new(1, SystemClock::shared())
+  }
+}
+
 impl Debug for Miner {
   fn fmt(&self, f: &mut Formatter) -> FmtResult {
     f.debug_struct("Miner")
       .field("worker", &self.worker)
       .field("answer", &self.answer)
+      .field("stats", &self.stats)
+      .field("clock", &self.clock)
       .finish()
   }
 }
@@ -87,9 +213,11 @@ impl Debug for Miner {
 #[cfg(test)]
 mod test {
   use super::*;
-  use crate::block::{BlockConsensus, SerializedBlockConsensus};
-  use crate::primitives::H256;
+  use crate::block::{Block, BlockConsensus, ConsensusError, SerializedBlockConsensus};
+  use crate::primitives::{CCDifficulty, H256};
+  use crate::utils::{utc_seconds_f64, MockClock};
   use crate::work::{get_hasher, is_valid_proof_of_work, mkhash};
+  use sawtooth_sdk::consensus::engine::Error;
 
   #[test]
   fn default_miner() {
@@ -102,8 +230,8 @@ mod test {
   /// It shouldn't yield an answer twice.
   fn worker_wont_stop() -> Result<(), Error> {
     let miner = Miner::default();
-    let block_id = b"1111111111111111".iter().copied().collect();
-    let peer_id = b"1111111111111111".iter().copied().collect();
+    let block_id = b"1111111111111111".to_vec();
+    let peer_id = b"1111111111111111".to_vec();
 
     let timestamp: f64 = utc_seconds_f64();
 
@@ -113,9 +241,12 @@ mod test {
       timestamp,
       block_id,
       peer_id,
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
     };
 
-    miner.worker.send(challenge.clone());
+    miner.worker.borrow().send(challenge.clone()).unwrap();
     let mut consensus: SerializedBlockConsensus;
     loop {
       if let Some(new) = miner.try_create_consensus() {
@@ -131,7 +262,7 @@ mod test {
       };
     }
     //Restart challenge
-    miner.worker.send(challenge.clone());
+    miner.worker.borrow().send(challenge.clone()).unwrap();
     loop {
       if let Some(new) = miner.try_create_consensus() {
         assert_ne!(consensus, new);
@@ -152,8 +283,8 @@ mod test {
   ///The worker should return a challenge with the next's block expected difficulty.
   fn worker_returns_challenge_with_expected_difficulty() -> Result<(), String> {
     let miner = Miner::default();
-    let block_id: Vec<u8> = b"1111111111111111".iter().copied().collect();
-    let peer_id: Vec<u8> = b"1111111111111111".iter().copied().collect();
+    let block_id: Vec<u8> = b"1111111111111111".to_vec();
+    let peer_id: Vec<u8> = b"1111111111111111".to_vec();
 
     let timestamp: f64 = utc_seconds_f64();
 
@@ -164,10 +295,13 @@ mod test {
       timestamp,
       block_id: block_id.clone(),
       peer_id: peer_id.clone(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
     };
 
-    miner.worker.send(challenge.clone());
-    while None == miner.try_create_consensus() {}
+    miner.worker.borrow().send(challenge.clone()).unwrap();
+    while miner.try_create_consensus().is_none() {}
 
     let consensus: BlockConsensus;
     //the second answer if any is guaranteed to be higher than the expected difficulty but the expected diff should stay the same
@@ -189,13 +323,15 @@ mod test {
     assert!(realized_difficulty > challenge.difficulty);
 
     //a new challenge should reset the current difficulty in the worker
-    miner.worker.send(challenge.clone());
+    miner.worker.borrow().send(challenge.clone()).unwrap();
 
-    while Some(MessageToMiner::Started) != miner.worker.try_recv() {}
+    while Ok(Some(MessageToMiner::Started)) != miner.worker.borrow().try_recv() {}
 
     std::thread::sleep(std::time::Duration::from_millis(250));
 
-    if let MessageToMiner::Solved(ans) = miner.worker.try_recv().unwrap() {
+    let message = std::iter::from_fn(|| miner.worker.borrow().try_recv().unwrap())
+      .find(|message| !matches!(message, MessageToMiner::Stats(_)));
+    if let Some(MessageToMiner::Solved(ans)) = message {
       let hash: H256 = mkhash(&mut get_hasher(), &block_id, &peer_id, ans.nonce);
       let (_, new_realized_difficulty) = is_valid_proof_of_work(&hash, ans.challenge.difficulty);
       assert!(realized_difficulty > new_realized_difficulty);
@@ -214,11 +350,11 @@ mod test {
     let config = PowConfig::new();
     let mut service = PowService::new(Box::new(MockService {}));
     let mut miner = Miner::default();
-    let block_id = b"1111111111111111".iter().copied().collect();
-    let peer_id = b"2222222222222222".iter().copied().collect();
+    let block_id = b"1111111111111111".to_vec();
+    let peer_id = b"2222222222222222".to_vec();
     miner.mine(block_id, peer_id, &mut service, &config)?;
     loop {
-      if let Some(MessageToMiner::Solved(ans)) = miner.worker.try_recv() {
+      if let Ok(Some(MessageToMiner::Solved(ans))) = miner.worker.borrow().try_recv() {
         // first block's difficulty should be pulled from config
         assert_eq!(ans.challenge.difficulty, config.initial_difficulty);
         break;
@@ -227,4 +363,182 @@ mod test {
 
     Ok(())
   }
+
+  #[test]
+  fn challenges_are_stamped_by_the_miner_clock() -> Result<(), Error> {
+    let config = PowConfig::new();
+    let mut service = PowService::new(Box::new(MockService {}));
+    let clock = MockClock::new(1_600_000_000.0);
+    let mut miner = Miner::new(1, clock.shared());
+    let block_id = b"1111111111111111".to_vec();
+    let peer_id = b"2222222222222222".to_vec();
+    miner.mine(block_id, peer_id, &mut service, &config)?;
+    loop {
+      if let Ok(Some(MessageToMiner::Solved(ans))) = miner.worker.borrow().try_recv() {
+        assert_eq!(ans.challenge.timestamp, 1_600_000_000.0);
+        break;
+      }
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn waiting_for_an_answer_blocks_until_one_is_found_or_the_timeout() {
+    let miner = Miner::default();
+    let challenge = |difficulty| Challenge {
+      difficulty,
+      next_difficulty: 0,
+      timestamp: utc_seconds_f64(),
+      block_id: b"1111111111111111".to_vec(),
+      peer_id: b"2222222222222222".to_vec(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
+    };
+
+    miner.dispatch(challenge(1));
+    assert!(miner.wait_for_answer(Duration::from_secs(30)));
+    // the answer is left to be taken
+    assert!(miner.wait_for_answer(Duration::ZERO));
+    assert!(miner.try_create_consensus().is_some());
+
+    miner.dispatch(challenge(255));
+    assert!(!miner.wait_for_answer(Duration::from_millis(100)));
+    assert!(miner.try_create_consensus().is_none());
+  }
+
+  #[test]
+  fn worker_threads_report_their_hash_rate() {
+    let miner = Miner::new(2, SystemClock::shared());
+    let challenge: Challenge = Challenge {
+      difficulty: 255,
+      next_difficulty: 0,
+      timestamp: utc_seconds_f64(),
+      block_id: b"1111111111111111".to_vec(),
+      peer_id: b"2222222222222222".to_vec(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
+    };
+    assert_eq!(miner.stats(), MiningStats::new(2));
+
+    miner.worker.borrow().send(challenge).unwrap();
+    let stats: MiningStats = loop {
+      let stats = miner.stats();
+      if stats.hash_rate() > 0.0 {
+        break stats;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(50));
+    };
+
+    assert!(stats.hashes() > 0);
+    assert!(stats.best_score().is_some());
+  }
+
+  #[test]
+  fn a_weaker_answer_from_another_thread_doesnt_replace_a_stronger_one() {
+    let miner = Miner::default();
+    let challenge = |difficulty| Challenge {
+      difficulty,
+      next_difficulty: 0,
+      timestamp: 1000.0,
+      block_id: b"1111111111111111".to_vec(),
+      peer_id: b"2222222222222222".to_vec(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
+    };
+    let answer = |challenge: Challenge, score: fn(CCDifficulty) -> bool| {
+      let mut algorithm = challenge.algorithm.build();
+      let nonce = (0..)
+        .find(|nonce| {
+          let hash = algorithm.hash(&challenge.block_id, &challenge.peer_id, *nonce);
+          score(algorithm.score(&hash))
+        })
+        .unwrap();
+      Answer { challenge, nonce }
+    };
+    let strong = answer(challenge(1), |score| score >= 8);
+    let weak = answer(challenge(1), |score| score == 1);
+    let other = answer(challenge(2), |score| score == 2);
+
+    miner.receive(MessageToMiner::Solved(strong.clone()));
+    miner.receive(MessageToMiner::Solved(weak.clone()));
+    assert_eq!(miner.answer.borrow().as_ref(), Some(&strong));
+
+    miner.clear_answer();
+    miner.receive(MessageToMiner::Solved(weak.clone()));
+    miner.receive(MessageToMiner::Solved(strong.clone()));
+    assert_eq!(miner.answer.borrow().as_ref(), Some(&strong));
+
+    // an answer to another challenge isn't comparable, the latest one wins
+    miner.receive(MessageToMiner::Solved(other.clone()));
+    assert_eq!(miner.answer.borrow().as_ref(), Some(&other));
+  }
+
+  #[test]
+  fn stopped_worker_threads_are_restarted() {
+    let miner = Miner::default();
+    let challenge: Challenge = Challenge {
+      difficulty: 1,
+      next_difficulty: 1,
+      timestamp: utc_seconds_f64(),
+      block_id: b"1111111111111111".to_vec(),
+      peer_id: b"2222222222222222".to_vec(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
+    };
+
+    miner.worker.borrow().shutdown();
+    // the pool disconnects once its last thread is gone
+    while miner.worker.borrow().try_recv().is_ok() {}
+
+    miner.dispatch(challenge);
+    while miner.try_create_consensus().is_none() {}
+  }
+
+  #[test]
+  fn a_chain_head_before_pow_is_mined_on_but_a_garbled_one_is_refused() -> Result<(), Error> {
+    use crate::simulator::{BlockStore, SimulatedService};
+
+    let config = PowConfig::new();
+    let store = BlockStore::new();
+    for (id, payload) in [(1, &b"Devmode"[..]), (2, &b"PoW:x"[..])] {
+      store.insert(Block {
+        block_id: vec![id],
+        block_num: 5,
+        payload: payload.to_vec(),
+        ..Block::default()
+      });
+    }
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    let mut miner = Miner::default();
+
+    miner.mine(vec![1], b"2222".to_vec(), &mut service, &config)?;
+    loop {
+      if let Ok(Some(MessageToMiner::Solved(ans))) = miner.worker.borrow().try_recv() {
+        assert_eq!(ans.challenge.difficulty, config.initial_difficulty);
+        break;
+      }
+    }
+
+    let error = miner
+      .mine(vec![2], b"2222".to_vec(), &mut service, &config)
+      .unwrap_err();
+    assert!(
+      matches!(
+        error.consensus(),
+        Some(ConsensusError::BadField {
+          field: "difficulty",
+          ..
+        })
+      ),
+      "{}",
+      error
+    );
+
+    Ok(())
+  }
 }
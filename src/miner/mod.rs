@@ -2,10 +2,12 @@ mod answer;
 mod challenge;
 mod channel;
 mod miner;
+mod stats;
 mod worker;
 
 pub use self::answer::*;
 pub use self::challenge::*;
 pub use self::channel::*;
 pub use self::miner::*;
+pub use self::stats::*;
 pub use self::worker::*;
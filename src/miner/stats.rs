@@ -0,0 +1,96 @@
+use crate::primitives::CCDifficulty;
+use crate::Duration;
+
+/// What a mining thread reports about the hashes it computed since its previous report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorkerStats {
+  /// Index of the reporting thread in the pool
+  pub thread: usize,
+  pub hashes: u64,
+  pub elapsed: Duration,
+  /// Best score computed on the thread's current challenge
+  pub best_score: Option<CCDifficulty>,
+}
+
+impl WorkerStats {
+  pub fn hash_rate(&self) -> f64 {
+    let seconds: f64 = self.elapsed.as_secs_f64();
+    if seconds > 0.0 {
+      self.hashes as f64 / seconds
+    } else {
+      0.0
+    }
+  }
+}
+
+/// The pool's statistics, as of the latest report of every thread
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MiningStats {
+  threads: Vec<Option<WorkerStats>>,
+  hashes: u64,
+}
+
+impl MiningStats {
+  pub fn new(threads: usize) -> Self {
+    Self {
+      threads: vec![None; threads],
+      hashes: 0,
+    }
+  }
+
+  pub fn record(&mut self, stats: WorkerStats) {
+    if stats.thread >= self.threads.len() {
+      self.threads.resize(stats.thread + 1, None);
+    }
+    self.hashes += stats.hashes;
+    self.threads[stats.thread] = Some(stats);
+  }
+
+  /// Hashes computed by the pool since it started, up to the latest reports
+  pub fn hashes(&self) -> u64 {
+    self.hashes
+  }
+
+  /// Hashes per second, summed over the latest report of every thread
+  pub fn hash_rate(&self) -> f64 {
+    self.reports().map(WorkerStats::hash_rate).sum()
+  }
+
+  /// Best score any thread computed on its current challenge
+  pub fn best_score(&self) -> Option<CCDifficulty> {
+    self.reports().filter_map(|stats| stats.best_score).max()
+  }
+
+  fn reports(&self) -> impl Iterator<Item = &WorkerStats> {
+    self.threads.iter().flatten()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn report(thread: usize, hashes: u64, millis: u64, best_score: u32) -> WorkerStats {
+    WorkerStats {
+      thread,
+      hashes,
+      elapsed: Duration::from_millis(millis),
+      best_score: Some(best_score),
+    }
+  }
+
+  #[test]
+  fn rates_add_up_over_the_latest_report_of_each_thread() {
+    let mut stats = MiningStats::new(2);
+    assert_eq!(stats.hash_rate(), 0.0);
+    assert_eq!(stats.best_score(), None);
+
+    stats.record(report(0, 1000, 1000, 7));
+    stats.record(report(1, 500, 250, 9));
+    stats.record(report(0, 3000, 1000, 5));
+
+    assert_eq!(stats.hashes(), 4500);
+    assert_eq!(stats.hash_rate(), 5000.0);
+    assert_eq!(stats.best_score(), Some(9));
+  }
+}
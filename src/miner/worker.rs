@@ -1,17 +1,26 @@
 use rand::rngs::ThreadRng;
 use rand::thread_rng;
 use rand::Rng;
+use std::cell::{Cell, RefCell};
 use std::thread::Builder;
 use std::thread::JoinHandle;
+use std::time::Instant;
 
-use crate::miner::{Answer, Challenge, Channel};
-use crate::primitives::{CCNonce, H256};
+use crate::metrics::METRICS;
+use crate::miner::{Answer, Challenge, Channel, Disconnected, WorkerStats};
+use crate::primitives::{CCDifficulty, CCNonce, H256};
 use crate::utils::to_hex;
-use crate::work::{get_hasher, is_valid_proof_of_work, mkhash_into, Hasher};
+use crate::work::PowAlgorithm;
+use crate::Duration;
 
 #[cfg(test)]
 use println as debug;
 
+/// Hashes a thread computes before adding them to `METRICS`
+const HASH_BATCH: u64 = 1 << 12;
+/// A thread reports its `WorkerStats` at the end of the first batch past this interval
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
 type Parent = Channel<MessageToWorker, MessageToMiner>;
 type Child = Channel<MessageToMiner, MessageToWorker>;
 
@@ -25,118 +34,327 @@ pub enum MessageToWorker {
 pub enum MessageToMiner {
   Solved(Answer),
   Started,
+  Stats(WorkerStats),
 }
 
 #[derive(Debug)]
 pub struct Worker {
-  channel: Channel<MessageToWorker, MessageToMiner>,
-  handle: Option<JoinHandle<()>>,
+  channels: Vec<Parent>,
+  handles: Vec<JoinHandle<()>>,
+  challenge: RefCell<Option<Challenge>>,
+  started: Cell<bool>,
 }
 
 impl Default for Worker {
   fn default() -> Self {
-    Self::new()
+    Self::new(1)
   }
 }
 
 impl Worker {
-  fn new() -> Self {
-    let (chan1, chan2): (Parent, Child) = Channel::duplex();
+  /// Spawns a pool of `threads` mining threads, each one searching its own slice of the nonce space.
+  pub fn new(threads: usize) -> Self {
+    let threads: usize = threads.max(1);
+    let (parents, children): (Vec<Parent>, Vec<Child>) = Channel::fan_out(threads);
 
-    let handle: JoinHandle<()> = Builder::new()
-      .name("Miner".to_string())
-      .spawn(Self::task(chan2))
-      .expect("Worker thread failed to spawn");
+    let handles: Vec<JoinHandle<()>> = children
+      .into_iter()
+      .enumerate()
+      .map(|(index, child)| {
+        Builder::new()
+          .name(format!("Miner-{}", index))
+          .spawn(Self::task(child, index, NonceRange::new(index, threads)))
+          .expect("Worker thread failed to spawn")
+      })
+      .collect();
 
     Self {
-      channel: chan1,
-      handle: Some(handle),
+      channels: parents,
+      handles,
+      challenge: RefCell::new(None),
+      started: Cell::new(false),
+    }
+  }
+
+  pub fn threads(&self) -> usize {
+    self.channels.len()
+  }
+
+  /// Hands `challenge` to every thread, fails if any of them stopped
+  pub fn send(&self, challenge: Challenge) -> Result<(), Disconnected> {
+    self.challenge.borrow_mut().replace(challenge.clone());
+    self.started.set(false);
+
+    let mut result: Result<(), Disconnected> = Ok(());
+    for channel in self.channels.iter() {
+      result = result.and(channel.send(MessageToWorker::Challenge(challenge.clone())));
+    }
+    result
+  }
+
+  /// Ask every thread to stop
+  pub fn shutdown(&self) {
+    for channel in self.channels.iter() {
+      // a thread that already stopped has nothing to shut down
+      let _ = channel.send(MessageToWorker::Shutdown);
     }
   }
 
-  pub fn send(&self, challenge: Challenge) {
-    self.channel.send(MessageToWorker::Challenge(challenge));
+  /// The challenge the pool was last handed
+  pub fn challenge(&self) -> Option<Challenge> {
+    self.challenge.borrow().clone()
+  }
+
+  /// Drains the messages sent by the pool.
+  ///
+  /// Every thread acknowledges a new challenge, only the first acknowledgement is forwarded.
+  /// Answers to a stale challenge (from threads that haven't picked up the update yet) are dropped.
+  /// Statistics are always forwarded. Fails once every thread stopped.
+  pub fn try_recv(&self) -> Result<Option<MessageToMiner>, Disconnected> {
+    while let Some(message) = self.channels[0].try_recv()? {
+      if let Some(message) = self.forward(message) {
+        return Ok(Some(message));
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Like `try_recv`, but waits for a message to forward until `deadline`
+  pub fn recv_deadline(&self, deadline: Instant) -> Result<Option<MessageToMiner>, Disconnected> {
+    while let Some(message) = self.channels[0].recv_deadline(deadline)? {
+      if let Some(message) = self.forward(message) {
+        return Ok(Some(message));
+      }
+    }
+
+    Ok(None)
   }
 
-  pub fn try_recv(&self) -> Option<MessageToMiner> {
-    self.channel.try_recv()
+  /// `message` unless it is a repeated acknowledgement or a stale answer
+  fn forward(&self, message: MessageToMiner) -> Option<MessageToMiner> {
+    match message {
+      MessageToMiner::Started if self.started.replace(true) => None,
+      MessageToMiner::Solved(answer)
+        if self.challenge.borrow().as_ref() != Some(&answer.challenge) =>
+      {
+        None
+      }
+      message => Some(message),
+    }
   }
 
   fn start(
     channel: &Channel<MessageToMiner, MessageToWorker>,
     challenge: Challenge,
+    range: &NonceRange,
     rng: &mut ThreadRng,
-  ) -> (Challenge, CCNonce) {
-    let _ = channel.send(MessageToMiner::Started);
-    (challenge, rng.gen_range(0..u64::MAX))
+  ) -> Result<(Challenge, CCNonce), Disconnected> {
+    channel.send(MessageToMiner::Started)?;
+    Ok((challenge, range.random(rng)))
   }
 
-  /// Mine until shutdown
+  /// Mine until shutdown, or until the miner is gone
   ///
-  fn task(channel: Channel<MessageToMiner, MessageToWorker>) -> impl Fn() {
+  fn task(
+    channel: Channel<MessageToMiner, MessageToWorker>,
+    thread: usize,
+    range: NonceRange,
+  ) -> impl Fn() {
     move || {
-      let mut hasher: Hasher = get_hasher();
-      let mut output: H256 = H256::new();
-      let mut rng: ThreadRng = thread_rng();
-
-      debug!("Waiting for challenge");
-      let (mut challenge, mut nonce) = match channel.recv() {
-        MessageToWorker::Challenge(challenge) => Worker::start(&channel, challenge, &mut rng),
-        MessageToWorker::Shutdown => return,
+      if Self::run(&channel, thread, &range).is_err() {
+        debug!("Miner disconnected, stopping");
+      }
+    }
+  }
+
+  fn run(
+    channel: &Channel<MessageToMiner, MessageToWorker>,
+    thread: usize,
+    range: &NonceRange,
+  ) -> Result<(), Disconnected> {
+    let mut output: H256 = H256::new();
+    let mut rng: ThreadRng = thread_rng();
+
+    debug!("Waiting for challenge");
+    let (mut challenge, mut nonce) = match channel.recv()? {
+      MessageToWorker::Challenge(challenge) => Worker::start(channel, challenge, range, &mut rng)?,
+      MessageToWorker::Shutdown => return Ok(()),
+    };
+    debug!("Received challenge: {:?}", challenge);
+    let mut algorithm: Box<dyn PowAlgorithm> = challenge.algorithm.build();
+    // score of the last answer sent for this challenge
+    let mut best_score: Option<CCDifficulty> = None;
+    // best score computed for this challenge, answer or not
+    let mut best_seen: Option<CCDifficulty> = None;
+    let mut hashes: u64 = 0;
+    // hashes since the last report
+    let mut reported: u64 = 0;
+    let mut report_start: Instant = Instant::now();
+
+    loop {
+      algorithm.hash_into(&mut output, &challenge.block_id, &challenge.peer_id, nonce);
+      hashes += 1;
+      if hashes == HASH_BATCH {
+        METRICS.hashes.add(hashes);
+        reported += hashes;
+        hashes = 0;
+
+        let elapsed: Duration = report_start.elapsed();
+        if elapsed >= STATS_INTERVAL {
+          channel.send(MessageToMiner::Stats(WorkerStats {
+            thread,
+            hashes: reported,
+            elapsed,
+            best_score: best_seen,
+          }))?;
+          reported = 0;
+          report_start = Instant::now();
+        }
+      }
+      //if solved send the answer, then only send strictly better ones
+      let score: CCDifficulty = algorithm.score(&output);
+      best_seen = best_seen.max(Some(score));
+      let improved: bool = match best_score {
+        Some(best_score) => score > best_score,
+        None => challenge
+          .mode
+          .meets(&*algorithm, &output, challenge.difficulty),
       };
-      debug!("Received challenge: {:?}", challenge);
-      let mut current_difficulty = challenge.difficulty.saturating_sub(1);
-
-      loop {
-        mkhash_into(
-          &mut hasher,
-          &mut output,
-          &challenge.block_id,
-          &challenge.peer_id,
+      if improved {
+        debug!("Found nonce: {:?} -> {}", nonce, to_hex(&output));
+        channel.send(MessageToMiner::Solved(Answer {
+          challenge: challenge.clone(),
           nonce,
-        );
-        //if solved send the answer, increase diff and continue
-        let (_, realized_diffulty) = is_valid_proof_of_work(&output, current_difficulty);
-        if realized_diffulty > current_difficulty {
-          debug!("Found nonce: {:?} -> {}", nonce, to_hex(&output));
-          channel.send(MessageToMiner::Solved(Answer {
-            challenge: challenge.clone(),
-            nonce,
-          }));
-          current_difficulty = realized_diffulty;
-        }
+        }))?;
+        best_score = Some(score);
+      }
 
-        //if updated, send update confirmation.
-        match channel.try_recv() {
-          Some(MessageToWorker::Challenge(update)) => {
-            debug!("Received update: {:?}", update);
-            let challenge_nonce = Worker::start(&channel, update, &mut rng);
-            challenge = challenge_nonce.0;
-            nonce = challenge_nonce.1;
-            current_difficulty = challenge.difficulty.saturating_sub(1);
-          }
-          Some(MessageToWorker::Shutdown) => {
-            return;
-          }
-          None => {
-            nonce = nonce.wrapping_add(1);
-          }
+      //if updated, send update confirmation.
+      match channel.try_recv()? {
+        Some(MessageToWorker::Challenge(update)) => {
+          debug!("Received update: {:?}", update);
+          let challenge_nonce = Worker::start(channel, update, range, &mut rng)?;
+          challenge = challenge_nonce.0;
+          nonce = challenge_nonce.1;
+          algorithm = challenge.algorithm.build();
+          best_score = None;
+          best_seen = None;
+        }
+        Some(MessageToWorker::Shutdown) => {
+          METRICS.hashes.add(hashes);
+          return Ok(());
+        }
+        None => {
+          nonce = range.next(nonce);
         }
       }
     }
   }
 }
 
+/// The slice of the nonce space searched by a single mining thread.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NonceRange {
+  start: CCNonce,
+  end: CCNonce,
+}
+
+impl NonceRange {
+  fn new(index: usize, count: usize) -> Self {
+    let span: CCNonce = CCNonce::MAX / count as CCNonce;
+    let start: CCNonce = span * index as CCNonce;
+    let end: CCNonce = if index + 1 == count {
+      CCNonce::MAX
+    } else {
+      start + span - 1
+    };
+
+    Self { start, end }
+  }
+
+  fn random(&self, rng: &mut ThreadRng) -> CCNonce {
+    rng.gen_range(self.start..=self.end)
+  }
+
+  fn next(&self, nonce: CCNonce) -> CCNonce {
+    if nonce >= self.end {
+      self.start
+    } else {
+      nonce + 1
+    }
+  }
+}
+
 impl Drop for Worker {
   fn drop(&mut self) {
-    self.channel.send(MessageToWorker::Shutdown);
+    self.shutdown();
 
-    if let Some(handle) = self.handle.take() {
+    for handle in self.handles.drain(..) {
       if let Err(error) = handle.join() {
         error!("Handle failed to join: {:?}", error);
       }
-    } else {
-      error!("Handle is `None`");
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::ConsensusFormat;
+  use crate::work::{DifficultyMode, PowAlgorithmKind};
+
+  #[test]
+  fn nonce_ranges_cover_the_nonce_space() {
+    let ranges: Vec<NonceRange> = (0..3).map(|index| NonceRange::new(index, 3)).collect();
+
+    assert_eq!(ranges[0].start, 0);
+    assert_eq!(ranges[2].end, CCNonce::MAX);
+
+    for pair in ranges.windows(2) {
+      assert_eq!(pair[0].end + 1, pair[1].start);
+    }
+  }
+
+  #[test]
+  fn nonce_range_wraps_within_itself() {
+    let range = NonceRange::new(1, 4);
+
+    assert_eq!(range.next(range.start), range.start + 1);
+    assert_eq!(range.next(range.end), range.start);
+  }
+
+  #[test]
+  fn every_thread_acknowledges_through_a_single_started() {
+    let worker = Worker::new(4);
+    let challenge: Challenge = Challenge {
+      difficulty: 1,
+      next_difficulty: 1,
+      timestamp: 0.0,
+      block_id: b"1111111111111111".to_vec(),
+      peer_id: b"2222222222222222".to_vec(),
+      algorithm: PowAlgorithmKind::default(),
+      format: ConsensusFormat::default(),
+      mode: DifficultyMode::default(),
+    };
+
+    assert_eq!(worker.threads(), 4);
+    worker.send(challenge.clone()).unwrap();
+
+    let mut started: usize = 0;
+    let mut solved: usize = 0;
+
+    while solved < 8 {
+      match worker.try_recv().unwrap() {
+        Some(MessageToMiner::Started) => started += 1,
+        Some(MessageToMiner::Stats(_)) | None => {}
+        Some(MessageToMiner::Solved(answer)) => {
+          assert_eq!(answer.challenge, challenge);
+          solved += 1;
+        }
+      }
+    }
+
+    assert_eq!(started, 1);
+  }
+}
@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::block::{Block, BlockId};
+
+/// Heights remembered by default, well past a tuning period or a fork worth resolving
+pub const ANCESTOR_INDEX_CAPACITY: usize = 4096;
+
+/// Where the blocks seen so far sit in the chain, to guess the ids of the ancestors of a block
+/// before fetching it, so that they can be asked for in the same `get_blocks` call.
+///
+/// The validator can't look blocks up by height, so the index only knows the heights of the
+/// blocks it was shown and of their predecessors. A guess may be wrong on a fork the index hasn't
+/// seen yet; it costs a few extra blocks in a batch, never a wrong answer, as callers still follow
+/// `previous_id`.
+#[derive(Clone, Debug)]
+pub struct AncestorIndex {
+  capacity: usize,
+  /// The block last seen at each of the highest heights, with its predecessor
+  by_height: BTreeMap<u64, (BlockId, BlockId)>,
+  /// The height of every id in `by_height`, blocks and predecessors
+  heights: HashMap<BlockId, u64>,
+}
+
+impl Default for AncestorIndex {
+  fn default() -> Self {
+    Self::new(ANCESTOR_INDEX_CAPACITY)
+  }
+}
+
+impl AncestorIndex {
+  /// An index of at most `capacity` heights, the lowest are forgotten first
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      by_height: BTreeMap::new(),
+      heights: HashMap::new(),
+    }
+  }
+
+  /// Remember where `block` sits, it replaces whichever block was seen at its height before
+  pub fn record(&mut self, block: &Block) {
+    if self.capacity == 0 {
+      return;
+    }
+
+    self.heights.insert(block.block_id.clone(), block.block_num);
+    if block.block_num > 0 {
+      self
+        .heights
+        .insert(block.previous_id.clone(), block.block_num - 1);
+    }
+    self.by_height.insert(
+      block.block_num,
+      (block.block_id.clone(), block.previous_id.clone()),
+    );
+
+    while self.by_height.len() > self.capacity {
+      self.by_height.pop_first();
+    }
+    // ids of replaced or forgotten blocks linger until there are as many of them as live ones
+    if self.heights.len() > 4 * self.capacity {
+      self.heights = self
+        .by_height
+        .iter()
+        .flat_map(|(&height, (block_id, previous_id))| {
+          [
+            (block_id.clone(), height),
+            (previous_id.clone(), height.saturating_sub(1)),
+          ]
+        })
+        .collect();
+    }
+  }
+
+  pub fn height_of(&self, block_id: &[u8]) -> Option<u64> {
+    self.heights.get(block_id).copied()
+  }
+
+  /// The ids of up to `count` ancestors of `block_id`, the nearest first.
+  ///
+  /// Predecessors are exact as long as the walk stays on blocks the index has seen, past that the
+  /// blocks last seen at the heights below are guessed.
+  pub fn ancestors(&self, block_id: &[u8], count: usize) -> Vec<BlockId> {
+    let mut ancestors: Vec<BlockId> = Vec::new();
+    let mut height: u64 = match self.height_of(block_id) {
+      Some(height) => height,
+      None => return ancestors,
+    };
+    let mut cursor: &[u8] = block_id;
+
+    while ancestors.len() < count && height > 0 {
+      let predecessor: &BlockId = match self.by_height.get(&height) {
+        Some((id, previous_id)) if id.as_slice() == cursor => previous_id,
+        _ => match self.by_height.get(&(height - 1)) {
+          Some((id, _)) => id,
+          None => break,
+        },
+      };
+
+      ancestors.push(predecessor.clone());
+      cursor = predecessor;
+      height -= 1;
+    }
+
+    ancestors
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn block(block_num: u64, block_id: u8, previous_id: u8) -> Block {
+    Block {
+      block_id: vec![block_id],
+      previous_id: vec![previous_id],
+      block_num,
+      ..Block::default()
+    }
+  }
+
+  #[test]
+  fn ancestors_follow_known_links_then_heights() {
+    let mut index = AncestorIndex::new(8);
+    for n in 1..=5 {
+      index.record(&block(n, n as u8, n as u8 - 1));
+    }
+    assert_eq!(index.ancestors(&[5], 3), vec![vec![4], vec![3], vec![2]]);
+    assert_eq!(index.ancestors(&[2], 10), vec![vec![1], vec![0]]);
+    assert!(index.ancestors(&[9], 3).is_empty());
+
+    // a fork block replaces 4, walking from it the links below are still known
+    index.record(&block(4, 14, 3));
+    assert_eq!(index.height_of(&[14]), Some(4));
+    assert_eq!(index.ancestors(&[14], 2), vec![vec![3], vec![2]]);
+    // 4 no longer sits at its height, its predecessor is guessed from the height below
+    assert_eq!(index.ancestors(&[5], 2), vec![vec![4], vec![3]]);
+  }
+
+  #[test]
+  fn the_lowest_heights_are_forgotten_first() {
+    let mut index = AncestorIndex::new(2);
+    for n in 1..=100 {
+      index.record(&block(n, n as u8, n as u8 - 1));
+    }
+    assert_eq!(index.by_height.len(), 2);
+    assert!(index.heights.len() <= 8);
+    assert_eq!(index.ancestors(&[100], 5), vec![vec![99], vec![98]]);
+  }
+}
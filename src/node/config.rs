@@ -1,16 +1,26 @@
-use sawtooth_sdk::consensus::engine::Error;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
-use crate::block::BlockId;
-use crate::node::PowService;
+use crate::block::{BlockId, ConsensusFormat};
+use crate::error::PowError;
+use crate::node::{ForkChoice, PowService, Schedule, TieBreak};
+use crate::primitives::CCTimestamp;
+use crate::work::{DifficultyAlgorithmKind, DifficultyMode, PowAlgorithmKind};
 use crate::Duration;
 
 const INITIAL_DIFFICULTY: u32 = 22;
 const SECONDS_BETWEEN_BLOCKS: u64 = 60;
 const DIFFICULTY_ADJUSTMENT_BLOCK_COUNT: u64 = 10;
 const DIFFICULTY_TUNING_BLOCK_COUNT: u64 = 100;
-const UPDATE_RECV_TIMEOUT: Duration = Duration::from_millis(10);
+const WORKER_THREADS: usize = 1;
+const STATS_INTERVAL_SECS: u64 = 60;
+const DIFFICULTY_WINDOW: u64 = 60;
+const ASERT_HALF_LIFE: u64 = 3600;
+// Timestamp rules are off unless enabled on-chain, Bitcoin uses 11 blocks and 7200 seconds.
+const MEDIAN_TIME_SPAN: u64 = 0;
+const MAX_FUTURE_DRIFT: u64 = 0;
+const MIN_PUBLISHING_DELAY_MS: u64 = 500;
+const PUBLISHING_TARGET_PERCENT: u64 = 0;
 
 #[derive(Debug)]
 pub struct PowConfig {
@@ -18,7 +28,48 @@ pub struct PowConfig {
   pub seconds_between_blocks: u64,
   pub difficulty_adjustment_block_count: u64,
   pub difficulty_tuning_block_count: u64,
-  pub update_recv_timeout: Duration,
+  /// The difficulty retargeting algorithm, by activation height
+  pub difficulty_algorithm: Schedule<DifficultyAlgorithmKind>,
+  /// Blocks looked back at by the LWMA and ASERT retargets
+  pub difficulty_window: u64,
+  /// Seconds off schedule that halve or double the work under ASERT
+  pub asert_half_life: u64,
+  /// How difficulties are read, leading zero bits or compact targets, by activation height
+  pub difficulty_mode: Schedule<DifficultyMode>,
+  /// Whether forks are weighed by the work achieved or required, by activation height
+  pub fork_choice: Schedule<ForkChoice>,
+  /// How forks of equal work are resolved, by activation height
+  pub tie_break: Schedule<TieBreak>,
+  /// Whether fork resolution counts both chain heads, instead of walking down from their
+  /// predecessors, by activation height
+  pub count_fork_heads: Schedule<bool>,
+  /// The proof-of-work algorithm, by activation height
+  pub algorithm: Schedule<PowAlgorithmKind>,
+  /// The encoding of new consensus payloads, by activation height
+  pub consensus_format: Schedule<ConsensusFormat>,
+  /// Whether incoming payloads must be canonically encoded, by activation height
+  pub strict_consensus: Schedule<bool>,
+  /// Whether blocks must claim the very difficulty the chain calls for from their successor, by
+  /// activation height
+  pub strict_difficulty: Schedule<bool>,
+  /// A block's timestamp must exceed the median of this many PoW ancestors, 0 disables the rule,
+  /// by activation height
+  pub median_time_span: Schedule<u64>,
+  /// Seconds a block's timestamp may be ahead of local time, later blocks are checked once it is
+  /// no longer so far behind, or fail if they are `MAX_POSTPONE_DRIFTS` times further, 0 disables
+  /// the rule, by activation height
+  pub max_future_drift: Schedule<u64>,
+  /// Milliseconds to wait on a new chain head before publishing on it
+  pub min_publishing_delay_ms: u64,
+  /// Percent of `seconds_between_blocks`, counted from the chain head's timestamp, to wait before
+  /// publishing on it, 0 disables the rule
+  pub publishing_target_percent: u64,
+  /// Number of mining threads, a local setting that is never read from the chain
+  pub worker_threads: usize,
+  /// Seconds between mining summaries in the log, 0 disables them, a local setting as well
+  pub stats_interval_secs: u64,
+  /// Fields set locally through `set`, which on-chain settings don't replace
+  pub pinned: HashSet<String>,
 }
 
 impl Default for PowConfig {
@@ -28,7 +79,24 @@ impl Default for PowConfig {
       seconds_between_blocks: SECONDS_BETWEEN_BLOCKS,
       difficulty_adjustment_block_count: DIFFICULTY_ADJUSTMENT_BLOCK_COUNT,
       difficulty_tuning_block_count: DIFFICULTY_TUNING_BLOCK_COUNT,
-      update_recv_timeout: UPDATE_RECV_TIMEOUT,
+      difficulty_algorithm: Schedule::default(),
+      difficulty_window: DIFFICULTY_WINDOW,
+      asert_half_life: ASERT_HALF_LIFE,
+      difficulty_mode: Schedule::default(),
+      fork_choice: Schedule::default(),
+      tie_break: Schedule::default(),
+      count_fork_heads: Schedule::default(),
+      algorithm: Schedule::default(),
+      consensus_format: Schedule::default(),
+      strict_consensus: Schedule::default(),
+      strict_difficulty: Schedule::default(),
+      median_time_span: Schedule::new(MEDIAN_TIME_SPAN),
+      max_future_drift: Schedule::new(MAX_FUTURE_DRIFT),
+      min_publishing_delay_ms: MIN_PUBLISHING_DELAY_MS,
+      publishing_target_percent: PUBLISHING_TARGET_PERCENT,
+      worker_threads: WORKER_THREADS,
+      stats_interval_secs: STATS_INTERVAL_SECS,
+      pinned: HashSet::new(),
     }
   }
 }
@@ -38,19 +106,102 @@ impl PowConfig {
     Self::default()
   }
 
+  /// How long to wait, at `now`, before publishing on a chain head stamped `head_timestamp`
+  pub fn publishing_delay(
+    &self,
+    head_timestamp: Option<CCTimestamp>,
+    now: CCTimestamp,
+  ) -> Duration {
+    let minimum: Duration = Duration::from_millis(self.min_publishing_delay_ms);
+
+    let target: Duration = match head_timestamp {
+      Some(timestamp) if self.publishing_target_percent > 0 => {
+        let share: f64 =
+          self.seconds_between_blocks as f64 * self.publishing_target_percent as f64 / 100.0;
+        // a head from the future waits no longer than the share itself, a bogus one not at all
+        Duration::from_secs_f64((share - (now - timestamp)).max(0.0).min(share))
+      }
+      _ => Duration::ZERO,
+    };
+
+    minimum.max(target)
+  }
+
+  /// Set the field named `key` from its textual value, as read from a configuration file or the
+  /// environment. Unlike on-chain settings, unknown keys and invalid values are errors, and the
+  /// field is pinned: `load` leaves it as set.
+  pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+    macro_rules! set_fields {
+      ($($field:ident),*) => {
+        match key {
+          $(
+          stringify!($field) => {
+            self.$field = value
+              .parse()
+              .map_err(|error| format!("Invalid {} {}: {}", key, value, error))?;
+          }
+          )*
+          _ => return Err(format!("Unknown setting: {}", key)),
+        }
+      };
+    }
+
+    set_fields!(
+      seconds_between_blocks,
+      difficulty_adjustment_block_count,
+      difficulty_tuning_block_count,
+      initial_difficulty,
+      difficulty_algorithm,
+      difficulty_window,
+      asert_half_life,
+      difficulty_mode,
+      fork_choice,
+      tie_break,
+      count_fork_heads,
+      algorithm,
+      consensus_format,
+      strict_consensus,
+      strict_difficulty,
+      median_time_span,
+      max_future_drift,
+      min_publishing_delay_ms,
+      publishing_target_percent,
+      worker_threads,
+      stats_interval_secs
+    );
+
+    self.pinned.insert(key.to_owned());
+    Ok(())
+  }
+
   fn consensus_chain_settings() -> Vec<String> {
     vec![
       conf_key!("seconds_between_blocks").to_string(),
       conf_key!("difficulty_adjustment_block_count").to_string(),
       conf_key!("difficulty_tuning_block_count").to_string(),
       conf_key!("initial_difficulty").to_string(),
+      conf_key!("difficulty_algorithm").to_string(),
+      conf_key!("difficulty_window").to_string(),
+      conf_key!("asert_half_life").to_string(),
+      conf_key!("difficulty_mode").to_string(),
+      conf_key!("fork_choice").to_string(),
+      conf_key!("tie_break").to_string(),
+      conf_key!("count_fork_heads").to_string(),
+      conf_key!("algorithm").to_string(),
+      conf_key!("consensus_format").to_string(),
+      conf_key!("strict_consensus").to_string(),
+      conf_key!("strict_difficulty").to_string(),
+      conf_key!("median_time_span").to_string(),
+      conf_key!("max_future_drift").to_string(),
+      conf_key!("min_publishing_delay_ms").to_string(),
+      conf_key!("publishing_target_percent").to_string(),
     ]
   }
 
   pub fn consensus_settings_view(
     service: &mut PowService,
     block_id: BlockId,
-  ) -> Result<Self, Error> {
+  ) -> Result<Self, PowError> {
     let keys = Self::consensus_chain_settings();
     let settings: HashMap<String, String> = service.get_settings(block_id, keys)?;
     let mut out = Self::default();
@@ -73,16 +224,38 @@ impl PowConfig {
       seconds_between_blocks,
       difficulty_adjustment_block_count,
       difficulty_tuning_block_count,
-      initial_difficulty
+      initial_difficulty,
+      difficulty_algorithm,
+      difficulty_window,
+      asert_half_life,
+      difficulty_mode,
+      fork_choice,
+      tie_break,
+      count_fork_heads,
+      algorithm,
+      consensus_format,
+      strict_consensus,
+      strict_difficulty,
+      median_time_span,
+      max_future_drift,
+      min_publishing_delay_ms,
+      publishing_target_percent
     );
 
     Ok(out)
   }
 
-  pub fn load(&mut self, service: &mut PowService, block_id: BlockId) -> Result<(), Error> {
+  /// Read the on-chain settings as of `block_id`, except for the pinned fields
+  pub fn load(&mut self, service: &mut PowService, block_id: BlockId) -> Result<(), PowError> {
     let keys = Self::consensus_chain_settings();
 
-    let settings: HashMap<String, String> = service.get_settings(block_id, keys)?;
+    let mut settings: HashMap<String, String> = service.get_settings(block_id, keys)?;
+    let pinned: &HashSet<String> = &self.pinned;
+    settings.retain(|key, _| {
+      key
+        .strip_prefix(conf_key!(""))
+        .is_none_or(|field| !pinned.contains(field))
+    });
     let mut changes: bool = false;
 
     if let Some(value) = get_setting(conf_key!("seconds_between_blocks"), &settings) {
@@ -113,6 +286,111 @@ impl PowConfig {
       }
     }
 
+    if let Some(value) = get_setting(conf_key!("difficulty_algorithm"), &settings) {
+      if self.difficulty_algorithm != value {
+        self.difficulty_algorithm = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("difficulty_window"), &settings) {
+      if self.difficulty_window != value {
+        self.difficulty_window = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("asert_half_life"), &settings) {
+      if self.asert_half_life != value {
+        self.asert_half_life = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("difficulty_mode"), &settings) {
+      if self.difficulty_mode != value {
+        self.difficulty_mode = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("fork_choice"), &settings) {
+      if self.fork_choice != value {
+        self.fork_choice = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("tie_break"), &settings) {
+      if self.tie_break != value {
+        self.tie_break = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("count_fork_heads"), &settings) {
+      if self.count_fork_heads != value {
+        self.count_fork_heads = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("algorithm"), &settings) {
+      if self.algorithm != value {
+        self.algorithm = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("consensus_format"), &settings) {
+      if self.consensus_format != value {
+        self.consensus_format = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("strict_consensus"), &settings) {
+      if self.strict_consensus != value {
+        self.strict_consensus = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("strict_difficulty"), &settings) {
+      if self.strict_difficulty != value {
+        self.strict_difficulty = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("median_time_span"), &settings) {
+      if self.median_time_span != value {
+        self.median_time_span = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("max_future_drift"), &settings) {
+      if self.max_future_drift != value {
+        self.max_future_drift = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("min_publishing_delay_ms"), &settings) {
+      if self.min_publishing_delay_ms != value {
+        self.min_publishing_delay_ms = value;
+        changes = true;
+      }
+    }
+
+    if let Some(value) = get_setting(conf_key!("publishing_target_percent"), &settings) {
+      if self.publishing_target_percent != value {
+        self.publishing_target_percent = value;
+        changes = true;
+      }
+    }
+
     if changes {
       trace!("PoW Config = {:?}", self);
     }
@@ -124,3 +402,58 @@ impl PowConfig {
 fn get_setting<T: FromStr>(key: &str, settings: &HashMap<String, String>) -> Option<T> {
   settings.get(key).and_then(|string| string.parse().ok())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn publishing_waits_for_the_minimum_and_the_target_share() {
+    let mut config = PowConfig {
+      seconds_between_blocks: 60,
+      min_publishing_delay_ms: 500,
+      ..PowConfig::new()
+    };
+    let delay =
+      |config: &PowConfig, head: Option<CCTimestamp>| config.publishing_delay(head, 1000.0);
+
+    // the target share is off by default
+    assert_eq!(delay(&config, Some(1000.0)), Duration::from_millis(500));
+
+    config.publishing_target_percent = 50;
+    assert_eq!(delay(&config, Some(1000.0)), Duration::from_secs(30));
+    assert_eq!(delay(&config, Some(990.0)), Duration::from_secs(20));
+    assert_eq!(delay(&config, Some(900.0)), Duration::from_millis(500));
+    assert_eq!(delay(&config, None), Duration::from_millis(500));
+
+    // heads from the future or without a usable timestamp
+    assert_eq!(delay(&config, Some(5000.0)), Duration::from_secs(30));
+    assert_eq!(
+      delay(&config, Some(CCTimestamp::NAN)),
+      Duration::from_millis(500)
+    );
+    assert_eq!(
+      delay(&config, Some(CCTimestamp::INFINITY)),
+      Duration::from_secs(30)
+    );
+  }
+
+  #[test]
+  fn fields_are_set_by_name() {
+    let mut config = PowConfig::new();
+    config.set("worker_threads", "4").unwrap();
+    config.set("min_publishing_delay_ms", "1500").unwrap();
+    config.set("difficulty_mode", "target").unwrap();
+
+    assert_eq!(config.worker_threads, 4);
+    assert_eq!(config.min_publishing_delay_ms, 1500);
+    assert_eq!(
+      config.difficulty_mode,
+      Schedule::new(DifficultyMode::Target)
+    );
+
+    assert!(config.set("worker_threads", "many").is_err());
+    assert!(config.set("workers", "4").is_err());
+    assert_eq!(config.worker_threads, 4);
+  }
+}
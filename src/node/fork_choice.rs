@@ -0,0 +1,110 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::block::{Block, BlockConsensus};
+use crate::primitives::CCTimestamp;
+
+/// How `resolve_fork` weighs a block, as set in `sawtooth.consensus.pow.fork_choice`
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum ForkChoice {
+  /// The work proven by the block hash itself, a lucky hash counts for more
+  #[default]
+  Achieved,
+  /// The work the block was required to prove, i.e. its predecessor's `expected_difficulty`
+  Required,
+}
+
+impl ForkChoice {
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Achieved => "achieved",
+      Self::Required => "required",
+    }
+  }
+}
+
+impl FromStr for ForkChoice {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "achieved" => Ok(Self::Achieved),
+      "required" => Ok(Self::Required),
+      _ => Err(format!("Unknown fork choice: {}", string)),
+    }
+  }
+}
+
+impl Display for ForkChoice {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.write_str(self.name())
+  }
+}
+
+/// Which head `resolve_fork` keeps when both forks weigh the same, as set in `sawtooth.consensus.pow.tie_break`.
+///
+/// Every rule but `FirstSeen` picks the same head whatever order the forks arrived in.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum TieBreak {
+  /// Keep the current head, nodes that saw the forks in a different order stay split. The default,
+  /// as validators behaved before tie breaks could be scheduled.
+  #[default]
+  FirstSeen,
+  /// The head with the lowest block id
+  LowestHash,
+  /// The head with the earliest timestamp, then the lowest block id
+  EarliestTimestamp,
+}
+
+impl TieBreak {
+  /// Whether `new_head` wins the tie against `cur_head`
+  pub fn prefers(self, new_head: &Block, cur_head: &Block) -> bool {
+    let lowest_hash = || new_head.block_id < cur_head.block_id;
+
+    match self {
+      Self::FirstSeen => false,
+      Self::LowestHash => lowest_hash(),
+      Self::EarliestTimestamp => {
+        let timestamp = |block: &Block| {
+          BlockConsensus::deserialize(&block.payload)
+            .map(|consensus| consensus.timestamp)
+            .unwrap_or(CCTimestamp::INFINITY)
+        };
+
+        match timestamp(new_head).total_cmp(&timestamp(cur_head)) {
+          Ordering::Less => true,
+          Ordering::Greater => false,
+          Ordering::Equal => lowest_hash(),
+        }
+      }
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::FirstSeen => "first_seen",
+      Self::LowestHash => "lowest_hash",
+      Self::EarliestTimestamp => "earliest_timestamp",
+    }
+  }
+}
+
+impl FromStr for TieBreak {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "first_seen" => Ok(Self::FirstSeen),
+      "lowest_hash" => Ok(Self::LowestHash),
+      "earliest_timestamp" => Ok(Self::EarliestTimestamp),
+      _ => Err(format!("Unknown tie break: {}", string)),
+    }
+  }
+}
+
+impl Display for TieBreak {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.write_str(self.name())
+  }
+}
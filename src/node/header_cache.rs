@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::block::{BlockHeader, BlockId};
+
+/// Headers kept by default, enough for a tuning period and the forks resolved around it
+pub const HEADER_CACHE_CAPACITY: usize = 256;
+
+/// How often lookups in a `HeaderCache` were answered from it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  /// Headers currently cached
+  pub len: usize,
+}
+
+impl CacheStats {
+  /// Share of lookups answered from the cache, 0 before the first one
+  pub fn hit_ratio(&self) -> f64 {
+    let lookups: u64 = self.hits + self.misses;
+    if lookups == 0 {
+      0.0
+    } else {
+      self.hits as f64 / lookups as f64
+    }
+  }
+}
+
+/// A bounded cache of parsed block headers, the least recently used one is evicted first.
+///
+/// Blocks never change under their id, so entries only leave when evicted or invalidated.
+#[derive(Clone, Debug)]
+pub struct HeaderCache {
+  capacity: usize,
+  /// Each header with the tick it was last used at
+  entries: HashMap<BlockId, (BlockHeader<'static>, u64)>,
+  /// Cached ids by the tick they were last used at, the oldest first
+  recency: BTreeMap<u64, BlockId>,
+  tick: u64,
+  hits: u64,
+  misses: u64,
+}
+
+impl Default for HeaderCache {
+  fn default() -> Self {
+    Self::new(HEADER_CACHE_CAPACITY)
+  }
+}
+
+impl HeaderCache {
+  /// A cache of at most `capacity` headers, a capacity of 0 disables it
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      entries: HashMap::new(),
+      recency: BTreeMap::new(),
+      tick: 0,
+      hits: 0,
+      misses: 0,
+    }
+  }
+
+  /// The header of `block_id` if it is cached, which counts as a use of it
+  pub fn get(&mut self, block_id: &[u8]) -> Option<&BlockHeader<'static>> {
+    self.tick += 1;
+    let tick: u64 = self.tick;
+
+    match self.entries.get_mut(block_id) {
+      Some((header, used)) => {
+        self.hits += 1;
+        let id: BlockId = self
+          .recency
+          .remove(used)
+          .unwrap_or_else(|| block_id.to_owned());
+        self.recency.insert(tick, id);
+        *used = tick;
+        Some(header)
+      }
+      None => {
+        self.misses += 1;
+        None
+      }
+    }
+  }
+
+  /// Whether `block_id` is cached, without counting as a use of it
+  pub fn contains(&self, block_id: &[u8]) -> bool {
+    self.entries.contains_key(block_id)
+  }
+
+  /// Cache `header` under its block id, evicting the least recently used header when full
+  pub fn insert(&mut self, header: BlockHeader<'static>) {
+    if self.capacity == 0 {
+      return;
+    }
+    self.tick += 1;
+    let block_id: BlockId = header.block_id.clone();
+
+    if let Some((_, used)) = self.entries.remove(&block_id) {
+      self.recency.remove(&used);
+    }
+    while self.entries.len() >= self.capacity {
+      match self.recency.pop_first() {
+        Some((_, oldest)) => {
+          self.entries.remove(&oldest);
+        }
+        None => break,
+      }
+    }
+
+    self.recency.insert(self.tick, block_id.clone());
+    self.entries.insert(block_id, (header, self.tick));
+  }
+
+  /// Drop the header of `block_id`, if cached
+  pub fn invalidate(&mut self, block_id: &[u8]) {
+    if let Some((_, used)) = self.entries.remove(block_id) {
+      self.recency.remove(&used);
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits,
+      misses: self.misses,
+      len: self.entries.len(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::Block;
+
+  fn header(id: u8) -> BlockHeader<'static> {
+    BlockHeader::owned(Block {
+      block_id: vec![id],
+      ..Block::default()
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn the_least_recently_used_header_is_evicted() {
+    let mut cache = HeaderCache::new(2);
+    cache.insert(header(1));
+    cache.insert(header(2));
+    assert!(cache.get(&[1]).is_some());
+
+    cache.insert(header(3));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&[2]).is_none());
+    assert!(cache.get(&[1]).is_some());
+    assert!(cache.get(&[3]).is_some());
+
+    // re-inserting refreshes an entry rather than duplicating it
+    cache.insert(header(1));
+    cache.insert(header(4));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&[3]).is_none());
+
+    assert_eq!(
+      cache.stats(),
+      CacheStats {
+        hits: 3,
+        misses: 2,
+        len: 2
+      }
+    );
+  }
+
+  #[test]
+  fn invalidated_and_disabled_entries_are_misses() {
+    let mut cache = HeaderCache::new(4);
+    cache.insert(header(1));
+    cache.invalidate(&[1]);
+    cache.invalidate(&[2]);
+    assert!(cache.get(&[1]).is_none());
+    assert!(cache.is_empty());
+
+    let mut disabled = HeaderCache::new(0);
+    disabled.insert(header(1));
+    assert!(disabled.get(&[1]).is_none());
+    assert_eq!(disabled.stats().hit_ratio(), 0.0);
+  }
+}
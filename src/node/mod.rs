@@ -1,13 +1,21 @@
+mod ancestor_index;
 mod config;
 mod event_result;
+mod fork_choice;
 mod guard;
+mod header_cache;
 mod node;
+mod schedule;
 mod service;
 mod state;
 
+pub use self::ancestor_index::*;
 pub use self::config::*;
 pub use self::event_result::*;
+pub use self::fork_choice::*;
 pub use self::guard::*;
+pub use self::header_cache::*;
 pub use self::node::*;
+pub use self::schedule::*;
 pub use self::service::*;
 pub use self::state::*;
@@ -1,25 +1,49 @@
+#[cfg(not(feature = "test-futures"))]
+use sawtooth_sdk::consensus::engine::Error;
 pub use sawtooth_sdk::consensus::engine::PeerId;
 use sawtooth_sdk::consensus::{
-  engine::{Error, StartupState, Update},
+  engine::{StartupState, Update},
   service::Service,
 };
 
 #[cfg(not(feature = "test-futures"))]
-use std::borrow::Cow;
+use std::{borrow::Cow, cmp::Ordering};
 
 use crate::node::{PowConfig, PowService, PowState};
-use crate::{block::BlockPrinter as Printer, futures::EventResult, miner::Miner};
+use crate::primitives::CCTimestamp;
+use crate::utils::{SharedClock, SystemClock};
 #[cfg(not(feature = "test-futures"))]
 use crate::{
-  block::{Block, BlockAncestors, BlockConsensus, BlockHeader, BlockId},
-  node::Guard,
+  block::{pow_chain, AncestorError, Block, BlockAncestors, BlockId, ConsensusError},
+  metrics::{PublishOutcome, METRICS},
+  node::{ForkChoice, Guard, TieBreak},
+  primitives::H256,
   utils::to_hex,
+  work::{required_difficulty, required_work, validate_consensus, DifficultyMode},
+};
+use crate::{
+  block::{BlockConsensus, BlockHeader, BlockPrinter as Printer},
+  error::PowError,
+  futures::EventResult,
+  miner::{Miner, MiningStats},
+  work::network_hash_rate,
+  Duration,
 };
 
 use super::EventPublishResult;
 
 #[cfg(not(feature = "test-futures"))]
 pub const NULL_BLOCK_IDENTIFIER: [u8; 8] = [0; 8];
+/// Consensus checks a new block gets when its ancestors can't be fetched, before it is failed
+#[cfg(not(feature = "test-futures"))]
+pub const MAX_BLOCK_ATTEMPTS: u32 = 3;
+/// How far past the future drift limit a block may be dated and still be postponed, in multiples
+/// of `max_future_drift`, later blocks fail
+#[cfg(not(feature = "test-futures"))]
+pub const MAX_POSTPONE_DRIFTS: u64 = 4;
+/// New blocks held until our clock catches up with them, the ones beyond fail
+#[cfg(not(feature = "test-futures"))]
+pub const MAX_POSTPONED_BLOCKS: usize = 64;
 
 pub struct PowNode {
   pub config: PowConfig,
@@ -27,11 +51,12 @@ pub struct PowNode {
   state: PowState,
   #[cfg_attr(feature = "test-futures", allow(dead_code))]
   miner: Miner,
+  clock: SharedClock,
 }
 
 #[cfg(feature = "test-futures")]
 impl PowNode {
-  pub fn handle_update(&mut self, update: Update) -> Result<EventResult, Error> {
+  pub fn handle_update(&mut self, update: Update) -> Result<EventResult, PowError> {
     let (_, res) = match update {
       Update::BlockNew(..) => ("BlockNew", Ok(EventResult::Continue)),
       Update::BlockValid(..) => ("BlockValid", Ok(EventResult::Continue)),
@@ -46,14 +71,16 @@ impl PowNode {
     res
   }
 
-  pub fn try_publish(&mut self) -> Result<EventPublishResult, Error> {
+  pub fn try_publish(&mut self) -> Result<EventPublishResult, PowError> {
     Ok(EventPublishResult::Published)
   }
+
+  pub fn recheck_postponed(&mut self) {}
 }
 
 #[cfg(not(feature = "test-futures"))]
 impl PowNode {
-  pub fn handle_update(&mut self, update: Update) -> Result<EventResult, Error> {
+  pub fn handle_update(&mut self, update: Update) -> Result<EventResult, PowError> {
     match update {
       Update::BlockNew(block) => self.on_block_new(block),
       Update::BlockValid(block_id) => self.on_block_valid(block_id),
@@ -72,25 +99,39 @@ impl PowNode {
     &mut self,
     block_id: &BlockId,
     error: impl std::error::Error,
-  ) -> Result<(), Error> {
-    debug!("Failed consensus check: {} - {:?}", to_hex(block_id), error);
-    self.service.fail_block(block_id.to_owned())
+  ) -> Result<(), PowError> {
+    debug!(block_id = to_hex(block_id); "Failed consensus check: {} - {:?}", to_hex(block_id), error);
+    METRICS.blocks_failed.inc();
+    self
+      .service
+      .fail_block(block_id.to_owned())
+      .map_err(Into::into)
   }
 
   /// Called when a new block is received; call for validation or fail the block.
-  /// Handle a `BlockValid` update from the Validator
-  ///
-  /// The block has been verified by the validator, so mark it as validated in the log and
-  /// attempt to handle the block.
+  fn on_block_new(&mut self, block: Block) -> Result<EventResult, PowError> {
+    // every block shown to the node is indexed, a walk down its fork is then fetched in batches
+    self.service.record(&block);
+    self.check_new_block(block, 1)
+  }
 
-  fn on_block_new(&mut self, block: Block) -> Result<EventResult, Error> {
+  /// Check the consensus of a new block, for the `attempt`th time
+  fn check_new_block(&mut self, block: Block, attempt: u32) -> Result<EventResult, PowError> {
     // This should never happen under normal circumstances
     if block.previous_id == NULL_BLOCK_IDENTIFIER {
       error!("Received Update::BlockNew for genesis block!");
       return Ok(EventResult::Continue);
     }
 
-    debug!("Checking block consensus: {}", Printer(&block));
+    debug!(block_num = block.block_num; "Checking block consensus: {}", Printer(&block));
+
+    // Reject non-canonical payloads once strict mode is active at this height
+    if *self.config.strict_consensus.at(block.block_num) {
+      if let Err(e) = BlockConsensus::deserialize_strict(&block.payload) {
+        self.on_block_new_error_handler(&block.block_id, e)?;
+        return Ok(EventResult::Continue);
+      }
+    }
 
     let header = match BlockHeader::borrowed(&block) {
       // Ensure the block consensus is valid
@@ -101,51 +142,160 @@ impl PowNode {
       }
     };
 
-    let expected_min_diff = {
-      let pred_header = match self.service.get_block(&header.previous_id) {
-        Ok(block) => match BlockHeader::owned(block.clone()) {
-          Ok(h) => h,
-          Err(e) => {
-            self.on_block_new_error_handler(&block.block_id, e)?;
-            return Ok(EventResult::Continue);
-          }
-        },
-        Err(e) => {
-          self.on_block_new_error_handler(&header.previous_id, e)?;
-          return Ok(EventResult::Continue);
-        }
-      };
-
-      trace!(
-        "Consensus min diff check: curr {:?} - pred {:?}",
-        &header,
-        &pred_header
-      );
-
-      pred_header.consensus.expected_difficulty
+    let pred_header: BlockHeader = match self.service.get_header(&header.previous_id) {
+      Ok(h) => h,
+      // the validator announced the block so it has the predecessor, ask for it again later
+      Err(e @ AncestorError::UnknownBlock(..)) => {
+        self.defer_new_block(block.clone(), attempt, e.into())?;
+        return Ok(EventResult::Continue);
+      }
+      // the predecessor may predate the switch to PoW, a PoW one that can't be read fails its successor
+      Err(e) => {
+        self.on_block_new_error_handler(&block.block_id, e)?;
+        return Ok(EventResult::Continue);
+      }
     };
 
-    // Ensure that the minimum difficulty has been reached.
-    // The block must pass the difficulty filter, use the lagged difficulty stored in the predecessor.
-    match header.validate(expected_min_diff) {
-      Ok(_) => (),
+    trace!(
+      "Consensus min diff check: curr {:?} - pred {:?}",
+      &header,
+      &pred_header
+    );
+
+    let expected_min_diff = required_difficulty(&pred_header, &self.config);
+
+    // Ensure that the minimum difficulty has been reached, the difficulty claimed for the next block
+    // is the one the chain calls for and the timestamp is after the median time past and not too
+    // far ahead of ours.
+    let now: CCTimestamp = self.clock.now();
+    match validate_consensus(&header, &pred_header, &mut self.service, &self.config, now) {
+      Ok(()) => {}
+      // a little ahead of our clock is only early, the block is acceptable once our clock catches up
+      Err(e @ PowError::Consensus(ConsensusError::TimestampInFuture { value, limit }))
+        if value.is_finite() && value - limit <= self.postpone_horizon(block.block_num) =>
+      {
+        self.postpone_new_block(block.clone(), now + (value - limit), e)?;
+        return Ok(EventResult::Continue);
+      }
+      // an ancestor the rules depend on couldn't be fetched, which says nothing about the block
+      Err(e @ PowError::Service(_))
+      | Err(e @ PowError::Ancestor(AncestorError::UnknownBlock(..))) => {
+        self.defer_new_block(block.clone(), attempt, e)?;
+        return Ok(EventResult::Continue);
+      }
       Err(e) => {
         self.on_block_new_error_handler(&block.block_id, e)?;
         return Ok(EventResult::Continue);
       }
     }
 
-    debug!("Passed consensus check: {}", Printer(&block));
+    debug!(
+      block_num = block.block_num,
+      block_id = to_hex(&block.block_id),
+      difficulty = expected_min_diff,
+      next_difficulty = header.consensus.expected_difficulty;
+      "Passed consensus check: {}",
+      Printer(&block)
+    );
     // Request block validation
     self.service.check_blocks(vec![block.block_id])?;
 
     Ok(EventResult::Continue)
   }
 
+  /// Check `block` again on the next commit, unless it was tried `MAX_BLOCK_ATTEMPTS` times already
+  fn defer_new_block(
+    &mut self,
+    block: Block,
+    attempt: u32,
+    error: PowError,
+  ) -> Result<(), PowError> {
+    if attempt >= MAX_BLOCK_ATTEMPTS {
+      return self.on_block_new_error_handler(&block.block_id, error);
+    }
+
+    warn!(
+      block_num = block.block_num,
+      block_id = to_hex(&block.block_id);
+      "Deferring consensus check (attempt {}): {} {}",
+      attempt,
+      error,
+      Printer(&block)
+    );
+    self.state.deferred.push((block, attempt));
+    Ok(())
+  }
+
+  /// Seconds past the future drift limit a block at `block_num` is postponed for at most
+  fn postpone_horizon(&self, block_num: u64) -> CCTimestamp {
+    let max_future_drift: u64 = *self.config.max_future_drift.at(block_num);
+    MAX_POSTPONE_DRIFTS.saturating_mul(max_future_drift) as CCTimestamp
+  }
+
+  /// Check `block` again once our clock reaches `due`, unlike a deferred block it is never failed
+  /// for waiting, only when `MAX_POSTPONED_BLOCKS` are waiting already
+  fn postpone_new_block(
+    &mut self,
+    block: Block,
+    due: CCTimestamp,
+    error: PowError,
+  ) -> Result<(), PowError> {
+    if self.state.postponed.len() >= MAX_POSTPONED_BLOCKS {
+      return self.on_block_new_error_handler(&block.block_id, error);
+    }
+
+    debug!(
+      block_num = block.block_num,
+      block_id = to_hex(&block.block_id);
+      "Postponing consensus check until {}: {}",
+      due,
+      Printer(&block)
+    );
+    self.state.postponed.push((block, due));
+    Ok(())
+  }
+
+  /// Check the postponed blocks that are due by now
+  pub fn recheck_postponed(&mut self) {
+    let now: CCTimestamp = self.clock.now();
+    let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.state.postponed)
+      .into_iter()
+      .partition(|(_, due)| *due <= now);
+    self.state.postponed = waiting;
+
+    for (block, _) in due {
+      if let Err(error) = self.check_new_block(block.clone(), 1) {
+        self.keep_unchecked(block, 1, error);
+      }
+    }
+  }
+
+  fn retry_deferred(&mut self) {
+    for (block, attempt) in std::mem::take(&mut self.state.deferred) {
+      if let Err(error) = self.check_new_block(block.clone(), attempt + 1) {
+        self.keep_unchecked(block, attempt + 1, error);
+      }
+    }
+  }
+
+  /// Keep a block whose check the validator cut short for the next commit, the blocks after it in
+  /// the queue are still checked
+  fn keep_unchecked(&mut self, block: Block, attempt: u32, error: PowError) {
+    warn!(
+      block_num = block.block_num,
+      block_id = to_hex(&block.block_id);
+      "Consensus check interrupted, retrying on the next commit: {} {}",
+      error,
+      Printer(&block)
+    );
+    self.state.deferred.push((block, attempt));
+  }
+
   /// Called when a block check succeeds
-  fn on_block_valid(&mut self, block_id: BlockId) -> Result<EventResult, Error> {
+  fn on_block_valid(&mut self, block_id: BlockId) -> Result<EventResult, PowError> {
     let cur_head: Block = self.service.get_block(&self.state.chain_head)?;
     let new_head: Block = self.service.get_block(&block_id)?;
+    self.service.record(&new_head);
 
     debug!(
       "Choosing between chain heads -- current: {} -- new: {}",
@@ -161,13 +311,19 @@ impl PowNode {
 
   /// Called when a block check fails
   /// The block has failed, perform cleanup of consensus' state
-  fn on_block_invalid(&mut self, _block_id: BlockId) -> Result<EventResult, Error> {
+  fn on_block_invalid(&mut self, _block_id: BlockId) -> Result<EventResult, PowError> {
     Ok(EventResult::Continue)
   }
 
   /// Called when a block commit completes
-  fn on_block_commit(&mut self, block_id: BlockId) -> Result<EventResult, Error> {
-    debug!("Chain head updated to {}", dbg_hex!(&block_id));
+  fn on_block_commit(&mut self, block_id: BlockId) -> Result<EventResult, PowError> {
+    debug!(block_id = to_hex(&block_id); "Chain head updated to {}", dbg_hex!(&block_id));
+
+    let block: Block = self.service.get_block(&block_id)?;
+    self.service.record(&block);
+
+    self.retry_deferred();
+    self.recheck_postponed();
 
     let mut did_publish = false;
     //don't try to publish if we have already published.
@@ -198,24 +354,48 @@ impl PowNode {
     // Remove publishing guards, allows starting the publishing state machine.
     self.state.guards.clear();
 
+    // Initialize a new block based on the updated chain head
+    self.service.initialize_block(Some(block_id.clone()))?;
+
     // Start the PoW process for this block
-    self.miner.mine(
+    self.start_mining(block_id);
+
+    Ok(EventResult::Restart(did_publish))
+  }
+
+  /// Hand the miner a challenge on `block_id`. If the ancestors it depends on can't be read, the
+  /// challenge is built again before each publishing attempt, and nothing is published until then.
+  fn start_mining(&mut self, block_id: BlockId) {
+    let mined = self.miner.mine(
       block_id.clone(),
       self.state.peer_id.clone(),
       &mut self.service,
       &self.config,
-    )?;
-
-    // Initialize a new block based on the updated chain head
-    self.service.initialize_block(Some(block_id))?;
+    );
 
-    Ok(EventResult::Restart(did_publish))
+    self.state.unmined = match mined {
+      Ok(()) => None,
+      Err(e) => {
+        warn!(
+          block_id = to_hex(&block_id);
+          "Can't mine on {} yet, retrying before publishing: {}",
+          dbg_hex!(&block_id),
+          e
+        );
+        Some(block_id)
+      }
+    };
   }
 
-  fn compare_forks(&mut self, cur_head: Block, new_head: Block) -> Result<(), Error> {
+  fn compare_forks(&mut self, cur_head: Block, new_head: Block) -> Result<(), PowError> {
     if !BlockConsensus::is_pow_consensus(&new_head.payload) {
-      debug!("Ignoring new block (consensus) {}", Printer(&new_head));
-      self.service.ignore_block(new_head.block_id)?;
+      debug!(
+        block_num = new_head.block_num,
+        block_id = to_hex(&new_head.block_id);
+        "Ignoring new block (consensus) {}",
+        Printer(&new_head)
+      );
+      self.wrapper_service_ignore_block(new_head.block_id)?;
       return Ok(());
     }
 
@@ -225,13 +405,23 @@ impl PowNode {
 
       loop {
         if fork_block.previous_id == cur_head.block_id {
-          debug!("Committing new block (consensus) {}", Printer(&new_head));
+          debug!(
+            block_num = new_head.block_num,
+            block_id = to_hex(&new_head.block_id);
+            "Committing new block (consensus) {}",
+            Printer(&new_head)
+          );
           self.wrapper_service_commit_block(new_head.block_id)?;
           break;
         } else if !BlockConsensus::is_pow_consensus(&fork_block.payload) {
           // also happens with genesis blocks
-          debug!("Ignoring new block (consensus) {}", Printer(&new_head));
-          self.service.ignore_block(new_head.block_id)?;
+          debug!(
+            block_num = new_head.block_num,
+            block_id = to_hex(&new_head.block_id);
+            "Ignoring new block (consensus) {}",
+            Printer(&new_head)
+          );
+          self.wrapper_service_ignore_block(new_head.block_id)?;
           break;
         }
 
@@ -240,7 +430,12 @@ impl PowNode {
     } else if new_head.block_num == cur_head.block_num + 1
       && new_head.previous_id == cur_head.block_id
     {
-      debug!("Committing new block (next) {}", Printer(&new_head));
+      debug!(
+        block_num = new_head.block_num,
+        block_id = to_hex(&new_head.block_id);
+        "Committing new block (next) {}",
+        Printer(&new_head)
+      );
       self.wrapper_service_commit_block(new_head.block_id)?;
     } else {
       self.resolve_fork(cur_head, new_head)?;
@@ -249,7 +444,7 @@ impl PowNode {
     Ok(())
   }
 
-  fn resolve_fork(&mut self, cur_head: Block, new_head: Block) -> Result<(), Error> {
+  fn resolve_fork(&mut self, cur_head: Block, new_head: Block) -> Result<(), PowError> {
     let cur_diff_size: u64 = cur_head.block_num.saturating_sub(new_head.block_num);
     let new_diff_size: u64 = new_head.block_num.saturating_sub(cur_head.block_num);
 
@@ -258,102 +453,180 @@ impl PowNode {
       cur_diff_size, new_diff_size,
     );
 
-    // Fetch all blocks from the current chain AFTER the head of the new chain
-    // Inverse of `new_chain_orphans`.
+    // Once activated, the orphans include both chain heads. Before, they were walked from the heads'
+    // predecessors, which skipped the longer fork's head and counted its deepest orphan twice.
+    let count_heads: bool = *self.config.count_fork_heads.at(new_head.block_num);
+    let (cur_start, new_start): (&[u8], &[u8]) = if count_heads {
+      (&cur_head.block_id, &new_head.block_id)
+    } else {
+      (&cur_head.previous_id, &new_head.previous_id)
+    };
+
+    // Fetch all blocks from the current chain AFTER the head of the new chain.
+    // Inverse of `new_chain_orphans`. The current chain was accepted, failing to read it is an error.
     let cur_chain_orphans: Vec<BlockHeader> =
-      BlockAncestors::new(&cur_head.previous_id, &mut self.service)
-        .take(cur_diff_size as usize)
-        .take_while(|block| block.consensus.is_pow())
-        .collect();
+      pow_chain(cur_start, cur_diff_size, &mut self.service)?;
 
     // Fetch all blocks from the new chain AFTER the head of the current chain.
     // Inverse of `cur_chain_orphans`.
     let new_chain_orphans: Vec<BlockHeader> =
-      BlockAncestors::new(&new_head.previous_id, &mut self.service)
-        .take(new_diff_size as usize)
-        .take_while(|block| block.consensus.is_pow())
-        .collect();
-
-    // Convert both chain heads to `BlockHeader`s. Propagate errors since
-    // PoW validation should have been an earlier step.
-    let cur_header: BlockHeader = BlockHeader::borrowed(&cur_head)
-      .unwrap_or_else(|_| panic!("Cur_header {}", Printer::from(&cur_head)));
-    let new_header: BlockHeader = BlockHeader::borrowed(&new_head)
-      .unwrap_or_else(|_| panic!("New_header {}", Printer::from(&new_head)));
-
-    // Fetch the earliest block from both orphan chains; default to the current head
-    let cur_fork_head: &BlockHeader = cur_chain_orphans.last().unwrap_or(&cur_header);
-    let new_fork_head: &BlockHeader = new_chain_orphans.last().unwrap_or(&new_header);
+      match pow_chain(new_start, new_diff_size, &mut self.service) {
+        Ok(headers) => headers,
+        Err(error) => return self.on_unreadable_fork(new_head, error),
+      };
 
-    debug_assert_eq!(cur_fork_head.block_num, new_fork_head.block_num);
+    // Both forks continue at the same height below their orphans; default to the chain heads
+    let fork_head = |orphans: &[BlockHeader], head: &Block| -> BlockId {
+      match orphans.last() {
+        Some(block) if count_heads => block.previous_id.clone(),
+        Some(block) => block.block_id.clone(),
+        None => head.block_id.clone(),
+      }
+    };
+    let cur_fork_head: BlockId = fork_head(&cur_chain_orphans, &cur_head);
+    let new_fork_head: BlockId = fork_head(&new_chain_orphans, &new_head);
 
     // Construct a `ForkChain` to quickly traverse ancestors in pairs.
     // Traverse until:
     //   1. A common ancestor is found
-    //   2. Either block is a genesis block
-    //   3. Either block is NOT a PoW block
-    let cur_ancestors = BlockAncestors::new(&cur_fork_head.block_id, &mut self.service);
-    let (cur_fork_blocks, new_fork_blocks): (Vec<_>, Vec<_>) = cur_ancestors
-      .paired_fork_iter(&new_fork_head.block_id)
-      .take_while(|(block_a, block_b)| block_a.block_id != block_b.block_id)
-      .take_while(|(block_a, block_b)| !block_a.is_genesis() && !block_b.is_genesis())
-      .take_while(|(block_a, block_b)| block_a.consensus.is_pow() && block_b.consensus.is_pow())
-      .unzip();
+    //   2. Either fork reaches the genesis block or a non-PoW block
+    //   3. Either fork can't be read, which is an error on the current chain and disqualifies the new one
+    let mut cur_fork_blocks: Vec<BlockHeader> = Vec::new();
+    let mut new_fork_blocks: Vec<BlockHeader> = Vec::new();
+    for pair in
+      BlockAncestors::new(&cur_fork_head, &mut self.service).paired_fork_iter(&new_fork_head)
+    {
+      match pair {
+        (Ok(block_a), Ok(block_b)) if block_a.block_id == block_b.block_id => break,
+        (Ok(block_a), Ok(block_b)) => {
+          cur_fork_blocks.push(block_a);
+          new_fork_blocks.push(block_b);
+        }
+        (Err(error), _) if !error.is_end() => return Err(error.into()),
+        (_, Err(error)) if !error.is_end() => return self.on_unreadable_fork(new_head, error),
+        // the end of the PoW chain on either side
+        _ => break,
+      }
+    }
 
     // Chain the new orphan chain with any uncommon
     // ancestors; sum the total amount of work.
-    let new_work: u64 = new_chain_orphans
-      .iter()
-      .chain(new_fork_blocks.iter())
-      .fold(0, |total, block| total + block.work());
+    let mut new_work: H256 = H256::MIN;
+    for block in new_chain_orphans.iter().chain(new_fork_blocks.iter()) {
+      match self.fork_work(block) {
+        Ok(work) => new_work = new_work.saturating_add(&work),
+        Err(error) => return self.on_unreadable_fork(new_head, error),
+      }
+    }
 
     // Chain the current orphan chain with any uncommon
     // ancestors; sum the total amount of work.
-    let cur_work: u64 = cur_chain_orphans
-      .iter()
-      .chain(cur_fork_blocks.iter())
-      .fold(0, |total, block| total + block.work());
+    let mut cur_work: H256 = H256::MIN;
+    for block in cur_chain_orphans.iter().chain(cur_fork_blocks.iter()) {
+      cur_work = cur_work.saturating_add(&self.fork_work(block)?);
+    }
+
+    // Commit the new fork if it has greater work, settle equal work with the tie break at the new height
+    let tie_break: TieBreak = *self.config.tie_break.at(new_head.block_num);
+    let (commit, rule): (bool, &str) = match new_work.cmp(&cur_work) {
+      Ordering::Greater => (true, "more work"),
+      Ordering::Less => (false, "less work"),
+      Ordering::Equal => (tie_break.prefers(&new_head, &cur_head), tie_break.name()),
+    };
 
-    // Commit the new fork if it has greater work
-    if new_work > cur_work {
+    // Blocks from the common ancestor up to the new head
+    let depth: usize = new_chain_orphans.len() + new_fork_blocks.len();
+    METRICS.fork_depth.observe(depth as f64);
+
+    if commit {
+      METRICS.forks_switched.inc();
       debug!(
-        "Committing new fork (work {}/{}) {}",
-        new_work,
-        cur_work,
+        "Committing new fork (work {:e}/{:e}, {}) {}",
+        new_work.to_f64(),
+        cur_work.to_f64(),
+        rule,
         Printer(&new_head),
       );
 
       self.wrapper_service_commit_block(new_head.block_id)?;
     } else {
+      METRICS.forks_kept.inc();
       debug!(
-        "Ignoring new fork (work {}/{}) {}",
-        new_work,
-        cur_work,
+        "Ignoring new fork (work {:e}/{:e}, {}) {}",
+        new_work.to_f64(),
+        cur_work.to_f64(),
+        rule,
         Printer(&new_head),
       );
 
-      self.service.ignore_block(new_head.block_id)?;
+      self.wrapper_service_ignore_block(new_head.block_id)?;
     }
 
     Ok(())
   }
 
+  /// The new fork can't be weighed against the current chain, ignore it unless the validator
+  /// itself couldn't be reached
+  fn on_unreadable_fork(&mut self, new_head: Block, error: AncestorError) -> Result<(), PowError> {
+    if let AncestorError::UnknownBlock(..) = error {
+      return Err(error.into());
+    }
+
+    warn!(
+      block_num = new_head.block_num,
+      block_id = to_hex(&new_head.block_id);
+      "Ignoring new fork, {} {}",
+      error,
+      Printer(&new_head)
+    );
+    self.wrapper_service_ignore_block(new_head.block_id)
+  }
+
+  /// The work a fork block counts for: what its hash proved or, once required work
+  /// or targets are active at its height, what its predecessor required.
+  fn fork_work(&mut self, block: &BlockHeader) -> Result<H256, AncestorError> {
+    let required: bool = *self.config.fork_choice.at(block.block_num) == ForkChoice::Required
+      || *self.config.difficulty_mode.at(block.block_num) == DifficultyMode::Target;
+
+    if !required {
+      return Ok(block.work(&self.config));
+    }
+
+    // the predecessor may predate the switch to PoW, which requires the minimum
+    let predecessor: BlockHeader = self.service.get_header(&block.previous_id)?;
+
+    Ok(required_work(&predecessor, &self.config))
+  }
+
   /// Is reentrant. Can be retried at any publishing state.
-  pub fn try_publish(&mut self) -> Result<EventPublishResult, Error> {
+  pub fn try_publish(&mut self) -> Result<EventPublishResult, PowError> {
     // If we already published at this height, exit early.
     if self.state.guards.contains(&Guard::Finalized) {
       //A block has not been commited yet.
       //While we are still waiting for a block to be committed
+      METRICS.publish_attempt(Guard::Finalized, PublishOutcome::Skipped);
       return Ok(EventPublishResult::Pending);
     }
 
+    // the miner may still hold an answer to the previous chain head
+    if let Some(block_id) = self.state.unmined.take() {
+      self.start_mining(block_id);
+      if self.state.unmined.is_some() {
+        METRICS.publish_attempt(Guard::Consensus, PublishOutcome::Pending);
+        return Ok(EventPublishResult::Pending);
+      }
+    }
+
     //always update consensus, i.e. never skip it.
     let consensus: Vec<u8> = match self.miner.try_create_consensus() {
       Some(consensus) => {
         self.state.guards.insert(Guard::Consensus);
         consensus
       }
-      None => return Ok(EventPublishResult::Pending),
+      None => {
+        METRICS.publish_attempt(Guard::Consensus, PublishOutcome::Pending);
+        return Ok(EventPublishResult::Pending);
+      }
     };
 
     // Try summarizing the blocks contents with a digest
@@ -367,10 +640,12 @@ impl PowNode {
         }
         Err(Error::BlockNotReady) => {
           trace!("Cannot summarize block: not ready");
+          METRICS.publish_attempt(Guard::Summarized, PublishOutcome::Pending);
           return Ok(EventPublishResult::Pending);
         }
         Err(error) => {
-          return Err(error);
+          METRICS.publish_attempt(Guard::Summarized, PublishOutcome::Error);
+          return Err(error.into());
         }
       }
     }
@@ -378,7 +653,7 @@ impl PowNode {
     if !finalized {
       match self.service.finalize_block(consensus) {
         Ok(block_id) => {
-          debug!("Publishing block: {}", dbg_hex!(&block_id));
+          debug!(block_id = to_hex(&block_id); "Publishing block: {}", dbg_hex!(&block_id));
 
           // Set publishing guard
           self.state.guards.insert(Guard::Finalized);
@@ -386,14 +661,18 @@ impl PowNode {
           self.state.guards.remove(&Guard::Consensus);
           self.state.guards.remove(&Guard::Summarized);
 
+          METRICS.publish_attempt(Guard::Finalized, PublishOutcome::Published);
+          METRICS.blocks_published.inc();
           return Ok(EventPublishResult::Published);
         }
         Err(Error::BlockNotReady) => {
           trace!("Cannot finalize block: not ready");
+          METRICS.publish_attempt(Guard::Finalized, PublishOutcome::Pending);
           return Ok(EventPublishResult::Pending);
         }
         Err(error) => {
-          return Err(error);
+          METRICS.publish_attempt(Guard::Finalized, PublishOutcome::Error);
+          return Err(error.into());
         }
       }
     }
@@ -401,9 +680,15 @@ impl PowNode {
     unreachable!();
   }
 
-  fn wrapper_service_commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+  fn wrapper_service_commit_block(&mut self, block_id: BlockId) -> Result<(), PowError> {
     self.state.chain_head = block_id.to_owned();
-    self.service.commit_block(block_id)
+    METRICS.blocks_committed.inc();
+    self.service.commit_block(block_id).map_err(Into::into)
+  }
+
+  fn wrapper_service_ignore_block(&mut self, block_id: BlockId) -> Result<(), PowError> {
+    METRICS.blocks_ignored.inc();
+    self.service.ignore_block(block_id).map_err(Into::into)
   }
 }
 
@@ -413,18 +698,38 @@ impl PowNode {
   }
 
   pub fn with_config(config: PowConfig, service: Box<dyn Service>) -> Self {
+    Self::with_clock(config, service, SystemClock::shared())
+  }
+
+  /// A node reading the time from `clock`, shared with its miner
+  pub fn with_clock(config: PowConfig, service: Box<dyn Service>, clock: SharedClock) -> Self {
     let state: PowState = PowState::new();
-    let miner: Miner = Miner::default();
+    let miner: Miner = Miner::new(config.worker_threads, clock.clone());
 
     Self {
       config,
       state,
       miner,
+      clock,
       service: PowService::new(service),
     }
   }
 
-  pub fn initialize(mut self, state: StartupState) -> Result<Self, Error> {
+  pub fn clock(&self) -> &SharedClock {
+    &self.clock
+  }
+
+  /// When the earliest postponed block is due to be checked again, if any
+  pub fn next_postponed(&self) -> Option<CCTimestamp> {
+    self
+      .state
+      .postponed
+      .iter()
+      .map(|(_, due)| *due)
+      .min_by(|a, b| a.total_cmp(b))
+  }
+
+  pub fn initialize(mut self, state: StartupState) -> Result<Self, PowError> {
     if state.chain_head.block_num > 1 {
       debug!("Starting from non-genesis: {}", Printer(&state.chain_head));
     }
@@ -440,27 +745,70 @@ impl PowNode {
       // Set initial on-chain configuration
       self.reload_configuration()?;
 
-      // Start the inital PoW process with the current chain head
-      self.miner.mine(
-        self.state.chain_head.clone(),
-        self.state.peer_id.clone(),
-        &mut self.service,
-        &self.config,
-      )?;
-
       // Initialize a new block based on the current chain head
       self.service.initialize_block(None)?;
+
+      // Start the inital PoW process with the current chain head
+      self.start_mining(self.state.chain_head.clone());
     }
 
     Ok(self)
   }
 
+  /// How long to wait before publishing on the current chain head, as the on-chain settings ask
+  pub fn publishing_delay(&mut self) -> Duration {
+    let head_timestamp = if self.config.publishing_target_percent > 0 {
+      self
+        .service
+        .get_block(&self.state.chain_head)
+        .ok()
+        .and_then(|head| BlockConsensus::deserialize(&head.payload).ok())
+        .map(|consensus| consensus.timestamp)
+    } else {
+      None
+    };
+
+    self
+      .config
+      .publishing_delay(head_timestamp, self.clock.now())
+  }
+
+  /// Block until the miner solved its challenge, false if it didn't within `timeout`
+  pub fn wait_for_answer(&self, timeout: Duration) -> bool {
+    self.miner.wait_for_answer(timeout)
+  }
+
+  /// Log the miner's hash rate next to the network's, as estimated from the blocks before the head
+  pub fn log_mining_summary(&mut self) {
+    let stats: MiningStats = self.miner.stats();
+    let window: u64 = self.config.difficulty_window;
+    let network: Option<f64> = self
+      .service
+      .get_block(&self.state.chain_head)
+      .ok()
+      .and_then(|head| BlockHeader::owned(head).ok())
+      .and_then(|head| network_hash_rate(&head, &mut self.service, &self.config, window));
+
+    info!(
+      hash_rate = stats.hash_rate(),
+      hashes = stats.hashes(),
+      best_score = stats.best_score(),
+      network_hash_rate = network;
+      "Mining at {:.0} H/s, {} hashes in total, best score {} on the current challenge; network at {} H/s",
+      stats.hash_rate(),
+      stats.hashes(),
+      stats
+        .best_score()
+        .map_or_else(|| "none".to_string(), |score| score.to_string()),
+      network.map_or_else(|| "unknown".to_string(), |rate| format!("{:.0}", rate)),
+    );
+  }
+
   /// Fetch and store on-chain settings as of the current head height
-  pub fn reload_configuration(&mut self) -> Result<(), Error> {
+  pub fn reload_configuration(&mut self) -> Result<(), PowError> {
     self
       .config
       .load(&mut self.service, self.state.chain_head.to_owned())
-      .map_err(Into::into)
   }
 }
 
@@ -469,6 +817,13 @@ mod tests {
 
   use super::*;
   use crate::node::tests::MockService;
+  use crate::node::Schedule;
+  use crate::primitives::{CCDifficulty, CCTimestamp};
+  use crate::simulator::{BlockStore, Decision, SimulatedService};
+  use crate::utils::MockClock;
+  use crate::work::PowAlgorithmKind;
+  use crate::Duration;
+  use std::time::Instant;
   #[test]
   fn if_already_published_dont_publish_on_block_commit() -> Result<(), Error> {
     let state = {
@@ -481,6 +836,7 @@ mod tests {
       service: PowService::new(Box::new(MockService {})),
       state,
       miner: Miner::default(),
+      clock: SystemClock::shared(),
     };
     //publishing finished successfully
     node.state.guards.insert(Guard::Finalized);
@@ -506,6 +862,7 @@ mod tests {
       service: PowService::new(Box::new(MockService {})),
       state,
       miner: Miner::default(),
+      clock: SystemClock::shared(),
     };
 
     node.state.guards.insert(Guard::Finalized);
@@ -516,4 +873,729 @@ mod tests {
 
     Ok(())
   }
+
+  const SIGNER: &[u8] = b"ssssssssssssssss";
+  /// Every hand-built block requires 4 leading zero bits from its successor
+  const REQUIRED: CCDifficulty = 4;
+
+  /// A child of `parent` whose hash has a leading zero bit count accepted by `score`
+  fn mined(
+    id: u8,
+    parent: &Block,
+    timestamp: CCTimestamp,
+    score: impl Fn(CCDifficulty) -> bool,
+  ) -> Block {
+    let mut algorithm = PowAlgorithmKind::default().build();
+    let nonce = (0..)
+      .find(|nonce| {
+        let hash = algorithm.hash(&parent.block_id, SIGNER, *nonce);
+        score(algorithm.score(&hash))
+      })
+      .unwrap();
+
+    Block {
+      block_id: vec![id; 8],
+      previous_id: parent.block_id.clone(),
+      signer_id: SIGNER.to_vec(),
+      block_num: parent.block_num + 1,
+      payload: BlockConsensus::serialize(REQUIRED, timestamp, nonce),
+      ..Block::default()
+    }
+  }
+
+  /// A child that proves exactly the required work
+  fn honest(id: u8, parent: &Block) -> Block {
+    mined(id, parent, 1000.0, |score| score == REQUIRED)
+  }
+
+  /// A child that proves at least 2^8 times the required work
+  fn lucky(id: u8, parent: &Block) -> Block {
+    mined(id, parent, 1000.0, |score| score >= REQUIRED + 8)
+  }
+
+  /// The common ancestor of every fork, right after the genesis block of a simulated validator
+  fn root() -> Block {
+    Block {
+      block_id: vec![1; 8],
+      previous_id: BlockStore::new().genesis().block_id,
+      block_num: 1,
+      payload: BlockConsensus::serialize(REQUIRED, 1000.0, 0),
+      ..Block::default()
+    }
+  }
+
+  /// A node whose validator holds `blocks`, on the system clock
+  fn fork_node(config: PowConfig, blocks: &[&Block]) -> (PowNode, SimulatedService) {
+    fork_node_with_clock(config, SystemClock::shared(), blocks)
+  }
+
+  /// A node on `clock` whose validator holds `blocks`
+  fn fork_node_with_clock(
+    config: PowConfig,
+    clock: SharedClock,
+    blocks: &[&Block],
+  ) -> (PowNode, SimulatedService) {
+    let store = BlockStore::new();
+    for block in blocks {
+      store.insert((*block).clone());
+    }
+    let service = SimulatedService::new(store, SIGNER.to_vec());
+    let node = PowNode::with_clock(config, Box::new(service.clone()), clock);
+
+    (node, service)
+  }
+
+  /// Resolve `new_head` against `cur_head`, true if the new fork is committed
+  fn switches_with(
+    config: PowConfig,
+    cur_head: &Block,
+    new_head: &Block,
+    blocks: &[&Block],
+  ) -> bool {
+    let (mut node, service) = fork_node(config, blocks);
+    node
+      .resolve_fork(cur_head.clone(), new_head.clone())
+      .unwrap();
+
+    let decisions: Vec<Decision> = service.decisions();
+    assert_eq!(decisions.len(), 1);
+    decisions[0] == Decision::Commit(new_head.block_id.clone())
+  }
+
+  fn switches(
+    fork_choice: Schedule<ForkChoice>,
+    cur_head: &Block,
+    new_head: &Block,
+    blocks: &[&Block],
+  ) -> bool {
+    let mut config = PowConfig::new();
+    config.fork_choice = fork_choice;
+    switches_with(config, cur_head, new_head, blocks)
+  }
+
+  /// The head a node ends up on after seeing `first` then `second`
+  fn head_after(tie_break: TieBreak, first: &Block, second: &Block, blocks: &[&Block]) -> BlockId {
+    let mut config = PowConfig::new();
+    config.tie_break = Schedule::new(tie_break);
+
+    if switches_with(config, first, second, blocks) {
+      second.block_id.clone()
+    } else {
+      first.block_id.clone()
+    }
+  }
+
+  #[test]
+  fn a_lucky_block_outweighs_a_longer_chain_only_on_achieved_work() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = lucky(0xb2, &root);
+    let blocks = [&root, &a2, &a3, &b2];
+
+    assert!(switches(
+      Schedule::new(ForkChoice::Achieved),
+      &a3,
+      &b2,
+      &blocks
+    ));
+    assert!(!switches(
+      Schedule::new(ForkChoice::Required),
+      &a3,
+      &b2,
+      &blocks
+    ));
+  }
+
+  #[test]
+  fn a_longer_chain_wins_on_required_work_even_against_luck() {
+    let root = root();
+    let a2 = lucky(0xa2, &root);
+    let b2 = honest(0xb2, &root);
+    let b3 = honest(0xb3, &b2);
+    let blocks = [&root, &a2, &b2, &b3];
+
+    assert!(!switches(
+      Schedule::new(ForkChoice::Achieved),
+      &a2,
+      &b3,
+      &blocks
+    ));
+    assert!(switches(
+      Schedule::new(ForkChoice::Required),
+      &a2,
+      &b3,
+      &blocks
+    ));
+  }
+
+  #[test]
+  fn modes_agree_on_honest_forks() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = honest(0xb2, &root);
+    let b3 = honest(0xb3, &b2);
+    let b4 = honest(0xb4, &b3);
+    let blocks = [&root, &a2, &a3, &b2, &b3, &b4];
+
+    for fork_choice in [ForkChoice::Achieved, ForkChoice::Required] {
+      assert!(switches(Schedule::new(fork_choice), &a3, &b4, &blocks));
+      assert!(!switches(Schedule::new(fork_choice), &b4, &a3, &blocks));
+    }
+  }
+
+  #[test]
+  fn required_work_applies_from_its_activation_height() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = lucky(0xb2, &root);
+    let blocks = [&root, &a2, &a3, &b2];
+
+    let late = Schedule::new(ForkChoice::Achieved).activate(3, ForkChoice::Required);
+    assert!(switches(late, &a3, &b2, &blocks));
+
+    let early = Schedule::new(ForkChoice::Achieved).activate(2, ForkChoice::Required);
+    assert!(!switches(early, &a3, &b2, &blocks));
+  }
+
+  #[test]
+  fn forks_from_before_the_switch_to_pow_weigh_their_first_blocks_at_the_minimum() {
+    let root = Block {
+      payload: b"Devmode".to_vec(),
+      ..root()
+    };
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = lucky(0xb2, &root);
+    let blocks = [&root, &a2, &a3, &b2];
+
+    assert!(!switches(
+      Schedule::new(ForkChoice::Required),
+      &a3,
+      &b2,
+      &blocks
+    ));
+    assert!(switches(
+      Schedule::new(ForkChoice::Required),
+      &b2,
+      &a3,
+      &blocks
+    ));
+  }
+
+  #[test]
+  fn targets_weigh_forks_on_work_beyond_64_bits() {
+    let targeted = |id: u8, parent: &Block, bits: CCDifficulty| Block {
+      block_id: vec![id; 8],
+      previous_id: parent.block_id.clone(),
+      block_num: parent.block_num + 1,
+      payload: BlockConsensus::serialize(bits, 1000.0, 0),
+      ..Block::default()
+    };
+    // about 2^80 hashes are required after the root and on fork a, 2^88 on fork b
+    let root = Block {
+      payload: BlockConsensus::serialize(0x1700_ffff, 1000.0, 0),
+      ..root()
+    };
+    let a2 = targeted(0xa2, &root, 0x1700_ffff);
+    let a3 = targeted(0xa3, &a2, 0x1700_ffff);
+    let b2 = targeted(0xb2, &root, 0x1600_ffff);
+    let b3 = targeted(0xb3, &b2, 0x1600_ffff);
+    let blocks = [&root, &a2, &a3, &b2, &b3];
+    let config = || PowConfig {
+      difficulty_mode: Schedule::new(DifficultyMode::Target),
+      ..PowConfig::new()
+    };
+
+    assert!(switches_with(config(), &a3, &b3, &blocks));
+    assert!(!switches_with(config(), &b3, &a3, &blocks));
+  }
+
+  #[test]
+  fn both_chain_heads_are_counted_from_the_activation_height() {
+    let root = root();
+    let a2 = mined(0xa2, &root, 1000.0, |score| score == REQUIRED + 8);
+    let a3 = honest(0xa3, &a2);
+    let b2 = mined(0xb2, &root, 1000.0, |score| score == REQUIRED + 9);
+    let blocks = [&root, &a2, &a3, &b2];
+
+    let switches_counting_heads = |count_fork_heads: Schedule<bool>| {
+      let mut config = PowConfig::new();
+      config.fork_choice = Schedule::new(ForkChoice::Achieved);
+      config.tie_break = Schedule::new(TieBreak::FirstSeen);
+      config.count_fork_heads = count_fork_heads;
+      switches_with(config, &a3, &b2, &blocks)
+    };
+
+    // a2 counted twice in place of a3 ties with b2
+    assert!(!switches_counting_heads(Schedule::new(false)));
+    assert!(!switches_counting_heads(
+      Schedule::new(false).activate(3, true)
+    ));
+    assert!(switches_counting_heads(
+      Schedule::new(false).activate(2, true)
+    ));
+  }
+
+  #[test]
+  fn first_seen_keeps_validators_split() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let b2 = honest(0xb2, &root);
+    let blocks = [&root, &a2, &b2];
+
+    assert_eq!(
+      head_after(TieBreak::FirstSeen, &a2, &b2, &blocks),
+      a2.block_id
+    );
+    assert_eq!(
+      head_after(TieBreak::FirstSeen, &b2, &a2, &blocks),
+      b2.block_id
+    );
+    // unless another tie break is scheduled
+    assert!(!switches_with(PowConfig::new(), &b2, &a2, &blocks));
+  }
+
+  #[test]
+  fn lowest_hash_settles_ties_whatever_the_order() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = honest(0xb2, &root);
+    let b3 = honest(0xb3, &b2);
+    let blocks = [&root, &a2, &a3, &b2, &b3];
+
+    assert_eq!(
+      head_after(TieBreak::LowestHash, &a3, &b3, &blocks),
+      a3.block_id
+    );
+    assert_eq!(
+      head_after(TieBreak::LowestHash, &b3, &a3, &blocks),
+      a3.block_id
+    );
+  }
+
+  #[test]
+  fn earliest_timestamp_settles_ties_whatever_the_order() {
+    let root = root();
+    let a2 = mined(0xa2, &root, 1010.0, |score| score == REQUIRED);
+    let b2 = mined(0xb2, &root, 1005.0, |score| score == REQUIRED);
+    let c2 = mined(0xc2, &root, 1005.0, |score| score == REQUIRED);
+    let blocks = [&root, &a2, &b2, &c2];
+
+    assert_eq!(
+      head_after(TieBreak::EarliestTimestamp, &a2, &b2, &blocks),
+      b2.block_id
+    );
+    assert_eq!(
+      head_after(TieBreak::EarliestTimestamp, &b2, &a2, &blocks),
+      b2.block_id
+    );
+    // same timestamp, lowest hash
+    assert_eq!(
+      head_after(TieBreak::EarliestTimestamp, &c2, &b2, &blocks),
+      b2.block_id
+    );
+  }
+
+  #[test]
+  fn ties_are_only_broken_on_equal_work() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = honest(0xb2, &root);
+    let blocks = [&root, &a2, &a3, &b2];
+
+    assert_eq!(
+      head_after(TieBreak::LowestHash, &a3, &b2, &blocks),
+      a3.block_id
+    );
+    assert_eq!(
+      head_after(TieBreak::LowestHash, &b2, &a3, &blocks),
+      a3.block_id
+    );
+  }
+
+  #[test]
+  fn forks_that_cant_be_read_are_not_weighed() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let a3 = honest(0xa3, &a2);
+    let b2 = Block {
+      payload: b"PoW:x".to_vec(),
+      ..honest(0xb2, &root)
+    };
+    let b3 = honest(0xb3, &b2);
+    let b4 = honest(0xb4, &b3);
+
+    // a longer fork is ignored when one of its blocks is unparsable
+    let (mut node, service) = fork_node(PowConfig::new(), &[&root, &a2, &a3, &b2, &b3, &b4]);
+    node.resolve_fork(a3.clone(), b4.clone()).unwrap();
+    assert_eq!(
+      service.decisions(),
+      vec![Decision::Ignore(b4.block_id.clone())]
+    );
+
+    // and left undecided when the validator doesn't return one
+    let (mut node, service) = fork_node(PowConfig::new(), &[&root, &a2, &a3, &b3, &b4]);
+    let error = node.resolve_fork(a3.clone(), b4.clone()).unwrap_err();
+    assert!(
+      matches!(error, PowError::Ancestor(AncestorError::UnknownBlock(..))),
+      "{}",
+      error
+    );
+    assert!(service.decisions().is_empty());
+  }
+
+  #[test]
+  fn hostile_payloads_fail_their_block_without_crashing_the_node() {
+    let root = root();
+    let mut binary_nan: Vec<u8> = b"PW2".to_vec();
+    binary_nan.extend_from_slice(&REQUIRED.to_be_bytes());
+    binary_nan.extend_from_slice(&0u64.to_be_bytes());
+    binary_nan.extend_from_slice(&f64::NAN.to_bits().to_be_bytes());
+    let payloads: Vec<Vec<u8>> = vec![
+      vec![],
+      vec![0xff; 64],
+      b"PoW".to_vec(),
+      b"PoW:".to_vec(),
+      b"PoW:1".to_vec(),
+      b"PoW:x:y:z".to_vec(),
+      b"PoW:\xff\xfe:1:1000".to_vec(),
+      b"PoW:99999999999:1:1000".to_vec(),
+      b"PoW:1:1:NaN".to_vec(),
+      b"PoW:1:1:inf".to_vec(),
+      b"PoW:1:1:-1e308".to_vec(),
+      b"PoW:4:18446744073709551615:1000".to_vec(),
+      b"PW2\x00".to_vec(),
+      binary_nan,
+    ];
+
+    for (index, payload) in payloads.into_iter().enumerate() {
+      // ids too short for the usual abbreviation in the logs
+      let block = Block {
+        block_id: vec![0x10 + index as u8],
+        previous_id: root.block_id.clone(),
+        signer_id: vec![1],
+        block_num: root.block_num + 1,
+        payload,
+        ..Block::default()
+      };
+      // the legacy rules take any timestamp, the timestamp rules are what bound it
+      let config = PowConfig {
+        median_time_span: Schedule::new(11),
+        max_future_drift: Schedule::new(7200),
+        ..PowConfig::new()
+      };
+      let (mut node, service) = fork_node(config, &[&root, &block]);
+
+      let result = node.handle_update(Update::BlockNew(block.clone()));
+      assert!(
+        matches!(result, Ok(EventResult::Continue)),
+        "payload {}",
+        index
+      );
+      assert_eq!(
+        service.decisions(),
+        vec![Decision::Fail(block.block_id.clone())],
+        "payload {}",
+        index
+      );
+      assert!(format!("{} {:?}", Printer(&block), Printer(&block)).contains("Block"));
+    }
+  }
+
+  #[test]
+  fn a_block_whose_predecessor_cant_be_fetched_is_checked_again() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+
+    // failed after the last attempt, and only then
+    let (mut node, service) = fork_node(PowConfig::new(), &[&a2]);
+    node.handle_update(Update::BlockNew(a2.clone())).unwrap();
+    node.retry_deferred();
+    assert!(service.decisions().is_empty());
+    node.retry_deferred();
+    assert_eq!(
+      service.decisions(),
+      vec![Decision::Fail(a2.block_id.clone())]
+    );
+    assert!(node.state.deferred.is_empty());
+
+    // checked once the validator returns the predecessor
+    let (mut node, service) = fork_node(PowConfig::new(), &[&a2]);
+    node.handle_update(Update::BlockNew(a2.clone())).unwrap();
+    assert_eq!(node.state.deferred.len(), 1);
+    service.store().insert(root.clone());
+    node.retry_deferred();
+    assert!(service.decisions().is_empty());
+    assert!(node.state.deferred.is_empty());
+  }
+
+  #[test]
+  fn a_check_the_validator_refuses_leaves_the_other_deferred_blocks_checked() {
+    let root = root();
+    let a2 = honest(0xa2, &root);
+    let b2 = honest(0xb2, &root);
+    let (mut node, service) = fork_node(PowConfig::new(), &[&a2, &b2]);
+    node.handle_update(Update::BlockNew(a2.clone())).unwrap();
+    node.handle_update(Update::BlockNew(b2.clone())).unwrap();
+    assert_eq!(node.state.deferred.len(), 2);
+
+    service.store().insert(root.clone());
+    service.refuse_checks(1);
+    node.retry_deferred();
+    assert!(matches!(service.next_update(), Some(Update::BlockValid(id)) if id == b2.block_id));
+    assert_eq!(node.state.deferred.len(), 1);
+
+    node.retry_deferred();
+    assert!(matches!(service.next_update(), Some(Update::BlockValid(id)) if id == a2.block_id));
+    assert!(node.state.deferred.is_empty());
+    assert!(service.decisions().is_empty());
+  }
+
+  #[test]
+  fn blocks_ahead_of_our_clock_wait_for_it_but_blocks_behind_the_median_fail() -> Result<(), Error>
+  {
+    let root = root();
+    let early = mined(0xa2, &root, 1100.0, |score| score == REQUIRED);
+    let late = mined(0xb2, &root, 999.0, |score| score == REQUIRED);
+    let config = PowConfig {
+      max_future_drift: Schedule::new(60),
+      median_time_span: Schedule::new(1),
+      ..PowConfig::new()
+    };
+    let clock = MockClock::new(1000.0);
+    let (mut node, service) = fork_node_with_clock(config, clock.shared(), &[&root, &early, &late]);
+
+    node.handle_update(Update::BlockNew(early.clone()))?;
+    assert!(service.decisions().is_empty());
+    assert_eq!(node.next_postponed(), Some(1040.0));
+
+    clock.advance(Duration::from_secs(39));
+    node.recheck_postponed();
+    assert!(service.next_update().is_none());
+
+    clock.advance(Duration::from_secs(1));
+    node.recheck_postponed();
+    assert!(matches!(service.next_update(), Some(Update::BlockValid(id)) if id == early.block_id));
+    assert_eq!(node.next_postponed(), None);
+
+    node.handle_update(Update::BlockNew(late.clone()))?;
+    assert_eq!(service.decisions(), vec![Decision::Fail(late.block_id)]);
+    assert_eq!(node.next_postponed(), None);
+
+    Ok(())
+  }
+
+  #[test]
+  fn blocks_too_far_ahead_or_beyond_the_cap_fail_instead_of_waiting() -> Result<(), Error> {
+    let root = root();
+    // the limit is 1060, blocks are postponed up to 4 drifts past it
+    let latest = mined(0xa2, &root, 1300.0, |score| score == REQUIRED);
+    let too_late = mined(0xa3, &root, 1300.5, |score| score == REQUIRED);
+    let early = mined(0xa4, &root, 1100.0, |score| score == REQUIRED);
+    let config = PowConfig {
+      max_future_drift: Schedule::new(60),
+      ..PowConfig::new()
+    };
+    let clock = MockClock::new(1000.0);
+    let (mut node, service) =
+      fork_node_with_clock(config, clock.shared(), &[&root, &latest, &too_late, &early]);
+
+    node.handle_update(Update::BlockNew(latest.clone()))?;
+    assert!(service.decisions().is_empty());
+    assert_eq!(node.next_postponed(), Some(1240.0));
+
+    node.handle_update(Update::BlockNew(too_late.clone()))?;
+    assert_eq!(
+      service.decisions(),
+      vec![Decision::Fail(too_late.block_id.clone())]
+    );
+
+    node
+      .state
+      .postponed
+      .resize(MAX_POSTPONED_BLOCKS, (latest, 1240.0));
+    node.handle_update(Update::BlockNew(early.clone()))?;
+    assert_eq!(
+      service.decisions(),
+      vec![
+        Decision::Fail(too_late.block_id),
+        Decision::Fail(early.block_id)
+      ]
+    );
+    assert_eq!(node.state.postponed.len(), MAX_POSTPONED_BLOCKS);
+
+    Ok(())
+  }
+
+  #[test]
+  fn a_block_whose_difficulty_depends_on_missing_ancestors_is_checked_again() -> Result<(), Error> {
+    // an adjustment block, the difficulty its successor claims depends on the blocks before it
+    let parent = Block {
+      block_id: vec![10; 8],
+      previous_id: vec![9; 8],
+      block_num: 10,
+      ..root()
+    };
+    let block = honest(0xa2, &parent);
+    let config = PowConfig {
+      strict_difficulty: Schedule::new(true),
+      ..PowConfig::new()
+    };
+    let clock = MockClock::new(1000.0);
+    let (mut node, service) = fork_node_with_clock(config, clock.shared(), &[&parent, &block]);
+
+    node.handle_update(Update::BlockNew(block.clone()))?;
+    assert!(service.decisions().is_empty());
+    assert_eq!(node.state.deferred.len(), 1);
+
+    node.retry_deferred();
+    node.retry_deferred();
+    assert_eq!(service.decisions(), vec![Decision::Fail(block.block_id)]);
+
+    Ok(())
+  }
+
+  #[test]
+  fn a_block_whose_median_time_past_depends_on_missing_ancestors_is_checked_again(
+  ) -> Result<(), Error> {
+    let parent = Block {
+      block_id: vec![3; 8],
+      previous_id: vec![2; 8],
+      block_num: 3,
+      ..root()
+    };
+    let block = honest(0xa2, &parent);
+    let config = PowConfig {
+      median_time_span: Schedule::new(5),
+      ..PowConfig::new()
+    };
+    let (mut node, service) = fork_node(config, &[&parent, &block]);
+
+    node.handle_update(Update::BlockNew(block.clone()))?;
+    assert!(service.decisions().is_empty());
+    assert_eq!(node.state.deferred.len(), 1);
+
+    // once the ancestors can be read, the rule applies: the block is dated at its parent's time
+    service.store().insert(Block {
+      block_id: vec![2; 8],
+      previous_id: BlockStore::new().genesis().block_id,
+      block_num: 2,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    });
+    node.retry_deferred();
+    assert_eq!(
+      service.decisions(),
+      vec![Decision::Fail(block.block_id.clone())]
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn a_chain_head_whose_challenge_cant_be_built_yet_is_mined_once_it_can() -> Result<(), Error> {
+    // an adjustment block, the difficulty its successor claims depends on the blocks before it
+    let head = Block {
+      block_id: vec![10; 8],
+      previous_id: vec![9; 8],
+      block_num: 10,
+      ..root()
+    };
+    let (node, service) = fork_node(PowConfig::new(), &[&head]);
+    let mut node = node.initialize(service.startup_state())?;
+
+    node.on_block_commit(head.block_id.clone())?;
+    assert_eq!(node.state.unmined, Some(head.block_id.clone()));
+    // the block on the new head was initialized all the same
+    assert!(service.clone().summarize_block().is_ok());
+    assert!(matches!(node.try_publish()?, EventPublishResult::Pending));
+    assert_eq!(node.state.unmined, Some(head.block_id.clone()));
+
+    service.store().insert(Block {
+      block_id: vec![9; 8],
+      previous_id: BlockStore::new().genesis().block_id,
+      block_num: 9,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    });
+    node.try_publish()?;
+    assert_eq!(node.state.unmined, None);
+
+    Ok(())
+  }
+
+  /// Let `node` publish and commit its own blocks until `service`'s head reaches `height`
+  fn mine_to(node: &mut PowNode, service: &SimulatedService, height: u64) -> Result<(), Error> {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while service.chain_head().block_num < height {
+      assert!(Instant::now() < deadline, "Mining timed out");
+      while let Some(update) = service.next_update() {
+        node.handle_update(update)?;
+      }
+      node.try_publish()?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn a_single_node_mines_and_commits_its_own_chain() -> Result<(), Error> {
+    let store = BlockStore::new();
+    store.set_setting(conf_key!("initial_difficulty"), "1");
+    let service = SimulatedService::new(store, b"node-aaaaaaaaaaa".to_vec());
+    let mut node = PowNode::new(Box::new(service.clone())).initialize(service.startup_state())?;
+    // other tests publish and commit alongside this one
+    let committed_before: u64 = METRICS.blocks_committed.get();
+    let published_before: u64 =
+      METRICS.publish_attempts(Guard::Finalized, PublishOutcome::Published);
+
+    mine_to(&mut node, &service, 3)?;
+
+    let published: Vec<BlockId> = service
+      .published()
+      .into_iter()
+      .map(|block| block.block_id)
+      .collect();
+    let decisions: Vec<Decision> = published.iter().cloned().map(Decision::Commit).collect();
+    assert_eq!(service.decisions(), decisions[..3]);
+    assert_eq!(node.config.initial_difficulty, 1);
+    assert!(METRICS.blocks_committed.get() >= committed_before + 3);
+    let published_after: u64 =
+      METRICS.publish_attempts(Guard::Finalized, PublishOutcome::Published);
+    assert!(published_after >= published_before + 3);
+
+    Ok(())
+  }
+
+  #[test]
+  fn the_publishing_delay_follows_the_settings_of_the_chain_head() -> Result<(), Error> {
+    let store = BlockStore::new();
+    store.set_setting(conf_key!("initial_difficulty"), "1");
+    store.set_setting(conf_key!("seconds_between_blocks"), "10");
+    store.set_setting(conf_key!("publishing_target_percent"), "50");
+    let clock = MockClock::new(1000.0);
+    let service = SimulatedService::new(store.clone(), b"node-aaaaaaaaaaa".to_vec());
+    let mut node = PowNode::with_clock(PowConfig::new(), Box::new(service.clone()), clock.shared())
+      .initialize(service.startup_state())?;
+
+    // genesis carries no timestamp, only the minimum applies
+    assert_eq!(node.publishing_delay(), Duration::from_millis(500));
+
+    // half the block time, counted from the head's timestamp
+    mine_to(&mut node, &service, 1)?;
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(node.publishing_delay(), Duration::from_secs(3));
+
+    // new settings apply once a commit reloads them
+    store.set_setting(conf_key!("min_publishing_delay_ms"), "8000");
+    assert_eq!(node.publishing_delay(), Duration::from_secs(3));
+    mine_to(&mut node, &service, 2)?;
+    assert_eq!(node.publishing_delay(), Duration::from_secs(8));
+
+    Ok(())
+  }
 }
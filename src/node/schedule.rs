@@ -0,0 +1,143 @@
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// An on-chain setting whose value changes at given block heights.
+///
+/// Written as `value` (active from genesis) or `height:value,height:value,...`, e.g. `0:sha256,120000:sha256d`.
+/// Heights before the first listed activation use the default value.
+#[derive(Clone, PartialEq)]
+pub struct Schedule<T> {
+  activations: Vec<(u64, T)>,
+}
+
+impl<T> Schedule<T> {
+  pub fn new(value: T) -> Self {
+    Self {
+      activations: vec![(0, value)],
+    }
+  }
+
+  /// Activate `value` from `height` onwards, replacing any later activation.
+  pub fn activate(mut self, height: u64, value: T) -> Self {
+    self.activations.retain(|(start, _)| *start < height);
+    self.activations.push((height, value));
+    self
+  }
+
+  /// The value in effect for the block at `height`
+  pub fn at(&self, height: u64) -> &T {
+    self
+      .activations
+      .iter()
+      .rev()
+      .find(|(start, _)| *start <= height)
+      .map(|(_, value)| value)
+      .expect("Schedule always has a genesis activation")
+  }
+
+  pub fn activations(&self) -> &[(u64, T)] {
+    &self.activations
+  }
+}
+
+impl<T: Default> Default for Schedule<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+impl<T> FromStr for Schedule<T>
+where
+  T: FromStr + Default,
+  <T as FromStr>::Err: Display,
+{
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    let mut activations: Vec<(u64, T)> = string
+      .split(',')
+      .map(str::trim)
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| {
+        let (height, value) = match entry.split_once(':') {
+          Some((height, value)) => (
+            height
+              .trim()
+              .parse::<u64>()
+              .map_err(|e| format!("height {}: {}", height, e))?,
+            value.trim(),
+          ),
+          None => (0, entry),
+        };
+        let value: T = value.parse().map_err(|e| format!("{}", e))?;
+        Ok((height, value))
+      })
+      .collect::<Result<_, String>>()?;
+
+    if activations.is_empty() {
+      return Err("Empty schedule".into());
+    }
+
+    activations.sort_by_key(|(height, _)| *height);
+
+    if activations.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+      return Err("Duplicate activation height".into());
+    }
+
+    if activations[0].0 != 0 {
+      activations.insert(0, (0, T::default()));
+    }
+
+    Ok(Self { activations })
+  }
+}
+
+impl<T: Display> Display for Schedule<T> {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    for (index, (height, value)) in self.activations.iter().enumerate() {
+      if index > 0 {
+        f.write_str(",")?;
+      }
+      write!(f, "{}:{}", height, value)?;
+    }
+    Ok(())
+  }
+}
+
+impl<T: Display> Debug for Schedule<T> {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    write!(f, "Schedule({})", self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn single_value_is_active_from_genesis() {
+    let schedule: Schedule<u32> = "7".parse().unwrap();
+
+    assert_eq!(*schedule.at(0), 7);
+    assert_eq!(*schedule.at(u64::MAX), 7);
+  }
+
+  #[test]
+  fn values_switch_at_activation_height() {
+    let schedule: Schedule<u32> = "100:2, 10:1".parse().unwrap();
+
+    assert_eq!(*schedule.at(9), 0);
+    assert_eq!(*schedule.at(10), 1);
+    assert_eq!(*schedule.at(99), 1);
+    assert_eq!(*schedule.at(100), 2);
+    assert_eq!(schedule.to_string(), "0:0,10:1,100:2");
+  }
+
+  #[test]
+  fn malformed_schedules_are_rejected() {
+    assert!("".parse::<Schedule<u32>>().is_err());
+    assert!("x:1".parse::<Schedule<u32>>().is_err());
+    assert!("1:x".parse::<Schedule<u32>>().is_err());
+    assert!("5:1,5:2".parse::<Schedule<u32>>().is_err());
+  }
+}
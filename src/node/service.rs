@@ -1,13 +1,27 @@
 use sawtooth_sdk::consensus::{
-  engine::{Block, Error},
+  engine::{Block, BlockId, Error},
   service::Service,
 };
 
+use crate::block::{AncestorError, BlockHeader};
+use crate::metrics::METRICS;
+use crate::node::{AncestorIndex, CacheStats, HeaderCache};
 use crate::utils::to_hex;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 
+/// Blocks asked for in one `get_blocks` call on a cache miss, the missing one and its ancestors
+pub const PREFETCH_BATCH: usize = 16;
+
 pub struct PowService {
   service: Box<dyn Service>,
+  /// Parsed headers of the blocks looked up recently, most lookups walk the same ancestors
+  headers: HeaderCache,
+  /// Heights of the blocks seen, to fetch the ancestors of a missing block along with it
+  ancestors: AncestorIndex,
+  /// `get_blocks` calls made to the validator
+  fetches: u64,
 }
 
 impl Deref for PowService {
@@ -26,24 +40,131 @@ impl DerefMut for PowService {
 
 impl PowService {
   pub fn new(service: Box<dyn Service>) -> Self {
-    PowService { service }
+    Self::with_cache(service, HeaderCache::default())
+  }
+
+  pub fn with_cache(service: Box<dyn Service>, headers: HeaderCache) -> Self {
+    PowService {
+      service,
+      headers,
+      ancestors: AncestorIndex::default(),
+      fetches: 0,
+    }
   }
 
+  /// The block `block_id`, from the header cache when it was looked up recently.
+  ///
+  /// On a miss the ancestors the index expects below it are fetched in the same call, so a walk
+  /// down the chain takes a round trip per `PREFETCH_BATCH` blocks rather than one per block.
+  /// Where the index knows the height but not the links, the blocks last seen at the heights below
+  /// are asked for speculatively, a wrong guess only costs its place in the batch.
   pub fn get_block(&mut self, block_id: &[u8]) -> Result<Block, Error> {
-    self
-      .service
-      .get_blocks(vec![block_id.to_owned()])
-      .and_then(|mut map| {
-        map
-          .remove(block_id)
-          .ok_or_else(|| Error::UnknownBlock(to_hex(block_id)))
-      })
+    if let Some(header) = self.headers.get(block_id) {
+      METRICS.header_cache_hits.inc();
+      return Ok(Block::clone(header));
+    }
+    METRICS.header_cache_misses.inc();
+
+    let block: Block = self.fetch_missing(block_id)?;
+    self.remember(block.clone());
+
+    Ok(block)
+  }
+
+  /// The header of `block_id`, parsed once and then served from the header cache as long as it is
+  /// cached. Fetched as `get_block` does on a miss.
+  ///
+  /// A block from before the switch to PoW gets an empty consensus, see
+  /// `BlockHeader::from_any_consensus`.
+  pub fn get_header(&mut self, block_id: &[u8]) -> Result<BlockHeader<'static>, AncestorError> {
+    if let Some(header) = self.headers.get(block_id) {
+      METRICS.header_cache_hits.inc();
+      return Ok(header.clone());
+    }
+    METRICS.header_cache_misses.inc();
+
+    let block: Block = self
+      .fetch_missing(block_id)
+      .map_err(|e| AncestorError::UnknownBlock(block_id.to_owned(), e))?;
+    let header: BlockHeader<'static> = BlockHeader::from_any_consensus(Cow::Owned(block))
+      .map_err(|e| AncestorError::Unparsable(block_id.to_owned(), e))?;
+    self.headers.insert(header.clone());
+
+    Ok(header)
+  }
+
+  /// Fetch `block_id`, which isn't cached, with the ancestors the index expects below it. The
+  /// ancestors are remembered, the block itself is left to the caller.
+  fn fetch_missing(&mut self, block_id: &[u8]) -> Result<Block, Error> {
+    // more than the cache holds would evict the prefetched blocks before they are asked for
+    let batch: usize = PREFETCH_BATCH.min(self.headers.capacity()).max(1);
+    let mut block_ids: Vec<BlockId> = vec![block_id.to_owned()];
+    block_ids.extend(
+      self
+        .ancestors
+        .ancestors(block_id, batch - 1)
+        .into_iter()
+        .filter(|id| !self.headers.contains(id)),
+    );
+
+    let mut blocks: HashMap<BlockId, Block> = match self.fetch(block_ids) {
+      Ok(blocks) => blocks,
+      // the validator refuses a whole batch over one unknown id, a guess may have been wrong
+      Err(Error::UnknownBlock(_)) => self.fetch(vec![block_id.to_owned()])?,
+      Err(error) => return Err(error),
+    };
+    let block: Block = blocks
+      .remove(block_id)
+      .ok_or_else(|| Error::UnknownBlock(to_hex(block_id)))?;
+
+    for prefetched in blocks.into_values() {
+      self.remember(prefetched);
+    }
+    self.ancestors.record(&block);
+
+    Ok(block)
+  }
+
+  /// Remember where `block` sits in the chain, so that a later walk through it is fetched in
+  /// batches even if the block itself was never looked up
+  pub fn record(&mut self, block: &Block) {
+    self.ancestors.record(block);
+  }
+
+  fn fetch(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+    self.fetches += 1;
+    self.service.get_blocks(block_ids)
+  }
+
+  fn remember(&mut self, block: Block) {
+    self.ancestors.record(&block);
+    // blocks from before the switch to PoW are read as the node reads them, broken payloads are
+    // left to the callers to report, uncached
+    if let Ok(header) = BlockHeader::from_any_consensus(Cow::Owned(block)) {
+      self.headers.insert(header);
+    }
+  }
+
+  /// Tell the validator `block_id` is invalid, and forget its header
+  pub fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+    self.headers.invalidate(&block_id);
+    self.service.fail_block(block_id)
+  }
+
+  pub fn cache_stats(&self) -> CacheStats {
+    self.headers.stats()
+  }
+
+  /// `get_blocks` calls made to the validator so far
+  pub fn fetches(&self) -> u64 {
+    self.fetches
   }
 }
 
 #[cfg(test)]
 pub mod tests {
   use crate::consensus::engine::{Block, BlockId, Error, PeerId};
+  use crate::simulator::{BlockStore, SimulatedService};
   pub use sawtooth_sdk::consensus::engine::StartupState;
   pub use sawtooth_sdk::consensus::service::*;
   use std::collections::hash_map::HashMap;
@@ -112,4 +233,152 @@ pub mod tests {
       Ok(Default::default())
     }
   }
+
+  #[test]
+  fn lookups_are_cached_until_the_block_fails() -> Result<(), Error> {
+    let store = BlockStore::new();
+    store.insert(Block {
+      block_id: vec![1],
+      ..Block::default()
+    });
+    let mut service = super::PowService::new(Box::new(SimulatedService::new(store, vec![])));
+
+    assert_eq!(service.get_block(&[1])?.block_id, vec![1]);
+    assert_eq!(service.get_block(&[1])?.block_id, vec![1]);
+    assert!(service.get_block(&[2]).is_err());
+    let stats = service.cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.len), (1, 2, 1));
+
+    service.fail_block(vec![1])?;
+    assert_eq!(service.cache_stats().len, 0);
+    service.get_block(&[1])?;
+    assert_eq!(service.cache_stats().misses, 3);
+
+    Ok(())
+  }
+
+  #[test]
+  fn blocks_from_before_the_switch_to_pow_are_cached() -> Result<(), Error> {
+    let store = BlockStore::new();
+    store.insert(Block {
+      block_id: vec![1],
+      previous_id: store.genesis().block_id,
+      block_num: 1,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    });
+    store.insert(Block {
+      block_id: vec![2],
+      previous_id: vec![1],
+      block_num: 2,
+      payload: b"PoW:x".to_vec(),
+      ..Block::default()
+    });
+    let mut service = super::PowService::new(Box::new(SimulatedService::new(store, vec![])));
+
+    for _ in 0..2 {
+      service.get_block(&[1])?;
+      service.get_block(&[2])?;
+    }
+    let stats = service.cache_stats();
+    // the broken PoW payload is fetched again, the pre-PoW block is not
+    assert_eq!((stats.hits, stats.misses), (1, 3));
+
+    Ok(())
+  }
+
+  #[test]
+  fn headers_are_parsed_once_and_say_why_they_cant_be_read() {
+    use crate::block::{AncestorError, BlockConsensus};
+
+    let store = BlockStore::new();
+    store.insert(Block {
+      block_id: vec![1],
+      previous_id: store.genesis().block_id,
+      block_num: 1,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    });
+    store.insert(Block {
+      block_id: vec![2],
+      previous_id: vec![1],
+      block_num: 2,
+      payload: BlockConsensus::serialize(7, 1000.0, 0),
+      ..Block::default()
+    });
+    store.insert(Block {
+      block_id: vec![3],
+      previous_id: vec![2],
+      block_num: 3,
+      payload: b"PoW:x".to_vec(),
+      ..Block::default()
+    });
+    let mut service = super::PowService::new(Box::new(SimulatedService::new(store, vec![])));
+
+    for _ in 0..2 {
+      let header = service.get_header(&[2]).unwrap();
+      assert_eq!(header.consensus.expected_difficulty, 7);
+      assert!(!service.get_header(&[1]).unwrap().consensus.is_pow());
+    }
+    assert_eq!(service.fetches(), 2);
+    let stats = service.cache_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+
+    assert!(matches!(
+      service.get_header(&[3]),
+      Err(AncestorError::Unparsable(id, _)) if id == [3]
+    ));
+    assert!(matches!(
+      service.get_header(&[4]),
+      Err(AncestorError::UnknownBlock(id, _)) if id == [4]
+    ));
+  }
+
+  #[test]
+  fn a_walk_down_a_known_chain_is_fetched_in_batches() -> Result<(), Error> {
+    use super::PREFETCH_BATCH;
+    use crate::block::BlockConsensus;
+    use crate::node::HeaderCache;
+
+    let store = BlockStore::new();
+    let mut previous_id: BlockId = store.genesis().block_id;
+    for n in 1..=60u8 {
+      store.insert(Block {
+        block_id: vec![n],
+        previous_id,
+        block_num: n as u64,
+        payload: BlockConsensus::serialize(1, n as f64, 0),
+        ..Block::default()
+      });
+      previous_id = vec![n];
+    }
+    let service = SimulatedService::new(store.clone(), vec![]);
+    let mut service =
+      super::PowService::with_cache(Box::new(service), HeaderCache::new(PREFETCH_BATCH));
+    let walk = |service: &mut super::PowService| -> Result<u64, Error> {
+      let fetches: u64 = service.fetches();
+      let mut block: Block = service.get_block(&[60])?;
+      while block.block_num > 0 {
+        block = service.get_block(&block.previous_id)?;
+      }
+      Ok(service.fetches() - fetches)
+    };
+
+    // nothing is known of the chain, each block is found through its successor
+    assert_eq!(walk(&mut service)?, 61);
+
+    let mut service = super::PowService::with_cache(
+      Box::new(SimulatedService::new(store.clone(), vec![])),
+      HeaderCache::new(PREFETCH_BATCH),
+    );
+    for n in 1..=60u8 {
+      service.record(&store.get(&[n]).unwrap());
+    }
+    // none of the blocks was looked up, but the node was shown each of them as it arrived
+    assert_eq!(walk(&mut service)?, 4);
+    // the headers are long evicted, the ancestors of each miss still come along with it
+    assert_eq!(walk(&mut service)?, 4);
+
+    Ok(())
+  }
 }
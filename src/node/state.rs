@@ -1,14 +1,21 @@
 use std::collections::BTreeSet;
 
-use crate::block::BlockId;
+use crate::block::{Block, BlockId};
 use crate::node::Guard;
 use crate::node::PeerId;
+use crate::primitives::CCTimestamp;
 
 #[derive(Debug, Default)]
 pub struct PowState {
   pub chain_head: BlockId,
   pub peer_id: PeerId,
   pub guards: BTreeSet<Guard>,
+  /// New blocks whose consensus checks are retried on the next commit, with the attempts made
+  pub deferred: Vec<(Block, u32)>,
+  /// New blocks dated too far ahead of our clock, with the time they are checked again at
+  pub postponed: Vec<(Block, CCTimestamp)>,
+  /// Chain head whose mining challenge couldn't be built yet, it is built again before publishing
+  pub unmined: Option<BlockId>,
 }
 
 impl PowState {
@@ -1,3 +1,5 @@
+use byteorder::{BigEndian, ByteOrder};
+
 macro_rules! impl_hash {
   ($name:ident, $size:expr) => {
     #[derive(Clone, Copy)]
@@ -10,11 +12,11 @@ macro_rules! impl_hash {
       pub const SIZE: usize = $size;
 
       pub const MIN: Self = Self {
-        inner: [u8::min_value(); $size],
+        inner: [u8::MIN; $size],
       };
 
       pub const MAX: Self = Self {
-        inner: [u8::max_value(); $size],
+        inner: [u8::MAX; $size],
       };
 
       pub const fn new() -> Self {
@@ -98,7 +100,7 @@ macro_rules! impl_hash {
 
     impl PartialOrd for $name {
       fn partial_cmp(&self, other: &Self) -> Option<::std::cmp::Ordering> {
-        (&**self).partial_cmp(&**other)
+        Some(self.cmp(other))
       }
     }
 
@@ -117,3 +119,344 @@ macro_rules! impl_hash {
 }
 
 impl_hash!(H256, 32);
+
+/// Big-endian 256-bit targets, as used by `DifficultyMode::Target`
+impl H256 {
+  /// Decode a compact "bits" target: one byte of size followed by a 3 byte mantissa,
+  /// `target = mantissa * 256^(size - 3)`. Negative mantissas decode to zero, overflows to `MAX`.
+  pub fn from_compact(bits: u32) -> Self {
+    let size: i64 = (bits >> 24) as i64;
+    let mantissa: u32 = bits & 0x007f_ffff;
+    let mut target: Self = Self::new();
+
+    if bits & 0x0080_0000 != 0 || mantissa == 0 {
+      return target;
+    }
+
+    for (offset, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+      let index: i64 = Self::SIZE as i64 - size + offset as i64;
+      if index < 0 {
+        if *byte != 0 {
+          return Self::MAX;
+        }
+      } else if index < Self::SIZE as i64 {
+        target[index as usize] = *byte;
+      }
+    }
+
+    target
+  }
+
+  /// Encode as compact "bits", keeping the 3 most significant bytes
+  pub fn to_compact(&self) -> u32 {
+    let first: usize = match self.iter().position(|byte| *byte != 0) {
+      Some(first) => first,
+      None => return 0,
+    };
+    let mut size: u32 = (Self::SIZE - first) as u32;
+    let mut mantissa: [u8; 4] = [0; 4];
+    for (offset, byte) in self[first..].iter().take(3).enumerate() {
+      mantissa[offset + 1] = *byte;
+    }
+    let mut mantissa: u32 = u32::from_be_bytes(mantissa);
+
+    // the top mantissa bit is a sign, move it to the next byte
+    if mantissa & 0x0080_0000 != 0 {
+      mantissa >>= 8;
+      size += 1;
+    }
+
+    (size << 24) | mantissa
+  }
+
+  /// Approximate value, for reporting only: consensus sticks to the integer methods below
+  pub fn to_f64(&self) -> f64 {
+    self
+      .iter()
+      .fold(0.0, |value, byte| value * 256.0 + *byte as f64)
+  }
+}
+
+/// Unsigned 256-bit arithmetic, used for targets and amounts of work.
+///
+/// Nothing here panics or wraps: results that don't fit saturate at `MAX`.
+impl H256 {
+  pub fn from_u64(value: u64) -> Self {
+    let mut this: Self = Self::new();
+    BigEndian::write_u64(&mut this[24..], value);
+    this
+  }
+
+  /// The value, or `u64::MAX` if it doesn't fit
+  pub fn saturating_u64(&self) -> u64 {
+    if self[..24].iter().any(|byte| *byte != 0) {
+      u64::MAX
+    } else {
+      BigEndian::read_u64(&self[24..])
+    }
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.iter().all(|byte| *byte == 0)
+  }
+
+  pub fn leading_zeros(&self) -> u32 {
+    match self.iter().position(|byte| *byte != 0) {
+      Some(index) => index as u32 * 8 + self[index].leading_zeros(),
+      None => Self::SIZE as u32 * 8,
+    }
+  }
+
+  pub fn saturating_add(&self, other: &Self) -> Self {
+    let (lhs, rhs) = (self.limbs(), other.limbs());
+    let mut sum: [u64; 4] = [0; 4];
+    let mut carry: bool = false;
+
+    for index in (0..4).rev() {
+      let (value, first) = lhs[index].overflowing_add(rhs[index]);
+      let (value, second) = value.overflowing_add(carry as u64);
+      sum[index] = value;
+      carry = first || second;
+    }
+
+    if carry {
+      Self::MAX
+    } else {
+      Self::from_limbs(sum)
+    }
+  }
+
+  pub fn saturating_shl(&self, shift: u32) -> Self {
+    if self.is_zero() {
+      *self
+    } else if shift > self.leading_zeros() {
+      Self::MAX
+    } else {
+      Self::from_limbs(shl_limbs(self.limbs(), shift))
+    }
+  }
+
+  /// `self * mul / div`, rounded down, without overflowing in between. A zero `div` saturates.
+  pub fn mul_div(&self, mul: u64, div: u64) -> Self {
+    if div == 0 {
+      return Self::MAX;
+    }
+
+    // 320-bit product, most significant limb first
+    let mut product: [u64; 5] = [0; 5];
+    let mut carry: u128 = 0;
+    for (index, limb) in self.limbs().iter().enumerate().rev() {
+      let value: u128 = *limb as u128 * mul as u128 + carry;
+      product[index + 1] = value as u64;
+      carry = value >> 64;
+    }
+    product[0] = carry as u64;
+
+    let mut remainder: u128 = 0;
+    for limb in product.iter_mut() {
+      let value: u128 = (remainder << 64) | *limb as u128;
+      *limb = (value / div as u128) as u64;
+      remainder = value % div as u128;
+    }
+
+    if product[0] != 0 {
+      Self::MAX
+    } else {
+      Self::from_limbs([product[1], product[2], product[3], product[4]])
+    }
+  }
+
+  /// `self / divisor`, rounded down. A zero `divisor` saturates.
+  pub fn saturating_div(&self, divisor: &Self) -> Self {
+    if divisor.is_zero() {
+      return Self::MAX;
+    }
+
+    let dividend: [u64; 4] = self.limbs();
+    let divisor: [u64; 4] = divisor.limbs();
+    let mut quotient: [u64; 4] = [0; 4];
+    let mut remainder: [u64; 4] = [0; 4];
+
+    // shift-subtract long division, one bit at a time from the top
+    for bit in 0..256 {
+      let (word, offset) = (bit / 64, 63 - bit % 64);
+      // the remainder is below the divisor, so a bit shifted out means it exceeds it
+      let overflow: bool = remainder[0] >> 63 != 0;
+      remainder = shl_limbs(remainder, 1);
+      remainder[3] |= (dividend[word] >> offset) & 1;
+
+      if overflow || remainder >= divisor {
+        remainder = sub_limbs(remainder, divisor);
+        quotient[word] |= 1 << offset;
+      }
+    }
+
+    Self::from_limbs(quotient)
+  }
+
+  /// Big-endian 64-bit limbs, most significant first
+  fn limbs(&self) -> [u64; 4] {
+    let mut limbs: [u64; 4] = [0; 4];
+    for (limb, bytes) in limbs.iter_mut().zip(self.chunks_exact(8)) {
+      *limb = BigEndian::read_u64(bytes);
+    }
+    limbs
+  }
+
+  fn from_limbs(limbs: [u64; 4]) -> Self {
+    let mut this: Self = Self::new();
+    for (limb, bytes) in limbs.iter().zip(this.chunks_exact_mut(8)) {
+      BigEndian::write_u64(bytes, *limb);
+    }
+    this
+  }
+}
+
+impl ::std::ops::Shr<u32> for H256 {
+  type Output = Self;
+
+  fn shr(self, shift: u32) -> Self {
+    if shift >= 256 {
+      return Self::MIN;
+    }
+
+    let limbs: [u64; 4] = self.limbs();
+    let (words, bits) = ((shift / 64) as usize, shift % 64);
+    let mut shifted: [u64; 4] = [0; 4];
+    for index in words..4 {
+      shifted[index] = limbs[index - words] >> bits;
+      if bits > 0 && index > words {
+        shifted[index] |= limbs[index - words - 1] << (64 - bits);
+      }
+    }
+
+    Self::from_limbs(shifted)
+  }
+}
+
+impl ::std::ops::Not for H256 {
+  type Output = Self;
+
+  fn not(self) -> Self {
+    let mut this: Self = self;
+    this.iter_mut().for_each(|byte| *byte = !*byte);
+    this
+  }
+}
+
+/// Shift left, dropping the bits shifted out
+fn shl_limbs(limbs: [u64; 4], shift: u32) -> [u64; 4] {
+  if shift >= 256 {
+    return [0; 4];
+  }
+
+  let (words, bits) = ((shift / 64) as usize, shift % 64);
+  let mut shifted: [u64; 4] = [0; 4];
+  for index in 0..4 - words {
+    shifted[index] = limbs[index + words] << bits;
+    if bits > 0 && index + words < 3 {
+      shifted[index] |= limbs[index + words + 1] >> (64 - bits);
+    }
+  }
+
+  shifted
+}
+
+/// `lhs - rhs`, wrapping
+fn sub_limbs(lhs: [u64; 4], rhs: [u64; 4]) -> [u64; 4] {
+  let mut difference: [u64; 4] = [0; 4];
+  let mut borrow: bool = false;
+
+  for index in (0..4).rev() {
+    let (value, first) = lhs[index].overflowing_sub(rhs[index]);
+    let (value, second) = value.overflowing_sub(borrow as u64);
+    difference[index] = value;
+    borrow = first || second;
+  }
+
+  difference
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn compact_round_trips() {
+    let target = H256::from_compact(0x1d00_ffff);
+    assert_eq!(&target[..6], &[0, 0, 0, 0, 0xff, 0xff]);
+    assert!(target[6..].iter().all(|byte| *byte == 0));
+    assert_eq!(target.to_compact(), 0x1d00_ffff);
+
+    assert_eq!(H256::from_compact(0x0312_3456).to_compact(), 0x0312_3456);
+    assert_eq!(H256::from_compact(0x0112_3456)[31], 0x12);
+  }
+
+  #[test]
+  fn compact_edge_cases() {
+    assert_eq!(H256::from_compact(0), H256::MIN);
+    assert_eq!(H256::from_compact(0x0480_0000), H256::MIN);
+    assert_eq!(H256::from_compact(0xff12_3456), H256::MAX);
+    assert_eq!(H256::MIN.to_compact(), 0);
+    // a leading 0x80 byte would read as negative, it moves to the next byte
+    assert_eq!(H256::from_compact(0x2100_ffff).to_compact(), 0x2100_ffff);
+    assert_eq!(H256::MAX.to_compact(), 0x2100_ffff);
+  }
+
+  #[test]
+  fn small_values_round_trip() {
+    assert_eq!(H256::from_u64(258)[30..], [1, 2]);
+    assert_eq!(H256::from_u64(258).saturating_u64(), 258);
+    assert_eq!(H256::MAX.saturating_u64(), u64::MAX);
+    assert_eq!(H256::from_u64(1).leading_zeros(), 255);
+    assert_eq!(H256::MIN.leading_zeros(), 256);
+    assert_eq!(
+      H256::from_compact(0x1d00_ffff).to_f64(),
+      65535.0 * 2f64.powi(208)
+    );
+  }
+
+  #[test]
+  fn arithmetic_saturates_instead_of_wrapping() {
+    let one = H256::from_u64(1);
+    assert_eq!(H256::MAX.saturating_add(&one), H256::MAX);
+    assert_eq!(one.saturating_shl(255)[0], 0x80);
+    assert_eq!(one.saturating_shl(256), H256::MAX);
+    assert_eq!(H256::MIN.saturating_shl(300), H256::MIN);
+    assert_eq!(H256::MAX.mul_div(2, 1), H256::MAX);
+    assert_eq!(one.mul_div(1, 0), H256::MAX);
+    assert_eq!(one.saturating_div(&H256::MIN), H256::MAX);
+    assert_eq!(H256::MAX >> 256, H256::MIN);
+    assert_eq!(!H256::MIN, H256::MAX);
+  }
+
+  #[test]
+  fn arithmetic_crosses_limbs() {
+    let carry = H256::from_u64(u64::MAX).saturating_add(&H256::from_u64(1));
+    assert_eq!(carry, H256::from_u64(1).saturating_shl(64));
+    assert_eq!(carry >> 1, H256::from_u64(1 << 63));
+    assert_eq!(
+      H256::from_u64(0xff).saturating_shl(60) >> 60,
+      H256::from_u64(0xff)
+    );
+
+    // (2^255 - 1) * 6 / 4 doesn't fit until divided
+    let half = H256::MAX >> 1;
+    assert_eq!(half.mul_div(6, 4), half.saturating_add(&(half >> 1)));
+
+    let target = H256::from_compact(0x1d00_ffff);
+    assert_eq!(
+      target.saturating_div(&H256::from_u64(0xffff)),
+      H256::from_u64(1).saturating_shl(208)
+    );
+    assert_eq!(
+      H256::MAX.saturating_div(&target),
+      H256::from_u64(0x1_0001_0001)
+    );
+    assert_eq!(H256::MAX.saturating_div(&H256::MAX), H256::from_u64(1));
+    assert_eq!(
+      H256::from_u64(7).saturating_div(&H256::from_u64(2)),
+      H256::from_u64(3)
+    );
+  }
+}
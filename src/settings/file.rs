@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use toml::Value;
+
+/// Parse a configuration file, whose settings are top-level keys set to a string, a number, a
+/// boolean or an array of those.
+///
+/// Values are kept as text, arrays joined with commas, which is how on-chain settings and
+/// environment variables spell them too.
+pub fn parse_toml(text: &str) -> Result<BTreeMap<String, String>, String> {
+  let table = match text.parse::<Value>() {
+    Ok(Value::Table(table)) => table,
+    Ok(_) => return Err("expected key = value lines".into()),
+    Err(error) => return Err(error.to_string()),
+  };
+
+  table
+    .into_iter()
+    .map(|(key, value)| {
+      let text: String = match value {
+        Value::Array(items) => items
+          .into_iter()
+          .map(|item| to_text(&key, item))
+          .collect::<Result<Vec<String>, String>>()?
+          .join(","),
+        value => to_text(&key, value)?,
+      };
+      Ok((key, text))
+    })
+    .collect()
+}
+
+fn to_text(key: &str, value: Value) -> Result<String, String> {
+  match value {
+    Value::String(string) => Ok(string),
+    Value::Integer(integer) => Ok(integer.to_string()),
+    Value::Float(float) => Ok(float.to_string()),
+    Value::Boolean(boolean) => Ok(boolean.to_string()),
+    Value::Table(_) => Err(format!("{} is a table, settings are top-level keys", key)),
+    value => Err(format!("unsupported value for {}: {}", key, value)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn values_are_read_as_text() {
+    let values = parse_toml(
+      r#"
+      # local settings
+      endpoint = "tcp://validator:5050"  # a comment
+      worker_threads = 4
+      strict_consensus = true
+      difficulty_mode = '0:leading_zeros, 100:target'
+      log_level = ["info", "ccconsensus::node=debug"]
+      empty = []
+      "#,
+    )
+    .unwrap();
+
+    assert_eq!(values["endpoint"], "tcp://validator:5050");
+    assert_eq!(values["worker_threads"], "4");
+    assert_eq!(values["strict_consensus"], "true");
+    assert_eq!(values["difficulty_mode"], "0:leading_zeros, 100:target");
+    assert_eq!(values["log_level"], "info,ccconsensus::node=debug");
+    assert_eq!(values["empty"], "");
+  }
+
+  #[test]
+  fn malformed_files_are_reported_with_the_line() {
+    let error = parse_toml("a = 1\nendpoint = localhost").unwrap_err();
+    assert!(error.contains("line 2"), "{}", error);
+    assert!(parse_toml("[pow]\na = 1").is_err());
+    assert!(parse_toml("a = [[1]]").is_err());
+    assert!(parse_toml("a = 1\na = 2").is_err());
+    assert!(parse_toml("a = \"open").is_err());
+    assert!(parse_toml("a = 1 2").is_err());
+  }
+}
@@ -0,0 +1,5 @@
+mod file;
+mod settings;
+
+pub use self::file::*;
+pub use self::settings::*;
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::node::PowConfig;
+use crate::settings::parse_toml;
+
+/// Environment variables named after a setting with this prefix, e.g. `CCCONSENSUS_WORKER_THREADS`
+pub const ENV_PREFIX: &str = "CCCONSENSUS_";
+
+/// Settings of the binary rather than of `PowConfig`
+pub const LOCAL_KEYS: &[&str] = &[
+  "config",
+  "endpoint",
+  "log_level",
+  "log_format",
+  "log_file",
+  "log_rotation",
+  "metrics_address",
+  "metrics_port",
+];
+
+/// Settings read from a TOML file or the environment, by key.
+///
+/// Keys are `LOCAL_KEYS` and the names of the `PowConfig` fields. The binary resolves them as
+/// CLI > env > file > on-chain > defaults: the `PowConfig` fields set here are pinned, and
+/// `PowConfig::load` only reads the others from the chain.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Settings {
+  values: BTreeMap<String, String>,
+}
+
+impl Settings {
+  pub fn from_file(path: &Path) -> Result<Self> {
+    let text: String = fs::read_to_string(path)
+      .map_err(|error| anyhow!("Cannot read {}: {}", path.display(), error))?;
+    let values = parse_toml(&text).map_err(|error| anyhow!("{}, {}", path.display(), error))?;
+
+    Ok(Self { values })
+  }
+
+  /// The variables named `ENV_PREFIX` followed by an upper-case key
+  pub fn from_env(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+    let values = vars
+      .into_iter()
+      .filter_map(|(name, value)| {
+        name
+          .strip_prefix(ENV_PREFIX)
+          .map(|key| (key.to_ascii_lowercase(), value))
+      })
+      .collect();
+
+    Self { values }
+  }
+
+  /// These settings, overridden by those of `other`
+  pub fn merge(mut self, other: Settings) -> Self {
+    self.values.extend(other.values);
+    self
+  }
+
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self.values.get(key).map(String::as_str)
+  }
+
+  /// The default `PowConfig`, with every field these settings name
+  pub fn pow_config(&self) -> Result<PowConfig> {
+    let mut config: PowConfig = PowConfig::new();
+
+    for (key, value) in self.values.iter() {
+      if !LOCAL_KEYS.contains(&key.as_str()) {
+        config.set(key, value).map_err(|error| anyhow!(error))?;
+      }
+    }
+
+    Ok(config)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::node::Schedule;
+
+  fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn the_environment_overrides_the_file() {
+    let file = Settings {
+      values: parse_toml(
+        "worker_threads = 2\nendpoint = \"tcp://file:5050\"\nmedian_time_span = 11",
+      )
+      .unwrap(),
+    };
+    let env = Settings::from_env(vars(&[
+      ("CCCONSENSUS_WORKER_THREADS", "8"),
+      ("CCCONSENSUS_LOG_LEVEL", "debug"),
+      ("HOME", "/root"),
+    ]));
+    let settings = file.merge(env);
+
+    assert_eq!(settings.get("endpoint"), Some("tcp://file:5050"));
+    assert_eq!(settings.get("log_level"), Some("debug"));
+    assert_eq!(settings.get("home"), None);
+
+    let config = settings.pow_config().unwrap();
+    assert_eq!(config.worker_threads, 8);
+    assert_eq!(config.median_time_span, Schedule::new(11));
+    assert_eq!(
+      config.seconds_between_blocks,
+      PowConfig::new().seconds_between_blocks
+    );
+  }
+
+  #[test]
+  fn the_file_overrides_on_chain_settings() {
+    use crate::node::PowService;
+    use crate::simulator::{BlockStore, SimulatedService};
+
+    let file = Settings {
+      values: parse_toml("median_time_span = 11\nworker_threads = 4").unwrap(),
+    };
+    let mut config = file.pow_config().unwrap();
+
+    let store = BlockStore::new();
+    store.set_setting(conf_key!("median_time_span"), "5");
+    store.set_setting(conf_key!("max_future_drift"), "60");
+    let genesis = store.genesis().block_id;
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    config.load(&mut service, genesis).unwrap();
+
+    assert_eq!(config.median_time_span, Schedule::new(11));
+    // the file doesn't set it, the chain does
+    assert_eq!(config.max_future_drift, Schedule::new(60));
+    assert_eq!(config.worker_threads, 4);
+  }
+
+  #[test]
+  fn unknown_settings_are_errors() {
+    let settings = Settings::from_env(vars(&[("CCCONSENSUS_WORKERS", "8")]));
+    assert!(settings.pow_config().is_err());
+  }
+}
@@ -0,0 +1,5 @@
+mod network;
+mod service;
+
+pub use self::network::*;
+pub use self::service::*;
@@ -0,0 +1,503 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sawtooth_sdk::consensus::engine::{Error, Update};
+use std::collections::{HashMap, HashSet};
+
+use crate::block::{Block, BlockId};
+use crate::node::{EventPublishResult, PowConfig, PowNode};
+use crate::primitives::CCTimestamp;
+use crate::simulator::{BlockStore, SimulatedService};
+use crate::utils::MockClock;
+use crate::Duration;
+
+const TICK: Duration = Duration::from_millis(100);
+const DELAY: Duration = Duration::from_millis(500);
+const MINING_TIME: Duration = Duration::from_secs(10);
+/// Wall-clock time a node may take to solve a challenge before the simulation gives up
+const MINING_TIMEOUT: Duration = Duration::from_secs(30);
+/// The UTC time nodes see at virtual time zero
+const EPOCH: CCTimestamp = 1_600_000_000.0;
+
+struct SimulatedNode {
+  node: PowNode,
+  service: SimulatedService,
+  /// Blocks delivered to this node, its own included
+  known: HashSet<BlockId>,
+  /// The head `next_publish` was drawn for
+  head: BlockId,
+  next_publish: Option<Duration>,
+  published: usize,
+}
+
+struct Message {
+  deliver_at: Duration,
+  from: usize,
+  to: usize,
+  block: Block,
+}
+
+/// Several `PowNode`s sharing a `BlockStore`, exchanging blocks over a simulated network.
+///
+/// Time is virtual and read by the nodes from a shared `MockClock`: every `step` advances the clock
+/// by one tick, delivers the blocks whose delay has elapsed and lets each node publish once its
+/// mining time, drawn from an exponential distribution around `mining_time` on every new head, has
+/// passed. Messages between partitioned nodes are held
+/// until the partition heals, and a block is only delivered once its parent is.
+pub struct SimulatedNetwork {
+  store: BlockStore,
+  nodes: Vec<SimulatedNode>,
+  in_flight: Vec<Message>,
+  now: Duration,
+  clock: MockClock,
+  tick: Duration,
+  delay: Duration,
+  link_delays: HashMap<(usize, usize), Duration>,
+  groups: Vec<usize>,
+  mining_time: Duration,
+  rng: StdRng,
+  /// Every published block with its virtual publication time
+  published: HashMap<BlockId, Duration>,
+}
+
+impl SimulatedNetwork {
+  /// Start `count` nodes on the genesis of `store`, configured by its settings
+  pub fn new(count: usize, store: BlockStore, seed: u64) -> Result<Self, Error> {
+    let genesis: BlockId = store.genesis().block_id;
+    let clock = MockClock::new(EPOCH);
+
+    let nodes = (0..count)
+      .map(|index| {
+        let peer_id = format!("node-{:011}", index).into_bytes();
+        let service = SimulatedService::new(store.clone(), peer_id);
+        let node = PowNode::with_clock(PowConfig::new(), Box::new(service.clone()), clock.shared())
+          .initialize(service.startup_state())?;
+
+        Ok(SimulatedNode {
+          node,
+          service,
+          known: vec![genesis.clone()].into_iter().collect(),
+          head: BlockId::new(),
+          next_publish: None,
+          published: 0,
+        })
+      })
+      .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(Self {
+      store,
+      nodes,
+      in_flight: Vec::new(),
+      now: Duration::default(),
+      clock,
+      tick: TICK,
+      delay: DELAY,
+      link_delays: HashMap::new(),
+      groups: vec![0; count],
+      mining_time: MINING_TIME,
+      rng: StdRng::seed_from_u64(seed),
+      published: HashMap::new(),
+    })
+  }
+
+  pub fn with_tick(mut self, tick: Duration) -> Self {
+    self.tick = tick;
+    self
+  }
+
+  /// Delay of every link without its own
+  pub fn with_delay(mut self, delay: Duration) -> Self {
+    self.delay = delay;
+    self
+  }
+
+  /// Mean time for any one node to find a block
+  pub fn with_mining_time(mut self, mining_time: Duration) -> Self {
+    self.mining_time = mining_time;
+    self
+  }
+
+  pub fn set_link_delay(&mut self, from: usize, to: usize, delay: Duration) {
+    self.link_delays.insert((from, to), delay);
+  }
+
+  /// Split the network, nodes only reach the nodes of their own group. Unlisted nodes form a group of their own.
+  pub fn partition(&mut self, groups: &[&[usize]]) {
+    self.groups = vec![groups.len(); self.nodes.len()];
+    for (group, members) in groups.iter().enumerate() {
+      for member in members.iter() {
+        self.groups[*member] = group;
+      }
+    }
+  }
+
+  pub fn heal(&mut self) {
+    self.groups = vec![0; self.nodes.len()];
+  }
+
+  pub fn now(&self) -> Duration {
+    self.now
+  }
+
+  /// The clock of every node, a fixed UTC time at virtual time zero
+  pub fn clock(&self) -> &MockClock {
+    &self.clock
+  }
+
+  pub fn store(&self) -> &BlockStore {
+    &self.store
+  }
+
+  pub fn service(&self, index: usize) -> &SimulatedService {
+    &self.nodes[index].service
+  }
+
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.nodes.is_empty()
+  }
+
+  /// Advance the clock by one tick
+  pub fn step(&mut self) -> Result<(), Error> {
+    self.advance(true)
+  }
+
+  pub fn run_for(&mut self, duration: Duration) -> Result<(), Error> {
+    let end: Duration = self.now + duration;
+    while self.now < end {
+      self.step()?;
+    }
+    Ok(())
+  }
+
+  /// Stop mining and deliver every block the partitions let through
+  pub fn settle(&mut self) -> Result<(), Error> {
+    loop {
+      self.advance(false)?;
+
+      // a block whose parent is held by a partition can't arrive either
+      let next: Option<Duration> = self
+        .in_flight
+        .iter()
+        .filter(|message| self.deliverable(message))
+        .map(|message| message.deliver_at)
+        .min();
+
+      match next {
+        // skip the ticks where nothing arrives
+        Some(next) => self.now = self.now.max(next.saturating_sub(self.tick)),
+        None => return Ok(()),
+      }
+    }
+  }
+
+  fn advance(&mut self, mining: bool) -> Result<(), Error> {
+    self.now += self.tick;
+    self.clock.set(EPOCH + self.now.as_secs_f64());
+    self.deliver();
+
+    for index in 0..self.nodes.len() {
+      self.process(index)?;
+
+      if mining && self.mining_due(index) {
+        self.publish(index)?;
+        self.process(index)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Whether the sender is on the recipient's side of any partition
+  fn reachable(&self, message: &Message) -> bool {
+    self.groups[message.from] == self.groups[message.to]
+  }
+
+  /// Whether the message can be handed over once due: reachable, and its parent known to the recipient
+  fn deliverable(&self, message: &Message) -> bool {
+    self.reachable(message)
+      && self.nodes[message.to]
+        .known
+        .contains(&message.block.previous_id)
+  }
+
+  /// Hand due blocks to their recipients, parents first
+  fn deliver(&mut self) {
+    loop {
+      let position = self
+        .in_flight
+        .iter()
+        .position(|message| message.deliver_at <= self.now && self.deliverable(message));
+
+      match position {
+        Some(position) => {
+          let message: Message = self.in_flight.swap_remove(position);
+          let node: &mut SimulatedNode = &mut self.nodes[message.to];
+          if node.known.insert(message.block.block_id.clone()) {
+            node.service.push_update(Update::BlockNew(message.block));
+          }
+        }
+        None => return,
+      }
+    }
+  }
+
+  /// Feed the node every update its validator queued, and send out what it eagerly published on the way
+  fn process(&mut self, index: usize) -> Result<(), Error> {
+    let node: &mut SimulatedNode = &mut self.nodes[index];
+    while let Some(update) = node.service.next_update() {
+      node.node.handle_update(update)?;
+    }
+    self.broadcast(index);
+
+    let node: &mut SimulatedNode = &mut self.nodes[index];
+    let head: BlockId = node.service.chain_head().block_id;
+    if head != node.head {
+      node.head = head;
+      let mean: f64 = self.mining_time.as_secs_f64() * self.nodes.len() as f64;
+      let sample: f64 = -mean * (1.0 - self.rng.gen::<f64>()).ln();
+      let node: &mut SimulatedNode = &mut self.nodes[index];
+      node.next_publish = Some(self.now + Duration::from_secs_f64(sample));
+    }
+
+    Ok(())
+  }
+
+  fn mining_due(&self, index: usize) -> bool {
+    matches!(self.nodes[index].next_publish, Some(at) if at <= self.now)
+  }
+
+  /// Publish on the current head and send the block to every other node
+  fn publish(&mut self, index: usize) -> Result<(), Error> {
+    let node: &mut SimulatedNode = &mut self.nodes[index];
+    node.next_publish = None;
+
+    while let EventPublishResult::Pending = node.node.try_publish()? {
+      if !node.node.wait_for_answer(MINING_TIMEOUT) {
+        return Err(Error::InvalidState(format!(
+          "Node {} found no solution in {} seconds",
+          index,
+          MINING_TIMEOUT.as_secs()
+        )));
+      }
+    }
+
+    self.broadcast(index);
+    Ok(())
+  }
+
+  /// Send the blocks the node published since the last call to every other node
+  fn broadcast(&mut self, index: usize) {
+    let count: usize = self.nodes.len();
+    let node: &mut SimulatedNode = &mut self.nodes[index];
+
+    for block in node.service.published().into_iter().skip(node.published) {
+      node.published += 1;
+      node.known.insert(block.block_id.clone());
+      self.published.insert(block.block_id.clone(), self.now);
+
+      for to in (0..count).filter(|to| *to != index) {
+        let delay: Duration = *self.link_delays.get(&(index, to)).unwrap_or(&self.delay);
+        self.in_flight.push(Message {
+          deliver_at: self.now + delay,
+          from: index,
+          to,
+          block: block.clone(),
+        });
+      }
+    }
+  }
+
+  /// Convergence, orphans and block intervals, measured along the chain of the first node
+  pub fn report(&self) -> NetworkReport {
+    let heads: Vec<Block> = self
+      .nodes
+      .iter()
+      .map(|node| node.service.chain_head())
+      .collect();
+
+    let mut chain: Vec<Block> = Vec::new();
+    let mut block: Option<Block> = Some(heads[0].clone());
+    while let Some(current) = block.filter(|block| block.block_num > 0) {
+      block = self.store.get(&current.previous_id);
+      chain.push(current);
+    }
+    chain.reverse();
+
+    let main: HashSet<&BlockId> = chain.iter().map(|block| &block.block_id).collect();
+    let orphans: usize = self
+      .published
+      .keys()
+      .filter(|block_id| !main.contains(block_id))
+      .count();
+
+    let times: Vec<Duration> = chain
+      .iter()
+      .filter_map(|block| self.published.get(&block.block_id).copied())
+      .collect();
+    let block_intervals: Vec<Duration> = times
+      .windows(2)
+      .map(|pair| pair[1].saturating_sub(pair[0]))
+      .collect();
+
+    NetworkReport {
+      converged: heads.iter().all(|head| head.block_id == heads[0].block_id),
+      height: heads[0].block_num,
+      heads: heads.into_iter().map(|head| head.block_id).collect(),
+      published: self.published.len(),
+      orphans,
+      block_intervals,
+    }
+  }
+}
+
+/// The state of a `SimulatedNetwork`, see `SimulatedNetwork::report`
+#[derive(Clone, Debug)]
+pub struct NetworkReport {
+  /// Whether every node has the same chain head
+  pub converged: bool,
+  pub heads: Vec<BlockId>,
+  /// Height of the first node's head
+  pub height: u64,
+  pub published: usize,
+  /// Published blocks off the first node's chain
+  pub orphans: usize,
+  /// Virtual time between consecutive blocks of the first node's chain
+  pub block_intervals: Vec<Duration>,
+}
+
+impl NetworkReport {
+  pub fn orphan_rate(&self) -> f64 {
+    if self.published == 0 {
+      0.0
+    } else {
+      self.orphans as f64 / self.published as f64
+    }
+  }
+
+  pub fn mean_block_interval(&self) -> Option<Duration> {
+    if self.block_intervals.is_empty() {
+      None
+    } else {
+      Some(self.block_intervals.iter().sum::<Duration>() / self.block_intervals.len() as u32)
+    }
+  }
+}
+
+#[cfg(all(test, not(feature = "test-futures")))]
+mod tests {
+  use super::*;
+  use crate::block::BlockConsensus;
+  use crate::utils::Clock;
+
+  fn store() -> BlockStore {
+    let store = BlockStore::new();
+    store.set_setting(conf_key!("initial_difficulty"), "1");
+    // forks of equal work are settled the same way on every node once they see them both
+    store.set_setting(conf_key!("tie_break"), "lowest_hash");
+    store
+  }
+
+  #[test]
+  fn a_connected_network_converges() -> Result<(), Error> {
+    let mut network = SimulatedNetwork::new(3, store(), 7)?;
+    network.run_for(Duration::from_secs(120))?;
+    network.settle()?;
+
+    let report = network.report();
+    assert!(report.converged, "{:?}", report);
+    assert!(report.height > 0);
+    assert_eq!(report.published, report.height as usize + report.orphans);
+    assert_eq!(report.block_intervals.len() as u64, report.height - 1);
+    assert!((0.0..1.0).contains(&report.orphan_rate()));
+
+    // blocks are stamped with virtual time
+    let head: Block = network.service(0).chain_head();
+    let timestamp = BlockConsensus::deserialize(&head.payload)
+      .unwrap()
+      .timestamp;
+    assert!((EPOCH..=network.clock().now()).contains(&timestamp));
+
+    Ok(())
+  }
+
+  #[test]
+  fn partitions_split_the_chain_until_they_heal() -> Result<(), Error> {
+    let mut network =
+      SimulatedNetwork::new(4, store(), 11)?.with_mining_time(Duration::from_secs(5));
+    network.partition(&[&[0, 1], &[2, 3]]);
+    network.run_for(Duration::from_secs(120))?;
+    network.settle()?;
+
+    let report = network.report();
+    assert!(!report.converged);
+    assert_eq!(report.heads[0], report.heads[1]);
+    assert_eq!(report.heads[2], report.heads[3]);
+
+    network.heal();
+    network.settle()?;
+
+    let report = network.report();
+    assert!(report.converged, "{:?}", report);
+    assert!(report.orphans > 0);
+
+    Ok(())
+  }
+
+  #[test]
+  fn settling_stops_at_a_block_whose_parent_is_held_by_a_partition() -> Result<(), Error> {
+    let store = store();
+    let mut network = SimulatedNetwork::new(3, store.clone(), 5)?;
+    let parent = Block {
+      block_id: vec![1; 8],
+      previous_id: store.genesis().block_id,
+      block_num: 1,
+      ..Block::default()
+    };
+    let child = Block {
+      block_id: vec![2; 8],
+      previous_id: parent.block_id.clone(),
+      block_num: 2,
+      ..Block::default()
+    };
+
+    // node 1 got the parent from node 0, node 2 didn't before the partition
+    network.partition(&[&[1, 2], &[0]]);
+    for (from, block) in [(0, parent), (1, child)] {
+      network.in_flight.push(Message {
+        deliver_at: network.now(),
+        from,
+        to: 2,
+        block,
+      });
+    }
+
+    network.settle()?;
+    assert_eq!(network.in_flight.len(), 2);
+
+    network.heal();
+    network.settle()?;
+    assert!(network.in_flight.is_empty());
+
+    Ok(())
+  }
+
+  #[test]
+  fn blocks_wait_for_their_delay() -> Result<(), Error> {
+    let mut network = SimulatedNetwork::new(2, store(), 3)?
+      .with_mining_time(Duration::from_secs(1))
+      .with_delay(Duration::from_secs(3600));
+
+    network.run_for(Duration::from_secs(30))?;
+    let report = network.report();
+    assert!(!report.converged);
+
+    network.settle()?;
+    assert!(network.now() >= Duration::from_secs(3600));
+    assert!(network.report().converged);
+
+    Ok(())
+  }
+}
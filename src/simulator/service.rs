@@ -0,0 +1,444 @@
+use sawtooth_sdk::consensus::{
+  engine::{Error, PeerInfo, StartupState, Update},
+  service::Service,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::block::{Block, BlockId};
+use crate::node::PeerId;
+use crate::utils::to_hex;
+
+/// The `previous_id` of the genesis block
+const NULL_BLOCK_IDENTIFIER: [u8; 8] = [0; 8];
+
+/// Blocks and on-chain settings, shared by every `SimulatedService` built on it
+#[derive(Clone)]
+pub struct BlockStore {
+  inner: Arc<Mutex<StoreInner>>,
+}
+
+struct StoreInner {
+  blocks: HashMap<BlockId, Block>,
+  settings: HashMap<String, String>,
+  genesis_id: BlockId,
+}
+
+impl BlockStore {
+  /// A store holding only a genesis block
+  pub fn new() -> Self {
+    let genesis: Block = Block {
+      block_id: Sha256::digest(b"genesis").to_vec(),
+      previous_id: NULL_BLOCK_IDENTIFIER.to_vec(),
+      block_num: 0,
+      ..Block::default()
+    };
+    let genesis_id: BlockId = genesis.block_id.clone();
+
+    Self {
+      inner: Arc::new(Mutex::new(StoreInner {
+        blocks: vec![(genesis_id.clone(), genesis)].into_iter().collect(),
+        settings: HashMap::new(),
+        genesis_id,
+      })),
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, StoreInner> {
+    self.inner.lock().expect("Block store lock")
+  }
+
+  pub fn genesis(&self) -> Block {
+    let inner = self.lock();
+    inner.blocks[&inner.genesis_id].clone()
+  }
+
+  pub fn get(&self, block_id: &[u8]) -> Option<Block> {
+    self.lock().blocks.get(block_id).cloned()
+  }
+
+  pub fn insert(&self, block: Block) {
+    self.lock().blocks.insert(block.block_id.clone(), block);
+  }
+
+  pub fn len(&self) -> usize {
+    self.lock().blocks.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Set an on-chain setting, e.g. `sawtooth.consensus.pow.initial_difficulty`, for every block
+  pub fn set_setting(&self, key: &str, value: &str) {
+    self
+      .lock()
+      .settings
+      .insert(key.to_owned(), value.to_owned());
+  }
+
+  /// Build the child of `previous_id` that `signer_id` finalized with `payload`
+  fn create_block(
+    &self,
+    previous_id: &[u8],
+    signer_id: &[u8],
+    payload: Vec<u8>,
+  ) -> Result<Block, Error> {
+    let parent: Block = self
+      .get(previous_id)
+      .ok_or_else(|| Error::UnknownBlock(to_hex(previous_id)))?;
+    let block_num: u64 = parent.block_num + 1;
+
+    let mut hasher = Sha256::new();
+    hasher.update(previous_id);
+    hasher.update(signer_id);
+    hasher.update(block_num.to_be_bytes());
+    hasher.update(&payload);
+
+    let block: Block = Block {
+      block_id: hasher.finalize().to_vec(),
+      previous_id: previous_id.to_vec(),
+      signer_id: signer_id.to_vec(),
+      block_num,
+      payload,
+      summary: Sha256::digest(previous_id).to_vec(),
+    };
+    self.insert(block.clone());
+
+    Ok(block)
+  }
+}
+
+impl Default for BlockStore {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// What the engine decided about a block it was asked to resolve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+  Commit(BlockId),
+  Ignore(BlockId),
+  Fail(BlockId),
+}
+
+/// A validator as seen by one consensus engine, backed by a `BlockStore`.
+///
+/// It builds blocks between `initialize_block` and `finalize_block`, treats every block it is asked to
+/// check as valid and queues the updates a validator would send back; drive the engine with `next_update`.
+/// Clones share the same state so that a test can keep one after boxing another for a `PowNode`.
+#[derive(Clone)]
+pub struct SimulatedService {
+  store: BlockStore,
+  inner: Arc<Mutex<ServiceInner>>,
+}
+
+struct ServiceInner {
+  peer_id: PeerId,
+  chain_head: BlockId,
+  /// The parent of the block being built, if any
+  building_on: Option<BlockId>,
+  updates: VecDeque<Update>,
+  decisions: Vec<Decision>,
+  published: Vec<Block>,
+  /// `check_blocks` requests still to refuse
+  refused_checks: u32,
+}
+
+impl SimulatedService {
+  pub fn new(store: BlockStore, peer_id: PeerId) -> Self {
+    let chain_head: BlockId = store.genesis().block_id;
+
+    Self {
+      store,
+      inner: Arc::new(Mutex::new(ServiceInner {
+        peer_id,
+        chain_head,
+        building_on: None,
+        updates: VecDeque::new(),
+        decisions: Vec::new(),
+        published: Vec::new(),
+        refused_checks: 0,
+      })),
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, ServiceInner> {
+    self.inner.lock().expect("Simulated service lock")
+  }
+
+  pub fn store(&self) -> &BlockStore {
+    &self.store
+  }
+
+  /// The startup state a validator would hand to the engine
+  pub fn startup_state(&self) -> StartupState {
+    let inner = self.lock();
+
+    StartupState {
+      chain_head: self.store.get(&inner.chain_head).expect("Chain head"),
+      peers: Vec::new(),
+      local_peer_info: PeerInfo {
+        peer_id: inner.peer_id.clone(),
+      },
+    }
+  }
+
+  pub fn chain_head(&self) -> Block {
+    self.store.get(&self.lock().chain_head).expect("Chain head")
+  }
+
+  /// The next update the validator would send to the engine
+  pub fn next_update(&self) -> Option<Update> {
+    self.lock().updates.pop_front()
+  }
+
+  /// Queue an update as if the validator sent it, e.g. a `BlockNew` published by another node
+  pub fn push_update(&self, update: Update) {
+    self.lock().updates.push_back(update);
+  }
+
+  pub fn decisions(&self) -> Vec<Decision> {
+    self.lock().decisions.clone()
+  }
+
+  /// Refuse the next `count` `check_blocks` requests, as a busy validator would
+  pub fn refuse_checks(&self, count: u32) {
+    self.lock().refused_checks = count;
+  }
+
+  /// Blocks finalized through this service, oldest first
+  pub fn published(&self) -> Vec<Block> {
+    self.lock().published.clone()
+  }
+}
+
+impl Service for SimulatedService {
+  fn send_to(
+    &mut self,
+    _peer: &PeerId,
+    _message_type: &str,
+    _payload: Vec<u8>,
+  ) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn broadcast(&mut self, _message_type: &str, _payload: Vec<u8>) -> Result<(), Error> {
+    Ok(())
+  }
+
+  fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+    let mut inner = self.lock();
+    if inner.building_on.is_some() {
+      return Err(Error::InvalidState("Block already initialized".into()));
+    }
+
+    let previous_id: BlockId = previous_id.unwrap_or_else(|| inner.chain_head.clone());
+    if self.store.get(&previous_id).is_none() {
+      return Err(Error::UnknownBlock(to_hex(&previous_id)));
+    }
+    inner.building_on = Some(previous_id);
+
+    Ok(())
+  }
+
+  fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+    match &self.lock().building_on {
+      Some(previous_id) => Ok(Sha256::digest(previous_id).to_vec()),
+      None => Err(Error::InvalidState("No block initialized".into())),
+    }
+  }
+
+  fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+    let mut inner = self.lock();
+    let previous_id: BlockId = inner
+      .building_on
+      .take()
+      .ok_or_else(|| Error::InvalidState("No block initialized".into()))?;
+
+    let block: Block = self
+      .store
+      .create_block(&previous_id, &inner.peer_id, data)?;
+    let block_id: BlockId = block.block_id.clone();
+
+    inner.published.push(block.clone());
+    inner.updates.push_back(Update::BlockNew(block));
+
+    Ok(block_id)
+  }
+
+  fn cancel_block(&mut self) -> Result<(), Error> {
+    match self.lock().building_on.take() {
+      Some(_) => Ok(()),
+      None => Err(Error::InvalidState("No block initialized".into())),
+    }
+  }
+
+  fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+    let mut inner = self.lock();
+    if inner.refused_checks > 0 {
+      inner.refused_checks -= 1;
+      return Err(Error::BlockNotReady);
+    }
+
+    for block_id in priority {
+      let update = match self.store.get(&block_id) {
+        Some(_) => Update::BlockValid(block_id),
+        None => Update::BlockInvalid(block_id),
+      };
+      inner.updates.push_back(update);
+    }
+
+    Ok(())
+  }
+
+  fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+    if self.store.get(&block_id).is_none() {
+      return Err(Error::UnknownBlock(to_hex(&block_id)));
+    }
+
+    let mut inner = self.lock();
+    inner.chain_head = block_id.clone();
+    inner.decisions.push(Decision::Commit(block_id.clone()));
+    inner.updates.push_back(Update::BlockCommit(block_id));
+
+    Ok(())
+  }
+
+  fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+    self.lock().decisions.push(Decision::Ignore(block_id));
+    Ok(())
+  }
+
+  fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+    self.lock().decisions.push(Decision::Fail(block_id));
+    Ok(())
+  }
+
+  fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+    block_ids
+      .into_iter()
+      .map(|block_id| match self.store.get(&block_id) {
+        Some(block) => Ok((block_id, block)),
+        None => Err(Error::UnknownBlock(to_hex(&block_id))),
+      })
+      .collect()
+  }
+
+  fn get_chain_head(&mut self) -> Result<Block, Error> {
+    Ok(self.chain_head())
+  }
+
+  fn get_settings(
+    &mut self,
+    _block_id: BlockId,
+    keys: Vec<String>,
+  ) -> Result<HashMap<String, String>, Error> {
+    let store = self.store.lock();
+
+    Ok(
+      keys
+        .into_iter()
+        .filter_map(|key| store.settings.get(&key).cloned().map(|value| (key, value)))
+        .collect(),
+    )
+  }
+
+  fn get_state(
+    &mut self,
+    _block_id: BlockId,
+    _addresses: Vec<String>,
+  ) -> Result<HashMap<String, Vec<u8>>, Error> {
+    Ok(HashMap::new())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn service() -> SimulatedService {
+    SimulatedService::new(BlockStore::new(), b"peer-a".to_vec())
+  }
+
+  #[test]
+  fn blocks_are_built_on_the_initialized_parent() {
+    let mut service = service();
+    let genesis = service.store().genesis();
+
+    assert!(service.finalize_block(b"payload".to_vec()).is_err());
+    service.initialize_block(None).unwrap();
+    assert!(service.initialize_block(None).is_err());
+    service.summarize_block().unwrap();
+    let block_id = service.finalize_block(b"payload".to_vec()).unwrap();
+
+    let block = service.store().get(&block_id).unwrap();
+    assert_eq!(block.previous_id, genesis.block_id);
+    assert_eq!(block.block_num, 1);
+    assert_eq!(block.signer_id, b"peer-a".to_vec());
+    assert_eq!(service.published(), vec![block]);
+    assert!(
+      matches!(service.next_update(), Some(Update::BlockNew(new)) if new.block_id == block_id)
+    );
+    assert!(service.cancel_block().is_err());
+  }
+
+  #[test]
+  fn decisions_are_recorded_and_commits_move_the_head() {
+    let mut service = service();
+    service.initialize_block(None).unwrap();
+    let block_id = service.finalize_block(b"payload".to_vec()).unwrap();
+    service.next_update();
+
+    service
+      .check_blocks(vec![block_id.clone(), b"unknown".to_vec()])
+      .unwrap();
+    assert!(matches!(service.next_update(), Some(Update::BlockValid(id)) if id == block_id));
+    assert!(matches!(
+      service.next_update(),
+      Some(Update::BlockInvalid(_))
+    ));
+
+    service.ignore_block(b"other".to_vec()).unwrap();
+    service.commit_block(block_id.clone()).unwrap();
+    assert_eq!(service.chain_head().block_id, block_id);
+    assert!(matches!(service.next_update(), Some(Update::BlockCommit(id)) if id == block_id));
+    assert_eq!(
+      service.decisions(),
+      vec![
+        Decision::Ignore(b"other".to_vec()),
+        Decision::Commit(block_id)
+      ]
+    );
+    assert!(service.commit_block(b"unknown".to_vec()).is_err());
+  }
+
+  #[test]
+  fn settings_are_served_by_key() {
+    let mut service = service();
+    service
+      .store()
+      .set_setting(conf_key!("initial_difficulty"), "3");
+
+    let settings = service
+      .get_settings(
+        Vec::new(),
+        vec![
+          conf_key!("initial_difficulty").into(),
+          conf_key!("difficulty_window").into(),
+        ],
+      )
+      .unwrap();
+    assert_eq!(settings.len(), 1);
+    assert_eq!(settings[conf_key!("initial_difficulty")], "3");
+  }
+
+  #[test]
+  fn unknown_blocks_are_errors() {
+    let mut service = service();
+    assert!(service.get_blocks(vec![b"unknown".to_vec()]).is_err());
+    assert!(service.initialize_block(Some(b"unknown".to_vec())).is_err());
+  }
+}
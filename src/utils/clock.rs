@@ -0,0 +1,165 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use crate::primitives::CCTimestamp;
+use crate::utils::utc_seconds_f64;
+use crate::Duration;
+
+/// A future resolving once a `Clock` has moved past a deadline
+pub type ClockSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Source of the current time and of timers, shared by the node, its miner and the update loop
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The time as seen by the consensus engine
+pub trait Clock: Debug + Send + Sync {
+  /// The current time, in UTC seconds
+  fn now(&self) -> CCTimestamp;
+
+  /// Resolves once `duration` has elapsed on this clock
+  fn sleep(&self, duration: Duration) -> ClockSleep;
+}
+
+/// The server time, timers run on the tokio runtime
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+  pub fn shared() -> SharedClock {
+    Arc::new(Self)
+  }
+}
+
+impl Clock for SystemClock {
+  fn now(&self) -> CCTimestamp {
+    utc_seconds_f64()
+  }
+
+  fn sleep(&self, duration: Duration) -> ClockSleep {
+    Box::pin(tokio::time::sleep(duration))
+  }
+}
+
+#[derive(Debug)]
+struct MockState {
+  now: CCTimestamp,
+  sleepers: Vec<Waker>,
+}
+
+/// A clock that only moves when told to, pending `sleep`s resolve as `advance` passes their deadline
+#[derive(Clone, Debug)]
+pub struct MockClock {
+  state: Arc<Mutex<MockState>>,
+}
+
+impl MockClock {
+  pub fn new(now: CCTimestamp) -> Self {
+    Self {
+      state: Arc::new(Mutex::new(MockState {
+        now,
+        sleepers: Vec::new(),
+      })),
+    }
+  }
+
+  pub fn shared(&self) -> SharedClock {
+    Arc::new(self.clone())
+  }
+
+  pub fn advance(&self, duration: Duration) {
+    let mut state = self.lock();
+    state.now += duration.as_secs_f64();
+    state.sleepers.drain(..).for_each(Waker::wake);
+  }
+
+  /// Number of sleeps waiting for the clock to move, lets a test advance once the code under test is idle
+  pub fn sleepers(&self) -> usize {
+    self.lock().sleepers.len()
+  }
+
+  /// Move the clock to `now`, which may be in its past
+  pub fn set(&self, now: CCTimestamp) {
+    let mut state = self.lock();
+    state.now = now;
+    state.sleepers.drain(..).for_each(Waker::wake);
+  }
+
+  fn lock(&self) -> MutexGuard<'_, MockState> {
+    self.state.lock().expect("Mock clock lock")
+  }
+}
+
+impl Default for MockClock {
+  fn default() -> Self {
+    Self::new(0.0)
+  }
+}
+
+impl Clock for MockClock {
+  fn now(&self) -> CCTimestamp {
+    self.lock().now
+  }
+
+  fn sleep(&self, duration: Duration) -> ClockSleep {
+    Box::pin(MockSleep {
+      clock: self.clone(),
+      deadline: self.now() + duration.as_secs_f64(),
+    })
+  }
+}
+
+struct MockSleep {
+  clock: MockClock,
+  deadline: CCTimestamp,
+}
+
+impl Future for MockSleep {
+  type Output = ();
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let mut state = self.clock.lock();
+    if state.now >= self.deadline {
+      Poll::Ready(())
+    } else {
+      state.sleepers.push(cx.waker().clone());
+      Poll::Pending
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use futures::FutureExt;
+
+  #[test]
+  fn mock_clock_only_moves_when_advanced() {
+    let clock = MockClock::new(1000.0);
+    assert_eq!(clock.now(), 1000.0);
+
+    clock.advance(Duration::from_millis(1500));
+    assert_eq!(clock.now(), 1001.5);
+    assert_eq!(clock.shared().now(), 1001.5);
+
+    clock.set(10.0);
+    assert_eq!(clock.now(), 10.0);
+  }
+
+  #[test]
+  fn mock_sleep_resolves_once_its_deadline_passes() {
+    let clock = MockClock::new(0.0);
+    let mut sleep = clock.sleep(Duration::from_secs(10));
+    assert!(sleep.as_mut().now_or_never().is_none());
+    assert_eq!(clock.sleepers(), 1);
+
+    clock.advance(Duration::from_secs(9));
+    assert_eq!(clock.sleepers(), 0);
+    assert!(sleep.as_mut().now_or_never().is_none());
+
+    clock.advance(Duration::from_secs(1));
+    assert!(sleep.as_mut().now_or_never().is_some());
+  }
+}
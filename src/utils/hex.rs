@@ -1,16 +1,85 @@
-use anyhow::Result;
+use std::error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Why a string isn't hex
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HexError {
+  /// Bytes take two digits each, `length` is odd
+  OddLength { length: usize },
+  /// Only ASCII digits can be hex
+  NonAscii,
+  /// The two characters at `index` aren't a hex byte
+  InvalidDigit { index: usize, digits: String },
+}
+
+impl error::Error for HexError {}
+
+impl Display for HexError {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    match self {
+      Self::OddLength { length } => write!(f, "Invalid Hex String: odd length {}", length),
+      Self::NonAscii => write!(f, "Invalid Hex String: non-ASCII characters"),
+      Self::InvalidDigit { index, digits } => {
+        write!(f, "Invalid Hex String: {:?} at {}", digits, index)
+      }
+    }
+  }
+}
 
 pub fn to_hex(bytes: &[u8]) -> String {
   bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
 }
 
-pub fn unhex(hexed: &str) -> Result<Vec<u8>> {
-  if hexed.len() % 2 != 0 {
-    return Err(anyhow!("Invalid Hex String"));
+/// The hex of the first `count` bytes, or of all of them if there are fewer
+pub fn to_hex_prefix(bytes: &[u8], count: usize) -> String {
+  to_hex(&bytes[..bytes.len().min(count)])
+}
+
+pub fn unhex(hexed: &str) -> Result<Vec<u8>, HexError> {
+  // pairs of bytes must be pairs of characters
+  if !hexed.is_ascii() {
+    return Err(HexError::NonAscii);
+  }
+  if !hexed.len().is_multiple_of(2) {
+    return Err(HexError::OddLength {
+      length: hexed.len(),
+    });
   }
 
-  (0..hexed.len() / 2)
-    .map(|index| u8::from_str_radix(&hexed[index * 2..index * 2 + 2], 16))
-    .map(|result| result.map_err(|error| error.into()))
+  (0..hexed.len())
+    .step_by(2)
+    .map(|index| {
+      let digits: &str = &hexed[index..index + 2];
+      let invalid = || HexError::InvalidDigit {
+        index,
+        digits: digits.to_string(),
+      };
+      // from_str_radix would take a sign as the first digit
+      if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(invalid());
+      }
+      u8::from_str_radix(digits, 16).map_err(|_| invalid())
+    })
     .collect()
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn short_and_non_ascii_input_is_handled() {
+    assert_eq!(to_hex_prefix(&[1, 2, 3], 8), "010203");
+    assert_eq!(to_hex_prefix(&[0xab; 12], 2), "abab");
+    assert_eq!(unhex("a\u{20ac}"), Err(HexError::NonAscii));
+    assert_eq!(unhex("0af"), Err(HexError::OddLength { length: 3 }));
+    assert_eq!(
+      unhex("0a+f"),
+      Err(HexError::InvalidDigit {
+        index: 2,
+        digits: "+f".into()
+      })
+    );
+    assert_eq!(unhex("0aff").unwrap(), vec![0x0a, 0xff]);
+  }
+}
@@ -1,5 +1,7 @@
+mod clock;
 mod hex;
 mod time;
 
+pub use self::clock::*;
 pub use self::hex::*;
 pub use self::time::*;
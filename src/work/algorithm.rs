@@ -0,0 +1,158 @@
+use sha2::Digest;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::primitives::{CCDifficulty, CCNonce, H256};
+use crate::work::{digest_score, Hasher};
+
+/// A proof-of-work scheme: how a (block_id, peer_id, nonce) triple is hashed and how the hash is scored.
+pub trait PowAlgorithm: Send {
+  /// The bytes fed to the hash function in place of the nonce
+  fn encode_nonce(&self, nonce: CCNonce) -> Vec<u8>;
+
+  /// Hash the challenge with `nonce`, as encoded by `encode_nonce`, writing the digest to `output`
+  fn hash_into(&mut self, output: &mut H256, block_id: &[u8], peer_id: &[u8], nonce: CCNonce);
+
+  /// The difficulty proven by `hash`, the higher the harder
+  fn score(&self, hash: &H256) -> CCDifficulty;
+
+  fn hash(&mut self, block_id: &[u8], peer_id: &[u8], nonce: CCNonce) -> H256 {
+    let mut output: H256 = H256::new();
+    self.hash_into(&mut output, block_id, peer_id, nonce);
+    output
+  }
+
+  fn is_valid(&self, hash: &H256, difficulty: CCDifficulty) -> (bool, CCDifficulty) {
+    let score: CCDifficulty = self.score(hash);
+    (score >= difficulty, score)
+  }
+}
+
+/// The original scheme: `sha256(block_id || peer_id || decimal(nonce))`, scored by leading zero bits.
+#[derive(Clone, Default)]
+pub struct Sha256Decimal {
+  hasher: Hasher,
+}
+
+impl PowAlgorithm for Sha256Decimal {
+  fn encode_nonce(&self, nonce: CCNonce) -> Vec<u8> {
+    nonce.to_string().into_bytes()
+  }
+
+  fn hash_into(&mut self, output: &mut H256, block_id: &[u8], peer_id: &[u8], nonce: CCNonce) {
+    let nonce: Vec<u8> = self.encode_nonce(nonce);
+    self.hasher.update(block_id);
+    self.hasher.update(peer_id);
+    self.hasher.update(nonce);
+    output.copy_from_slice(&self.hasher.finalize_reset());
+  }
+
+  fn score(&self, hash: &H256) -> CCDifficulty {
+    digest_score(hash)
+  }
+}
+
+/// `sha256(sha256(block_id || peer_id || be_bytes(nonce)))`, scored by leading zero bits.
+#[derive(Clone, Default)]
+pub struct DoubleSha256 {
+  hasher: Hasher,
+}
+
+impl PowAlgorithm for DoubleSha256 {
+  fn encode_nonce(&self, nonce: CCNonce) -> Vec<u8> {
+    nonce.to_be_bytes().to_vec()
+  }
+
+  fn hash_into(&mut self, output: &mut H256, block_id: &[u8], peer_id: &[u8], nonce: CCNonce) {
+    let nonce: Vec<u8> = self.encode_nonce(nonce);
+    self.hasher.update(block_id);
+    self.hasher.update(peer_id);
+    self.hasher.update(nonce);
+    let first = self.hasher.finalize_reset();
+    self.hasher.update(first);
+    output.copy_from_slice(&self.hasher.finalize_reset());
+  }
+
+  fn score(&self, hash: &H256) -> CCDifficulty {
+    digest_score(hash)
+  }
+}
+
+/// The on-chain name of a `PowAlgorithm`, as set in `sawtooth.consensus.pow.algorithm`
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum PowAlgorithmKind {
+  #[default]
+  Sha256Decimal,
+  DoubleSha256,
+}
+
+impl PowAlgorithmKind {
+  pub fn build(self) -> Box<dyn PowAlgorithm> {
+    match self {
+      Self::Sha256Decimal => Box::new(Sha256Decimal::default()),
+      Self::DoubleSha256 => Box::new(DoubleSha256::default()),
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Sha256Decimal => "sha256",
+      Self::DoubleSha256 => "sha256d",
+    }
+  }
+}
+
+impl FromStr for PowAlgorithmKind {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "sha256" => Ok(Self::Sha256Decimal),
+      "sha256d" => Ok(Self::DoubleSha256),
+      _ => Err(format!("Unknown PoW algorithm: {}", string)),
+    }
+  }
+}
+
+impl Display for PowAlgorithmKind {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.write_str(self.name())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::work::{get_hasher, mkhash};
+  use sha2::Sha256;
+
+  #[test]
+  fn default_algorithm_matches_mkhash() {
+    let mut algorithm = PowAlgorithmKind::default().build();
+    let expected: H256 = mkhash(&mut get_hasher(), b"block", b"peer", 12345);
+
+    assert_eq!(algorithm.hash(b"block", b"peer", 12345), expected);
+    assert_eq!(algorithm.encode_nonce(12345), b"12345".to_vec());
+  }
+
+  #[test]
+  fn double_sha256_hashes_twice() {
+    let mut algorithm = PowAlgorithmKind::DoubleSha256.build();
+    let first = Sha256::digest([&b"blockpeer"[..], &7u64.to_be_bytes()].concat());
+    let second = Sha256::digest(first);
+
+    assert_eq!(&algorithm.hash(b"block", b"peer", 7)[..], &second[..]);
+    assert_eq!(algorithm.encode_nonce(7), 7u64.to_be_bytes().to_vec());
+  }
+
+  #[test]
+  fn algorithm_names_round_trip() {
+    for kind in [
+      PowAlgorithmKind::Sha256Decimal,
+      PowAlgorithmKind::DoubleSha256,
+    ] {
+      assert_eq!(kind.to_string().parse::<PowAlgorithmKind>(), Ok(kind));
+    }
+    assert!("md5".parse::<PowAlgorithmKind>().is_err());
+  }
+}
@@ -0,0 +1,311 @@
+use crate::block::BlockId;
+use std::borrow::Cow;
+
+use crate::block::read_ancestor;
+use crate::block::AncestorError;
+use crate::block::BlockHeader;
+use crate::block::ConsensusError;
+use crate::error::PowError;
+use crate::node::PowConfig;
+use crate::node::PowService;
+use crate::primitives::{CCDifficulty, CCTimestamp, H256};
+use crate::work::{difficulty_to_work, DifficultyMode};
+
+/// The difficulty the successor of `header`, stamped `timestamp`, must claim for its own successor.
+///
+/// Fails when the ancestors it depends on can't be read, rather than guessing a difficulty that an
+/// honest block wouldn't claim.
+pub fn get_difficulty(
+  header: &BlockHeader,
+  timestamp: CCTimestamp,
+  service: &mut PowService,
+  config: &PowConfig,
+) -> Result<CCDifficulty, PowError> {
+  // The result is stored in the new block and read by its successor, whose height selects the mode.
+  let mode: DifficultyMode = *config.difficulty_mode.at(header.block_num + 2);
+
+  // PoW starts over after genesis, or after a block from before the switch to PoW
+  if header.is_genesis() || !header.consensus.is_pow() {
+    return Ok(initial_difficulty(config, header.block_num + 2));
+  }
+
+  // The new block is the one storing the result, its height selects the algorithm.
+  let algorithm = config.difficulty_algorithm.at(header.block_num + 1).build();
+  let window: u64 = algorithm.window(header, config);
+
+  let ancestors: Vec<BlockHeader> = pow_ancestors(header, service, window)?;
+  Ok(mode.encode(&algorithm.next_work(&ancestors, timestamp, config)))
+}
+
+/// `initial_difficulty`, a number of leading zero bits, as required from the block at `height`
+pub fn initial_difficulty(config: &PowConfig, height: u64) -> CCDifficulty {
+  config
+    .difficulty_mode
+    .at(height)
+    .encode(&difficulty_to_work(config.initial_difficulty))
+}
+
+/// What `predecessor` requires from its successor, anything goes after a non-PoW block
+pub fn required_difficulty(predecessor: &BlockHeader, config: &PowConfig) -> CCDifficulty {
+  if predecessor.consensus.is_pow() {
+    predecessor.consensus.expected_difficulty
+  } else {
+    config
+      .difficulty_mode
+      .at(predecessor.block_num + 1)
+      .minimum()
+  }
+}
+
+/// The work `block` requires from its successor
+pub fn required_work(block: &BlockHeader, config: &PowConfig) -> H256 {
+  config
+    .difficulty_mode
+    .at(block.block_num + 1)
+    .work(required_difficulty(block, config))
+}
+
+/// Check that `header` claims the difficulty its predecessor and timestamp call for, once
+/// `strict_difficulty` is active at its height. Blocks below it may have been accepted by nodes
+/// that computed it differently.
+///
+/// This is the same computation `Miner::mine` runs when it builds a challenge on top of `predecessor`.
+pub fn validate_expected_difficulty(
+  header: &BlockHeader,
+  predecessor: &BlockHeader,
+  service: &mut PowService,
+  config: &PowConfig,
+) -> Result<(), PowError> {
+  if !*config.strict_difficulty.at(header.block_num) {
+    return Ok(());
+  }
+
+  let expected: CCDifficulty =
+    get_difficulty(predecessor, header.consensus.timestamp, service, config)?;
+  let claimed: CCDifficulty = header.consensus.expected_difficulty;
+
+  if claimed == expected {
+    Ok(())
+  } else {
+    Err(ConsensusError::UnexpectedDifficulty { claimed, expected }.into())
+  }
+}
+
+/// Hashes per second spent by the network on the last `window` PoW blocks up to `head`, from the
+/// work they proved and the time they took. `None` without two PoW blocks to time.
+pub fn network_hash_rate(
+  head: &BlockHeader,
+  service: &mut PowService,
+  config: &PowConfig,
+  window: u64,
+) -> Option<f64> {
+  if !head.consensus.is_pow() {
+    return None;
+  }
+
+  let headers: Vec<BlockHeader> = pow_ancestors(head, service, window + 1).ok()?;
+  if headers.len() < 2 {
+    return None;
+  }
+
+  // every block proved the work its predecessor required
+  let work: f64 = headers[1..]
+    .iter()
+    .map(|predecessor| required_work(predecessor, config).to_f64())
+    .sum();
+  let elapsed: f64 =
+    headers[0].consensus.timestamp - headers[headers.len() - 1].consensus.timestamp;
+
+  (elapsed > 0.0).then(|| work / elapsed)
+}
+
+/// The parent and its PoW ancestors, at most `count` headers in total
+fn pow_ancestors<'a>(
+  parent: &BlockHeader<'a>,
+  service: &mut PowService,
+  count: u64,
+) -> Result<Vec<BlockHeader<'a>>, AncestorError> {
+  let mut headers: Vec<BlockHeader<'a>> = vec![parent.clone()];
+  let mut block_id: Cow<BlockId> = Cow::Borrowed(&parent.previous_id);
+
+  while (headers.len() as u64) < count {
+    let header: BlockHeader = match read_ancestor(service, &block_id) {
+      Ok(header) => header,
+      Err(error) if error.is_end() => break,
+      Err(error) => return Err(error),
+    };
+    block_id = Cow::Owned(header.previous_id.clone());
+    headers.push(header);
+  }
+
+  Ok(headers)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::{Block, BlockConsensus};
+  use crate::node::tests::MockService;
+  use crate::node::Schedule;
+  use crate::simulator::{BlockStore, SimulatedService};
+
+  fn block(block_num: u64, expected_difficulty: CCDifficulty) -> Block {
+    Block {
+      block_num,
+      payload: BlockConsensus::serialize(expected_difficulty, 1000.0, 0),
+      ..Block::default()
+    }
+  }
+
+  fn strict() -> PowConfig {
+    PowConfig {
+      strict_difficulty: Schedule::new(true),
+      ..PowConfig::new()
+    }
+  }
+
+  #[test]
+  fn blocks_after_genesis_claim_the_initial_difficulty() {
+    let mut service = PowService::new(Box::new(MockService {}));
+    let config = strict();
+    let genesis = Block::default();
+    let predecessor = BlockHeader::borrowed(&genesis).unwrap();
+
+    let honest = block(1, config.initial_difficulty);
+    let header = BlockHeader::borrowed(&honest).unwrap();
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_ok());
+
+    let dishonest = block(1, config.initial_difficulty - 5);
+    let header = BlockHeader::borrowed(&dishonest).unwrap();
+    assert_eq!(
+      validate_expected_difficulty(&header, &predecessor, &mut service, &config)
+        .unwrap_err()
+        .consensus(),
+      Some(&ConsensusError::UnexpectedDifficulty {
+        claimed: config.initial_difficulty - 5,
+        expected: config.initial_difficulty
+      })
+    );
+  }
+
+  #[test]
+  fn difficulty_is_carried_over_between_adjustments() {
+    let mut service = PowService::new(Box::new(MockService {}));
+    let config = strict();
+    let pred_block = block(5, 9);
+    let predecessor = BlockHeader::borrowed(&pred_block).unwrap();
+
+    let honest = block(6, 9);
+    let header = BlockHeader::borrowed(&honest).unwrap();
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_ok());
+
+    let dishonest = block(6, 0);
+    let header = BlockHeader::borrowed(&dishonest).unwrap();
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_err());
+  }
+
+  #[test]
+  fn the_claimed_difficulty_is_only_checked_from_the_activation_height() {
+    let mut service = PowService::new(Box::new(MockService {}));
+    let pred_block = block(5, 9);
+    let predecessor = BlockHeader::borrowed(&pred_block).unwrap();
+    let dishonest = block(6, 0);
+    let header = BlockHeader::borrowed(&dishonest).unwrap();
+
+    let mut config = PowConfig::new();
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_ok());
+
+    config.strict_difficulty = Schedule::new(false).activate(7, true);
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_ok());
+
+    config.strict_difficulty = Schedule::new(false).activate(6, true);
+    assert!(validate_expected_difficulty(&header, &predecessor, &mut service, &config).is_err());
+  }
+
+  #[test]
+  fn target_mode_converts_the_initial_difficulty() {
+    let mut service = PowService::new(Box::new(MockService {}));
+    let mut config = PowConfig::new();
+    config.difficulty_mode =
+      Schedule::new(DifficultyMode::LeadingZeros).activate(3, DifficultyMode::Target);
+    let genesis = Block::default();
+    let genesis = BlockHeader::borrowed(&genesis).unwrap();
+
+    let expected = DifficultyMode::Target.encode(&difficulty_to_work(config.initial_difficulty));
+    assert_eq!(
+      get_difficulty(&genesis, 1000.0, &mut service, &config).unwrap(),
+      config.initial_difficulty
+    );
+
+    // block 2 stores the requirement of block 3, the first one in target mode
+    let pred_block = block(1, config.initial_difficulty);
+    let predecessor = BlockHeader::borrowed(&pred_block).unwrap();
+    assert_eq!(
+      get_difficulty(&predecessor, 1000.0, &mut service, &config).unwrap(),
+      expected
+    );
+    assert_eq!(
+      required_work(&predecessor, &config),
+      difficulty_to_work(config.initial_difficulty)
+    );
+  }
+
+  #[test]
+  fn ancestors_that_cant_be_read_are_an_error_not_the_initial_difficulty() {
+    let store = BlockStore::new();
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    let config = PowConfig::new();
+    // an adjustment block, whose successor's difficulty depends on the blocks before it
+    let pred_block = Block {
+      block_id: vec![10],
+      previous_id: vec![9],
+      ..block(10, 9)
+    };
+    let predecessor = BlockHeader::borrowed(&pred_block).unwrap();
+
+    let error = get_difficulty(&predecessor, 1000.0, &mut service, &config).unwrap_err();
+    assert!(
+      matches!(error, PowError::Ancestor(AncestorError::UnknownBlock(ref id, _)) if id == &[9]),
+      "{}",
+      error
+    );
+  }
+
+  #[test]
+  fn network_hash_rate_divides_the_proved_work_by_the_time_taken() {
+    let store = BlockStore::new();
+    let mut previous_id: BlockId = store.genesis().block_id;
+    for block_num in 1..=4u8 {
+      store.insert(Block {
+        block_id: vec![block_num],
+        previous_id: previous_id.clone(),
+        block_num: block_num as u64,
+        payload: BlockConsensus::serialize(10, 1000.0 + 60.0 * block_num as f64, 0),
+        ..Block::default()
+      });
+      previous_id = vec![block_num];
+    }
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    let config = PowConfig::new();
+    let head = BlockHeader::owned(service.get_block(&[4]).unwrap()).unwrap();
+
+    // three intervals of 60 seconds, each proving 2^10 hashes
+    let rate: f64 = 3.0 * 1024.0 / 180.0;
+    assert_eq!(
+      network_hash_rate(&head, &mut service, &config, 3),
+      Some(rate)
+    );
+    // the walk stops at the genesis block
+    assert_eq!(
+      network_hash_rate(&head, &mut service, &config, 10),
+      Some(rate)
+    );
+    assert_eq!(
+      network_hash_rate(&head, &mut service, &config, 1),
+      Some(1024.0 / 60.0)
+    );
+
+    let first = BlockHeader::owned(service.get_block(&[1]).unwrap()).unwrap();
+    assert_eq!(network_hash_rate(&first, &mut service, &config, 3), None);
+  }
+}
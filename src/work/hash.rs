@@ -0,0 +1,67 @@
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::primitives::{CCDifficulty, CCNonce, H256};
+
+pub type Hasher = Sha256;
+
+pub fn get_hasher() -> Hasher {
+  Sha256::new()
+}
+
+pub fn mkhash(hasher: &mut Hasher, block_id: &[u8], peer_id: &[u8], nonce: CCNonce) -> H256 {
+  let mut output: H256 = H256::new();
+
+  mkhash_into(hasher, &mut output, block_id, peer_id, nonce);
+
+  output
+}
+
+pub fn mkhash_into(
+  hasher: &mut Hasher,
+  output: &mut H256,
+  block_id: &[u8],
+  peer_id: &[u8],
+  nonce: CCNonce,
+) {
+  hasher.update(block_id);
+  hasher.update(peer_id);
+  hasher.update(nonce.to_string().as_bytes());
+  output.copy_from_slice(&hasher.finalize_reset());
+}
+
+pub fn is_valid_proof_of_work(hash: &H256, difficulty: CCDifficulty) -> (bool, CCDifficulty) {
+  let digest = digest_score(hash);
+  (digest >= difficulty, digest)
+}
+
+pub fn digest_score(digest: &H256) -> u32 {
+  let mut score: u32 = 0;
+
+  for byte in digest.iter().copied() {
+    if byte > 0 {
+      if byte >= 128 {
+        continue;
+      } else if byte >= 64 {
+        score += 1;
+      } else if byte >= 32 {
+        score += 2;
+      } else if byte >= 16 {
+        score += 3;
+      } else if byte >= 8 {
+        score += 4;
+      } else if byte >= 4 {
+        score += 5;
+      } else if byte >= 2 {
+        score += 6;
+      } else {
+        score += 7;
+      }
+      break;
+    } else {
+      score += 8;
+    }
+  }
+
+  score
+}
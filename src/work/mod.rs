@@ -0,0 +1,15 @@
+mod algorithm;
+mod difficulty;
+mod hash;
+mod retarget;
+mod target;
+mod timestamp;
+mod validation;
+
+pub use self::algorithm::*;
+pub use self::difficulty::*;
+pub use self::hash::*;
+pub use self::retarget::*;
+pub use self::target::*;
+pub use self::timestamp::*;
+pub use self::validation::*;
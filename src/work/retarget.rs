@@ -0,0 +1,579 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::block::BlockHeader;
+use crate::node::PowConfig;
+use crate::primitives::{CCDifficulty, CCTimestamp, H256};
+use crate::work::required_work;
+
+pub const MAX_DIFFICULTY: CCDifficulty = 255;
+
+/// Computes the work required by the successor of a new block, which `DifficultyMode::encode` turns
+/// into the difficulty stored in the new block.
+///
+/// `ancestors` starts at the parent of the new block and walks back through PoW blocks only,
+/// it holds at most `window` headers and is never empty. `timestamp` is the new block's timestamp.
+///
+/// Every validator must reach the same result, so work is a 256-bit integer and timestamps are
+/// truncated to whole seconds: no floating point beyond comparisons and subtractions.
+pub trait DifficultyAlgorithm {
+  /// How many ancestors, parent included, `next_work` needs
+  fn window(&self, parent: &BlockHeader, config: &PowConfig) -> u64;
+
+  fn next_work(
+    &self,
+    ancestors: &[BlockHeader],
+    timestamp: CCTimestamp,
+    config: &PowConfig,
+  ) -> H256;
+}
+
+/// The original ±1 bit step, i.e. twice or half the work, on tuning and adjustment blocks only.
+pub struct Legacy;
+
+impl Legacy {
+  fn is_tuning_block(parent: &BlockHeader, config: &PowConfig) -> bool {
+    parent
+      .block_num
+      .is_multiple_of(config.difficulty_tuning_block_count)
+  }
+
+  fn is_adjustment_block(parent: &BlockHeader, config: &PowConfig) -> bool {
+    parent
+      .block_num
+      .is_multiple_of(config.difficulty_adjustment_block_count)
+  }
+
+  /// Time taken since the `total_count`th ancestor, and the time it should have taken
+  fn elapsed_time(
+    ancestors: &[BlockHeader],
+    timestamp: CCTimestamp,
+    total_count: u64,
+    expected_interval: u64,
+  ) -> (f64, f64) {
+    // the parent always counts, the first ancestor before it too if there is one
+    let count: usize = ancestors.len().min(total_count.max(2) as usize);
+    let previous_time: f64 = ancestors[count - 1].consensus.timestamp;
+
+    let time_taken: f64 = timestamp - previous_time;
+    let time_expected: f64 = (count as u64 * expected_interval) as f64;
+
+    (time_taken, time_expected)
+  }
+}
+
+impl DifficultyAlgorithm for Legacy {
+  fn window(&self, parent: &BlockHeader, config: &PowConfig) -> u64 {
+    if Self::is_tuning_block(parent, config) {
+      config.difficulty_tuning_block_count.max(2)
+    } else if Self::is_adjustment_block(parent, config) {
+      config.difficulty_adjustment_block_count.max(2)
+    } else {
+      1
+    }
+  }
+
+  fn next_work(
+    &self,
+    ancestors: &[BlockHeader],
+    timestamp: CCTimestamp,
+    config: &PowConfig,
+  ) -> H256 {
+    let parent: &BlockHeader = &ancestors[0];
+    let work: H256 = required_work(parent, config);
+    let max_work: H256 = difficulty_to_work(MAX_DIFFICULTY);
+    let min_work: H256 = H256::from_u64(1);
+
+    if Self::is_tuning_block(parent, config) {
+      let (time_taken, time_expected) = Self::elapsed_time(
+        ancestors,
+        timestamp,
+        config.difficulty_tuning_block_count,
+        config.seconds_between_blocks,
+      );
+
+      if time_taken < time_expected && work < max_work {
+        work.saturating_shl(1)
+      } else if time_taken > time_expected && work > min_work {
+        work >> 1
+      } else {
+        work
+      }
+    } else if Self::is_adjustment_block(parent, config) {
+      let (time_taken, time_expected) = Self::elapsed_time(
+        ancestors,
+        timestamp,
+        config.difficulty_adjustment_block_count,
+        config.seconds_between_blocks,
+      );
+
+      if time_taken < time_expected / 2.0 && work < max_work {
+        work.saturating_shl(1)
+      } else if time_taken > time_expected * 2.0 && work > min_work {
+        work >> 1
+      } else {
+        work
+      }
+    } else {
+      work
+    }
+  }
+}
+
+/// Linearly weighted moving average of the work and solve times over `difficulty_window` blocks,
+/// recent blocks weigh the most. Retargets on every block.
+pub struct Lwma;
+
+impl DifficultyAlgorithm for Lwma {
+  fn window(&self, _parent: &BlockHeader, config: &PowConfig) -> u64 {
+    // N solve times need N + 1 timestamps, the new block brings one
+    config.difficulty_window.max(1)
+  }
+
+  fn next_work(
+    &self,
+    ancestors: &[BlockHeader],
+    timestamp: CCTimestamp,
+    config: &PowConfig,
+  ) -> H256 {
+    let target: i64 = config.seconds_between_blocks.min(i32::MAX as u64) as i64;
+    let samples: Vec<(i64, H256)> = solve_times(ancestors, timestamp, config);
+    let n: i64 = samples.len() as i64;
+
+    // oldest sample first so that its weight is 1 and the newest's is N
+    let (weighted_time, total_work) = samples.iter().rev().enumerate().fold(
+      (0i64, H256::MIN),
+      |(weighted_time, total_work), (index, (solve_time, work))| {
+        let solve_time: i64 = (*solve_time).max(-6 * target).min(6 * target);
+        (
+          weighted_time + (index as i64 + 1) * solve_time,
+          total_work.saturating_add(work),
+        )
+      },
+    );
+
+    let k: i64 = n * (n + 1) / 2 * target;
+    let weighted_time: i64 = weighted_time.max(k / 10);
+
+    // the average work times k / weighted_time, divided once to keep the remainder
+    total_work.mul_div(k as u64, (n * weighted_time) as u64)
+  }
+}
+
+/// Exponential retarget in the style of ASERT, anchored at the oldest block of the window:
+/// every `asert_half_life` seconds behind (ahead of) schedule halves (doubles) the work.
+///
+/// The power of two is the cubic approximation of aserti3-2d, on a 16.16 fixed-point exponent.
+pub struct Asert;
+
+impl DifficultyAlgorithm for Asert {
+  fn window(&self, _parent: &BlockHeader, config: &PowConfig) -> u64 {
+    config.difficulty_window.max(1)
+  }
+
+  fn next_work(
+    &self,
+    ancestors: &[BlockHeader],
+    timestamp: CCTimestamp,
+    config: &PowConfig,
+  ) -> H256 {
+    let anchor: &BlockHeader = &ancestors[ancestors.len() - 1];
+    // blocks mined since the anchor, the new one included
+    let blocks: i64 = ancestors.len() as i64;
+
+    let time_expected: i64 = blocks.saturating_mul(config.seconds_between_blocks as i64);
+    let time_taken: i64 = seconds(timestamp).saturating_sub(seconds(anchor.consensus.timestamp));
+    let half_life: i64 = config.asert_half_life.clamp(1, i64::MAX as u64) as i64;
+
+    let exponent: i64 = time_expected
+      .saturating_sub(time_taken)
+      .saturating_mul(ASERT_ONE as i64)
+      / half_life;
+
+    let work: H256 = asert_scale(&required_work(anchor, config), exponent);
+    if work.is_zero() {
+      H256::from_u64(1)
+    } else {
+      work
+    }
+  }
+}
+
+/// 1.0 in the 16.16 fixed point of ASERT exponents
+const ASERT_ONE: u64 = 1 << 16;
+
+/// `work * 2^(exponent / 2^16)`, rounded down
+fn asert_scale(work: &H256, exponent: i64) -> H256 {
+  // the whole part rounds towards negative infinity, which leaves a fraction in [0, 1)
+  let shifts: i64 = exponent >> 16;
+  let fraction: u128 = (exponent & 0xffff) as u128;
+
+  // 2^fraction - 1 to within 0.013%, scaled by 2^48, then rounded to 2^16
+  let polynomial: u128 = 195_766_423_245_049 * fraction
+    + 971_821_376 * fraction * fraction
+    + 5_127 * fraction * fraction * fraction;
+  let factor: u64 = ASERT_ONE + ((polynomial + (1 << 47)) >> 48) as u64;
+
+  let scaled: H256 = work.mul_div(factor, ASERT_ONE);
+  if shifts >= 0 {
+    scaled.saturating_shl(shifts.min(256) as u32)
+  } else {
+    scaled >> shifts.unsigned_abs().min(256) as u32
+  }
+}
+
+/// (solve time, work it was mined at) for the new block and each ancestor that has a predecessor, newest first
+fn solve_times(
+  ancestors: &[BlockHeader],
+  timestamp: CCTimestamp,
+  config: &PowConfig,
+) -> Vec<(i64, H256)> {
+  let mut samples: Vec<(i64, H256)> = Vec::with_capacity(ancestors.len());
+  let mut next_time: i64 = seconds(timestamp);
+
+  for block in ancestors {
+    let time: i64 = seconds(block.consensus.timestamp);
+    samples.push((next_time.saturating_sub(time), required_work(block, config)));
+    next_time = time;
+  }
+
+  samples
+}
+
+/// Whole seconds of a timestamp, truncated and saturating as `as` does
+fn seconds(timestamp: CCTimestamp) -> i64 {
+  timestamp as i64
+}
+
+pub fn difficulty_to_work(difficulty: CCDifficulty) -> H256 {
+  H256::from_u64(1).saturating_shl(difficulty)
+}
+
+/// The nearest number of leading zero bits: `log2(work)`, rounded up from `2^(n + 1/2)` as
+/// measured on the 16 most significant bits
+pub fn work_to_difficulty(work: &H256) -> CCDifficulty {
+  if work.is_zero() {
+    return 0;
+  }
+
+  let bits: u32 = 256 - work.leading_zeros();
+  let mantissa: u64 = if bits > 16 {
+    (*work >> (bits - 16)).saturating_u64()
+  } else {
+    work.saturating_u64() << (16 - bits)
+  };
+  let round_up: bool = mantissa >= SQRT_2_MANTISSA;
+
+  (bits - 1 + round_up as u32).min(MAX_DIFFICULTY)
+}
+
+/// 2^15.5, rounded up
+const SQRT_2_MANTISSA: u64 = 46_341;
+
+/// The on-chain name of a `DifficultyAlgorithm`, as set in `sawtooth.consensus.pow.difficulty_algorithm`
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum DifficultyAlgorithmKind {
+  #[default]
+  Legacy,
+  Lwma,
+  Asert,
+}
+
+impl DifficultyAlgorithmKind {
+  pub fn build(self) -> Box<dyn DifficultyAlgorithm> {
+    match self {
+      Self::Legacy => Box::new(Legacy),
+      Self::Lwma => Box::new(Lwma),
+      Self::Asert => Box::new(Asert),
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Legacy => "legacy",
+      Self::Lwma => "lwma",
+      Self::Asert => "asert",
+    }
+  }
+}
+
+impl FromStr for DifficultyAlgorithmKind {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "legacy" => Ok(Self::Legacy),
+      "lwma" => Ok(Self::Lwma),
+      "asert" => Ok(Self::Asert),
+      _ => Err(format!("Unknown difficulty algorithm: {}", string)),
+    }
+  }
+}
+
+impl Display for DifficultyAlgorithmKind {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.write_str(self.name())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::{Block, BlockConsensus};
+  use crate::node::Schedule;
+  use crate::work::DifficultyMode;
+
+  /// Ancestors of a new block, newest first, one every `interval` seconds ending at `head_time`
+  fn chain(
+    head_num: u64,
+    count: u64,
+    head_time: f64,
+    interval: f64,
+    difficulty: CCDifficulty,
+  ) -> Vec<Block> {
+    (0..count)
+      .map(|index| Block {
+        block_num: head_num - index,
+        payload: BlockConsensus::serialize(difficulty, head_time - index as f64 * interval, 0),
+        ..Block::default()
+      })
+      .collect()
+  }
+
+  fn next(
+    kind: DifficultyAlgorithmKind,
+    blocks: &[Block],
+    timestamp: f64,
+    config: &PowConfig,
+  ) -> CCDifficulty {
+    let headers: Vec<BlockHeader> = blocks
+      .iter()
+      .map(|block| BlockHeader::borrowed(block).unwrap())
+      .collect();
+    let algorithm = kind.build();
+    let window = algorithm.window(&headers[0], config) as usize;
+
+    // the new block stores the requirement of its successor
+    config
+      .difficulty_mode
+      .at(blocks[0].block_num + 2)
+      .encode(&algorithm.next_work(&headers[..window.min(headers.len())], timestamp, config))
+  }
+
+  fn target_mode() -> PowConfig {
+    let mut config = PowConfig::new();
+    config.difficulty_mode = Schedule::new(DifficultyMode::Target);
+    config
+  }
+
+  #[test]
+  fn legacy_only_moves_on_tuning_and_adjustment_blocks() {
+    let config = PowConfig::new();
+    let fast = chain(105, 100, 6000.0, 1.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Legacy, &fast, 6001.0, &config),
+      20
+    );
+
+    let fast = chain(110, 100, 6000.0, 1.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Legacy, &fast, 6001.0, &config),
+      21
+    );
+
+    let slow = chain(200, 100, 60000.0, 600.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Legacy, &slow, 60600.0, &config),
+      19
+    );
+
+    let on_time = chain(200, 100, 6000.0, 60.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Legacy, &on_time, 6030.0, &config),
+      21
+    );
+  }
+
+  #[test]
+  fn lwma_holds_steady_on_schedule() {
+    let config = PowConfig::new();
+    let on_time = chain(1000, 60, 60000.0, 60.0, 20);
+
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Lwma, &on_time, 60060.0, &config),
+      20
+    );
+  }
+
+  #[test]
+  fn lwma_follows_the_solve_times() {
+    let config = PowConfig::new();
+    let fast = chain(1000, 60, 60000.0, 15.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Lwma, &fast, 60015.0, &config),
+      22
+    );
+
+    let slow = chain(1000, 60, 60000.0, 240.0, 20);
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Lwma, &slow, 60240.0, &config),
+      18
+    );
+  }
+
+  #[test]
+  fn lwma_uses_what_is_available() {
+    let config = PowConfig::new();
+    let short = chain(1, 1, 1000.0, 60.0, 20);
+
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Lwma, &short, 1060.0, &config),
+      20
+    );
+  }
+
+  #[test]
+  fn asert_moves_by_schedule_offset_over_half_life() {
+    let mut config = PowConfig::new();
+    config.asert_half_life = 600;
+    let on_time = chain(1000, 60, 60000.0, 60.0, 20);
+
+    // the anchor is 59 intervals back, the new block makes 60 on schedule
+    let anchor_time = 60000.0 - 59.0 * 60.0;
+    let scheduled = anchor_time + 60.0 * 60.0;
+
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Asert, &on_time, scheduled, &config),
+      20
+    );
+    assert_eq!(
+      next(
+        DifficultyAlgorithmKind::Asert,
+        &on_time,
+        scheduled - 1200.0,
+        &config
+      ),
+      22
+    );
+    assert_eq!(
+      next(
+        DifficultyAlgorithmKind::Asert,
+        &on_time,
+        scheduled + 600.0,
+        &config
+      ),
+      19
+    );
+  }
+
+  #[test]
+  fn lwma_vector_in_target_mode() {
+    let config = target_mode();
+    // fractions of a second are dropped from every timestamp
+    let fast = chain(1000, 60, 60000.0, 45.25, 0x1d00_ffff);
+
+    assert_eq!(
+      next(DifficultyAlgorithmKind::Lwma, &fast, 60040.75, &config),
+      0x1d00_c061
+    );
+  }
+
+  #[test]
+  fn asert_vectors_in_target_mode() {
+    let mut config = target_mode();
+    config.asert_half_life = 600;
+    let on_time = chain(1000, 60, 60000.0, 60.0, 0x1d00_ffff);
+    let scheduled = 60000.0 - 59.0 * 60.0 + 60.0 * 60.0;
+
+    assert_eq!(
+      next(
+        DifficultyAlgorithmKind::Asert,
+        &on_time,
+        scheduled - 250.5,
+        &config
+      ),
+      0x1d00_bf93
+    );
+    assert_eq!(
+      next(
+        DifficultyAlgorithmKind::Asert,
+        &on_time,
+        scheduled + 1000.25,
+        &config
+      ),
+      0x1d03_2cc2
+    );
+  }
+
+  #[test]
+  fn asert_approximates_powers_of_two_exactly_the_same_everywhere() {
+    let work = H256::from_u64(1 << 20);
+
+    assert_eq!(asert_scale(&work, 0), work);
+    assert_eq!(asert_scale(&work, 1 << 16), H256::from_u64(1 << 21));
+    assert_eq!(asert_scale(&work, -(1 << 16)), H256::from_u64(1 << 19));
+    assert_eq!(asert_scale(&work, 1 << 15), H256::from_u64(1_482_784));
+    assert_eq!(asert_scale(&work, -(1 << 15)), H256::from_u64(741_392));
+    assert_eq!(asert_scale(&work, 98_427), H256::from_u64(2_969_440));
+    assert_eq!(asert_scale(&work, -200_000), H256::from_u64(126_462));
+    assert_eq!(asert_scale(&work, 300 << 16), H256::MAX);
+    assert_eq!(asert_scale(&work, -300 << 16), H256::MIN);
+  }
+
+  #[test]
+  fn half_a_step_rounds_on_the_approximation() {
+    let mut config = PowConfig::new();
+    config.asert_half_life = 600;
+    let on_time = chain(1000, 60, 60000.0, 60.0, 20);
+    let scheduled = 60000.0 - 59.0 * 60.0 + 60.0 * 60.0;
+
+    // 2^20.5 comes out as 1482784, just under the rounding point of 2^20 * 46341 / 32768
+    assert_eq!(
+      next(
+        DifficultyAlgorithmKind::Asert,
+        &on_time,
+        scheduled - 300.0,
+        &config
+      ),
+      20
+    );
+  }
+
+  #[test]
+  fn difficulty_stays_in_bounds() {
+    assert_eq!(work_to_difficulty(&H256::MIN), 0);
+    assert_eq!(work_to_difficulty(&H256::from_u64(1)), 0);
+    assert_eq!(work_to_difficulty(&H256::from_u64(3)), 2);
+    assert_eq!(work_to_difficulty(&H256::MAX), MAX_DIFFICULTY);
+    assert_eq!(work_to_difficulty(&difficulty_to_work(42)), 42);
+    assert_eq!(difficulty_to_work(256), H256::MAX);
+  }
+
+  #[test]
+  fn difficulty_rounds_at_the_square_root_of_two() {
+    assert_eq!(work_to_difficulty(&H256::from_u64(46_340)), 15);
+    assert_eq!(work_to_difficulty(&H256::from_u64(46_341)), 16);
+    assert_eq!(
+      work_to_difficulty(&H256::from_u64(46_340).saturating_shl(100)),
+      115
+    );
+    assert_eq!(
+      work_to_difficulty(&H256::from_u64(46_341).saturating_shl(100)),
+      116
+    );
+  }
+
+  #[test]
+  fn algorithm_names_round_trip() {
+    for kind in [
+      DifficultyAlgorithmKind::Legacy,
+      DifficultyAlgorithmKind::Lwma,
+      DifficultyAlgorithmKind::Asert,
+    ] {
+      assert_eq!(
+        kind.to_string().parse::<DifficultyAlgorithmKind>(),
+        Ok(kind)
+      );
+    }
+  }
+}
@@ -0,0 +1,183 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use crate::primitives::{CCDifficulty, H256};
+use crate::work::{difficulty_to_work, work_to_difficulty, PowAlgorithm};
+
+/// How the `expected_difficulty` of a block is read, as set in `sawtooth.consensus.pow.difficulty_mode`.
+///
+/// The value stored in block `n` is the requirement of block `n + 1`, so it is read with the mode active at `n + 1`.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum DifficultyMode {
+  /// The number of leading zero bits the hash must have
+  #[default]
+  LeadingZeros,
+  /// A compact 256-bit target the hash must not exceed
+  Target,
+}
+
+impl DifficultyMode {
+  /// Whether `hash` satisfies `requirement`
+  pub fn meets(self, algorithm: &dyn PowAlgorithm, hash: &H256, requirement: CCDifficulty) -> bool {
+    match self {
+      Self::LeadingZeros => algorithm.score(hash) >= requirement,
+      Self::Target => *hash <= H256::from_compact(requirement),
+    }
+  }
+
+  /// The expected number of hashes needed to meet `requirement`
+  pub fn work(self, requirement: CCDifficulty) -> H256 {
+    match self {
+      Self::LeadingZeros => difficulty_to_work(requirement),
+      Self::Target => target_to_work(&H256::from_compact(requirement)),
+    }
+  }
+
+  /// The work a hash happened to prove, regardless of what was required
+  pub fn achieved_work(self, algorithm: &dyn PowAlgorithm, hash: &H256) -> H256 {
+    match self {
+      Self::LeadingZeros => difficulty_to_work(algorithm.score(hash)),
+      Self::Target => target_to_work(hash),
+    }
+  }
+
+  /// The requirement closest to `work` that this mode can express
+  pub fn encode(self, work: &H256) -> CCDifficulty {
+    match self {
+      Self::LeadingZeros => work_to_difficulty(work),
+      Self::Target => work_to_target(work).to_compact(),
+    }
+  }
+
+  /// The requirement every hash meets
+  pub fn minimum(self) -> CCDifficulty {
+    match self {
+      Self::LeadingZeros => 0,
+      // 2^256, which saturates to `H256::MAX`
+      Self::Target => 0x2101_0000,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::LeadingZeros => "zeros",
+      Self::Target => "target",
+    }
+  }
+}
+
+impl FromStr for DifficultyMode {
+  type Err = String;
+
+  fn from_str(string: &str) -> Result<Self, Self::Err> {
+    match string {
+      "zeros" => Ok(Self::LeadingZeros),
+      "target" => Ok(Self::Target),
+      _ => Err(format!("Unknown difficulty mode: {}", string)),
+    }
+  }
+}
+
+impl Display for DifficultyMode {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.write_str(self.name())
+  }
+}
+
+/// `2^256 / (target + 1)`, rounded down: the expected number of hashes to get one at most `target`
+fn target_to_work(target: &H256) -> H256 {
+  if *target == H256::MAX {
+    return H256::from_u64(1);
+  }
+
+  // 2^256 doesn't fit, but 2^256 - (target + 1) is `!target`
+  let divisor: H256 = target.saturating_add(&H256::from_u64(1));
+  (!*target)
+    .saturating_div(&divisor)
+    .saturating_add(&H256::from_u64(1))
+}
+
+/// `2^256 / work - 1`, rounded down: the target expecting `work` hashes, the inverse of `target_to_work`
+fn work_to_target(work: &H256) -> H256 {
+  if *work <= H256::from_u64(1) {
+    return H256::MAX;
+  }
+
+  // 2^256 - work is `!work + 1`
+  (!*work)
+    .saturating_add(&H256::from_u64(1))
+    .saturating_div(work)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::work::PowAlgorithmKind;
+
+  fn hash_with_leading_byte(byte: u8) -> H256 {
+    let mut hash = H256::MAX;
+    hash[0] = 0;
+    hash[1] = byte;
+    hash
+  }
+
+  #[test]
+  fn target_mode_compares_the_whole_hash() {
+    let algorithm = PowAlgorithmKind::default().build();
+    let requirement: CCDifficulty = 0x2000_8000;
+    let target = H256::from_compact(requirement);
+    assert_eq!(&target[..3], &[0, 0x80, 0]);
+
+    assert!(DifficultyMode::Target.meets(&*algorithm, &hash_with_leading_byte(0x7f), requirement));
+    assert!(!DifficultyMode::Target.meets(&*algorithm, &hash_with_leading_byte(0x80), requirement));
+    assert!(DifficultyMode::LeadingZeros.meets(&*algorithm, &hash_with_leading_byte(0x7f), 9));
+    assert!(!DifficultyMode::LeadingZeros.meets(&*algorithm, &hash_with_leading_byte(0x7f), 10));
+  }
+
+  #[test]
+  fn work_and_requirements_agree() {
+    for mode in [DifficultyMode::LeadingZeros, DifficultyMode::Target] {
+      let requirement = mode.encode(&difficulty_to_work(20));
+      let work = mode.work(requirement);
+      // the compact form keeps 3 bytes of the target, which can cost one hash
+      assert!(work.saturating_u64().abs_diff(1 << 20) <= 1, "{}", mode);
+      assert_eq!(mode.encode(&work), requirement);
+      assert_eq!(mode.work(mode.minimum()), H256::from_u64(1));
+      assert!(mode.meets(
+        &*PowAlgorithmKind::default().build(),
+        &H256::MAX,
+        mode.minimum()
+      ));
+    }
+
+    // a target can sit between two powers of two
+    let between = DifficultyMode::Target.encode(&H256::from_u64(3 << 19));
+    assert!(DifficultyMode::Target.work(between) > difficulty_to_work(20));
+    assert!(DifficultyMode::Target.work(between) < difficulty_to_work(21));
+  }
+
+  #[test]
+  fn targets_and_work_convert_exactly() {
+    assert_eq!(target_to_work(&H256::MIN), H256::MAX);
+    assert_eq!(target_to_work(&H256::MAX), H256::from_u64(1));
+    assert_eq!(target_to_work(&(H256::MAX >> 1)), H256::from_u64(2));
+    assert_eq!(
+      target_to_work(&H256::from_compact(0x1d00_ffff)),
+      H256::from_u64(0x1_0001_0001)
+    );
+    assert_eq!(work_to_target(&H256::MIN), H256::MAX);
+    assert_eq!(work_to_target(&H256::from_u64(2)), H256::MAX >> 1);
+    assert_eq!(work_to_target(&H256::from_u64(3)).to_compact(), 0x2055_5555);
+    assert_eq!(
+      work_to_target(&H256::from_u64(0x1_0001_0001)).to_compact(),
+      0x1d00_ffff
+    );
+  }
+
+  #[test]
+  fn mode_names_round_trip() {
+    for mode in [DifficultyMode::LeadingZeros, DifficultyMode::Target] {
+      assert_eq!(mode.to_string().parse::<DifficultyMode>(), Ok(mode));
+    }
+  }
+}
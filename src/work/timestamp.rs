@@ -0,0 +1,224 @@
+use crate::block::{pow_chain, AncestorError, BlockHeader, ConsensusError};
+use crate::error::PowError;
+use crate::node::{PowConfig, PowService};
+use crate::primitives::CCTimestamp;
+
+/// The median timestamp of the `span` PoW blocks from `block_id` down, `None` if the PoW chain
+/// ends before the first one. Fails when one of them can't be read, rather than taking the median
+/// of a truncated window.
+pub fn median_time_past(
+  block_id: &[u8],
+  service: &mut PowService,
+  span: u64,
+) -> Result<Option<CCTimestamp>, AncestorError> {
+  let timestamps: Vec<CCTimestamp> = pow_chain(block_id, span, service)?
+    .iter()
+    .map(|block| block.consensus.timestamp)
+    .collect();
+
+  Ok(median(timestamps))
+}
+
+pub(crate) fn median(mut timestamps: Vec<CCTimestamp>) -> Option<CCTimestamp> {
+  if timestamps.is_empty() {
+    return None;
+  }
+
+  timestamps.sort_by(|a, b| a.total_cmp(b));
+  Some(timestamps[timestamps.len() / 2])
+}
+
+/// Check the block timestamp against the median-time-past and future drift rules.
+///
+/// Either rule is disabled when its setting at the block's height is zero. A broken rule is a
+/// `PowError::Consensus`, other errors mean the ancestors couldn't be read.
+pub fn validate_timestamp(
+  header: &BlockHeader,
+  service: &mut PowService,
+  config: &PowConfig,
+  now: CCTimestamp,
+) -> Result<(), PowError> {
+  let timestamp: CCTimestamp = header.consensus.timestamp;
+  let max_future_drift: u64 = *config.max_future_drift.at(header.block_num);
+  let median_time_span: u64 = *config.median_time_span.at(header.block_num);
+
+  if max_future_drift > 0 {
+    let limit: CCTimestamp = now + max_future_drift as CCTimestamp;
+    if timestamp.is_nan() || timestamp > limit {
+      return Err(
+        ConsensusError::TimestampInFuture {
+          value: timestamp,
+          limit,
+        }
+        .into(),
+      );
+    }
+  }
+
+  if median_time_span > 0 {
+    if let Some(median) = median_time_past(&header.previous_id, service, median_time_span)? {
+      if timestamp.is_nan() || timestamp <= median {
+        return Err(
+          ConsensusError::TimestampBeforeMedian {
+            value: timestamp,
+            median,
+          }
+          .into(),
+        );
+      }
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::block::{Block, BlockConsensus};
+  use crate::node::tests::MockService;
+  use crate::node::Schedule;
+  use crate::simulator::{BlockStore, SimulatedService};
+
+  fn header_at(timestamp: CCTimestamp) -> Block {
+    Block {
+      block_num: 1,
+      payload: BlockConsensus::serialize(1, timestamp, 0),
+      ..Block::default()
+    }
+  }
+
+  #[test]
+  fn median_picks_the_middle_timestamp() {
+    assert_eq!(median(vec![]), None);
+    assert_eq!(median(vec![3.0, 1.0, 2.0]), Some(2.0));
+    assert_eq!(median(vec![4.0, 1.0, 3.0, 2.0]), Some(3.0));
+  }
+
+  #[test]
+  fn future_drift_is_bounded() {
+    let mut service = PowService::new(Box::new(MockService {}));
+    let mut config = PowConfig::new();
+    config.max_future_drift = Schedule::new(60);
+
+    let block = header_at(1060.0);
+    let header = BlockHeader::borrowed(&block).unwrap();
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_ok());
+
+    let block = header_at(1060.5);
+    let header = BlockHeader::borrowed(&block).unwrap();
+    assert_eq!(
+      validate_timestamp(&header, &mut service, &config, 1000.0)
+        .unwrap_err()
+        .consensus(),
+      Some(&ConsensusError::TimestampInFuture {
+        value: 1060.5,
+        limit: 1060.0
+      })
+    );
+
+    config.max_future_drift = Schedule::new(0);
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_ok());
+
+    // blocks below the activation height keep the rules they were accepted under
+    config.max_future_drift = Schedule::new(0).activate(2, 60);
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_ok());
+    config.max_future_drift = Schedule::new(0).activate(1, 60);
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_err());
+  }
+
+  #[test]
+  fn the_median_time_past_is_taken_over_the_pow_ancestors() {
+    let store = BlockStore::new();
+    // a block from before the switch to PoW ends the walk
+    let mut parent = Block {
+      block_id: vec![1],
+      previous_id: store.genesis().block_id,
+      block_num: 1,
+      payload: b"Devmode".to_vec(),
+      ..Block::default()
+    };
+    store.insert(parent.clone());
+    for (n, timestamp) in [1000.0, 1010.0, 1020.0, 1040.0, 1030.0].iter().enumerate() {
+      let block = Block {
+        block_id: vec![n as u8 + 2],
+        previous_id: parent.block_id.clone(),
+        block_num: n as u64 + 2,
+        ..header_at(*timestamp)
+      };
+      store.insert(block.clone());
+      parent = block;
+    }
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    let child = Block {
+      previous_id: parent.block_id.clone(),
+      block_num: 7,
+      ..header_at(1020.0)
+    };
+    let header = BlockHeader::borrowed(&child).unwrap();
+
+    assert_eq!(
+      median_time_past(&parent.block_id, &mut service, 2).unwrap(),
+      Some(1040.0)
+    );
+    assert_eq!(
+      median_time_past(&parent.block_id, &mut service, 3).unwrap(),
+      Some(1030.0)
+    );
+    assert_eq!(
+      median_time_past(&parent.block_id, &mut service, 5).unwrap(),
+      Some(1020.0)
+    );
+    // only 5 PoW blocks to take it over
+    assert_eq!(
+      median_time_past(&parent.block_id, &mut service, 11).unwrap(),
+      Some(1020.0)
+    );
+
+    let mut config = PowConfig::new();
+    config.median_time_span = Schedule::new(0).activate(8, 5);
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_ok());
+
+    config.median_time_span = Schedule::new(5);
+    assert_eq!(
+      validate_timestamp(&header, &mut service, &config, 1000.0)
+        .unwrap_err()
+        .consensus(),
+      Some(&ConsensusError::TimestampBeforeMedian {
+        value: 1020.0,
+        median: 1020.0
+      })
+    );
+    let child = Block {
+      payload: BlockConsensus::serialize(1, 1020.5, 0),
+      ..child
+    };
+    let header = BlockHeader::borrowed(&child).unwrap();
+    assert!(validate_timestamp(&header, &mut service, &config, 1000.0).is_ok());
+  }
+
+  #[test]
+  fn ancestors_that_cant_be_read_are_an_error_not_a_skipped_rule() {
+    let store = BlockStore::new();
+    let genesis: Vec<u8> = store.genesis().block_id;
+    let mut service = PowService::new(Box::new(SimulatedService::new(store, vec![])));
+    let mut config = PowConfig::new();
+    config.median_time_span = Schedule::new(5);
+
+    // the first block after genesis has no median time past to be behind
+    assert_eq!(median_time_past(&genesis, &mut service, 5).unwrap(), None);
+
+    let child = Block {
+      previous_id: vec![9],
+      block_num: 10,
+      ..header_at(1000.0)
+    };
+    let header = BlockHeader::borrowed(&child).unwrap();
+    let error = validate_timestamp(&header, &mut service, &config, 1000.0).unwrap_err();
+    assert!(
+      matches!(error, PowError::Ancestor(AncestorError::UnknownBlock(ref id, _)) if id == &[9]),
+      "{}",
+      error
+    );
+  }
+}
@@ -0,0 +1,27 @@
+use crate::block::BlockHeader;
+use crate::error::PowError;
+use crate::node::{PowConfig, PowService};
+use crate::primitives::CCTimestamp;
+use crate::work::{required_difficulty, validate_expected_difficulty, validate_timestamp};
+
+/// The consensus rules a block must follow on top of `predecessor`, as checked at time `now`.
+///
+/// The proof of work meets the difficulty stored in the predecessor, the difficulty claimed for the
+/// next block is the one the chain calls for once `strict_difficulty` is active, and the timestamp
+/// passes the median time past and future drift rules. `service` only needs to serve ancestors, a
+/// live validator or an export.
+///
+/// A broken rule is a `PowError::Consensus`, other errors mean the ancestors couldn't be read.
+pub fn validate_consensus(
+  header: &BlockHeader,
+  predecessor: &BlockHeader,
+  service: &mut PowService,
+  config: &PowConfig,
+  now: CCTimestamp,
+) -> Result<(), PowError> {
+  let header = header
+    .clone()
+    .validate(config, required_difficulty(predecessor, config))?;
+  validate_expected_difficulty(&header, predecessor, service, config)?;
+  validate_timestamp(&header, service, config, now)
+}